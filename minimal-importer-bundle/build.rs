@@ -0,0 +1,35 @@
+//! Records what the build-info interface reports about this build.
+
+use std::env;
+use std::process::Command;
+
+fn main() {
+    let hash = Command::new("git")
+        .args(["describe", "--always", "--dirty"])
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|s| s.trim().to_owned())
+        .unwrap_or_else(|| "unknown".to_owned());
+    println!("cargo:rustc-env=MINIMAL_IMPORTER_GIT_HASH={hash}");
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs/heads");
+    println!("cargo:rerun-if-changed=../.git/packed-refs");
+    println!("cargo:rerun-if-changed=../.git/index");
+
+    let profile = env::var("PROFILE").unwrap_or_default();
+    println!("cargo:rustc-env=MINIMAL_IMPORTER_PROFILE={profile}");
+
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| {
+            let feature = key.strip_prefix("CARGO_FEATURE_")?;
+            Some(feature.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+    println!(
+        "cargo:rustc-env=MINIMAL_IMPORTER_FEATURES={}",
+        features.join(",")
+    );
+}
@@ -0,0 +1,96 @@
+//! The CoreFoundation operations the importer needs, behind a trait so the
+//! COM object itself doesn't care whether it's talking to CF.
+//!
+//! On macOS [`Platform`] is the real thing. Elsewhere it's a pure-Rust stand-in
+//! that reads and writes [`minimal_importer::abi`]'s portable types, which is
+//! what the factory gets passed by `minimal-importer-host` and the tests.
+//!
+//! Every pointer is whatever the host handed over: a `CFUUIDRef`,
+//! `CFStringRef`, `CFMutableDictionaryRef` or the factory's allocator
+//! argument, or their portable counterparts.
+
+use std::ffi::c_void;
+
+use log::Level;
+use minimal_importer::AttrValue;
+use minimal_importer::path::Resolved;
+
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(target_os = "macos")]
+pub use macos::Mac as Platform;
+
+#[cfg(not(target_os = "macos"))]
+mod portable;
+#[cfg(not(target_os = "macos"))]
+pub use portable::Portable as Platform;
+
+pub trait CoreFoundation {
+    /// The bytes of a `CFUUIDRef`.
+    ///
+    /// # Safety
+    ///
+    /// `uuid` must be a valid UUID of this platform.
+    unsafe fn uuid_bytes(uuid: *const c_void) -> [u8; 16];
+
+    /// A new UUID reference, released with [`release_uuid`].
+    ///
+    /// [`release_uuid`]: CoreFoundation::release_uuid
+    fn create_uuid(bytes: [u8; 16]) -> *mut c_void;
+
+    /// # Safety
+    ///
+    /// `uuid` must come from [`create_uuid`] and not be used afterwards.
+    ///
+    /// [`create_uuid`]: CoreFoundation::create_uuid
+    unsafe fn release_uuid(uuid: *mut c_void);
+
+    /// `CFPlugInAddInstanceForFactory`. `host` is the factory's allocator
+    /// argument.
+    ///
+    /// # Safety
+    ///
+    /// `host` and `factory` must be valid for this platform.
+    unsafe fn add_instance_for_factory(host: *mut c_void, factory: *const c_void);
+
+    /// `CFPlugInRemoveInstanceForFactory`.
+    ///
+    /// # Safety
+    ///
+    /// As for [`add_instance_for_factory`].
+    ///
+    /// [`add_instance_for_factory`]: CoreFoundation::add_instance_for_factory
+    unsafe fn remove_instance_for_factory(host: *mut c_void, factory: *const c_void);
+
+    /// The text of a `CFStringRef`, or `None` if it's NULL or can't be read
+    /// as UTF-8.
+    ///
+    /// # Safety
+    ///
+    /// `string` must be NULL or a valid string of this platform.
+    unsafe fn string(string: *const c_void) -> Option<String>;
+
+    /// The file a `CFStringRef` path or `CFURLRef` names, without losing
+    /// what UTF-8 can't hold; see [`minimal_importer::path`]. `None` if it's
+    /// neither, or a URL to somewhere other than this machine.
+    ///
+    /// # Safety
+    ///
+    /// `path` must be a valid string or URL of this platform.
+    unsafe fn path(path: *const c_void) -> Option<Resolved>;
+
+    /// Sets `key` in a mutable dictionary, converting `value` to the type
+    /// Spotlight expects.
+    ///
+    /// # Safety
+    ///
+    /// `dictionary` must be a valid mutable dictionary of this platform.
+    unsafe fn set(dictionary: *mut c_void, key: &str, value: &AttrValue);
+
+    /// Sends `message` to the system log, or the host's stand-in for it.
+    ///
+    /// # Safety
+    ///
+    /// `host` must be valid for this platform.
+    unsafe fn log(host: *mut c_void, level: Level, message: &str);
+}
@@ -0,0 +1,153 @@
+//! [`CoreFoundation`] backed by the real CoreFoundation.
+
+use std::ffi::c_void;
+use std::ptr::NonNull;
+use std::sync::Once;
+
+use log::{Level, LevelFilter};
+use minimal_importer::AttrValue;
+use minimal_importer::path::{self, Resolved};
+use objc2_core_foundation::{
+    CFArray, CFBoolean, CFDate, CFIndex, CFMutableDictionary, CFNumber, CFPlugIn, CFRange,
+    CFRetained, CFString, CFStringBuiltInEncodings, CFType, CFURL, CFUUID, CFUUIDBytes,
+    kCFAllocatorDefault,
+};
+use oslog::OsLogger;
+
+use super::CoreFoundation;
+
+pub struct Mac;
+
+#[rustfmt::skip]
+fn constant_uuid(bytes: [u8; 16]) -> CFRetained<CFUUID> {
+    let [b0, b1, b2, b3, b4, b5, b6, b7, b8, b9, b10, b11, b12, b13, b14, b15] = bytes;
+    CFUUID::constant_uuid_with_bytes(unsafe { kCFAllocatorDefault },
+        b0, b1, b2, b3, b4, b5, b6, b7,
+        b8, b9, b10, b11, b12, b13, b14, b15,
+    ).unwrap()
+}
+
+/// Borrows a CF object the host passed in, `None` if it passed NULL.
+unsafe fn borrow<'a, T>(ptr: *const c_void) -> Option<&'a T> {
+    unsafe { ptr.cast::<T>().as_ref() }
+}
+
+/// `MAXPATHLEN`, what `CFURLGetFileSystemRepresentation` can need.
+const MAX_PATH: usize = 1024;
+
+/// The bytes before the NUL a `Get…FileSystemRepresentation` wrote.
+fn representation(buffer: &[u8]) -> Resolved {
+    let len = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+    path::from_bytes(&buffer[..len])
+}
+
+/// Converts an importer attribute into the CoreFoundation type Spotlight expects.
+fn cf_value(value: &AttrValue) -> CFRetained<CFType> {
+    match value {
+        AttrValue::String(s) => CFString::from_str(s).into(),
+        AttrValue::Integer(i) => CFNumber::new_i64(*i).into(),
+        AttrValue::Real(f) => CFNumber::new_f64(*f).into(),
+        AttrValue::Bool(b) => CFBoolean::new(*b).into(),
+        AttrValue::Date(t) => CFDate::from_system_time(t).into(),
+        AttrValue::Array(values) => {
+            let values: Vec<CFRetained<CFType>> = values.iter().map(cf_value).collect();
+            (&*CFArray::from_retained_objects(&values)).into()
+        }
+    }
+}
+
+impl CoreFoundation for Mac {
+    unsafe fn uuid_bytes(uuid: *const c_void) -> [u8; 16] {
+        // No factory has the nil UUID, so a NULL one gets refused.
+        let Some(uuid) = (unsafe { borrow::<CFUUID>(uuid) }) else {
+            return [0; 16];
+        };
+        let bytes: CFUUIDBytes = uuid.uuid_bytes();
+        // Sixteen `u8` fields, in order.
+        unsafe { std::mem::transmute::<CFUUIDBytes, [u8; 16]>(bytes) }
+    }
+
+    fn create_uuid(bytes: [u8; 16]) -> *mut c_void {
+        CFRetained::into_raw(constant_uuid(bytes)).as_ptr().cast()
+    }
+
+    unsafe fn release_uuid(uuid: *mut c_void) {
+        drop(unsafe { CFRetained::from_raw(NonNull::new(uuid.cast::<CFUUID>()).unwrap()) });
+    }
+
+    unsafe fn add_instance_for_factory(_host: *mut c_void, factory: *const c_void) {
+        CFPlugIn::add_instance_for_factory(unsafe { borrow::<CFUUID>(factory) });
+    }
+
+    unsafe fn remove_instance_for_factory(_host: *mut c_void, factory: *const c_void) {
+        CFPlugIn::remove_instance_for_factory(unsafe { borrow::<CFUUID>(factory) });
+    }
+
+    unsafe fn string(string: *const c_void) -> Option<String> {
+        let string = unsafe { borrow::<CFString>(string) }?;
+        let utf8 = CFStringBuiltInEncodings::EncodingUTF8.0;
+        // Room for the worst case and the NUL.
+        let size = CFString::maximum_size_for_encoding(string.length(), utf8) + 1;
+        let mut buffer = vec![0_u8; size as usize];
+        // Fails on unpaired surrogates, which UTF-8 can't hold.
+        let ok = unsafe { string.c_string(buffer.as_mut_ptr().cast(), size, utf8) };
+        if !ok {
+            return None;
+        }
+        let len = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+        buffer.truncate(len);
+        String::from_utf8(buffer).ok()
+    }
+
+    unsafe fn path(path: *const c_void) -> Option<Resolved> {
+        let object = unsafe { borrow::<CFType>(path) }?;
+        if let Some(url) = object.downcast_ref::<CFURL>() {
+            let mut buffer = [0; MAX_PATH];
+            // Fails for URLs that aren't local files.
+            let ok = unsafe {
+                url.file_system_representation(true, buffer.as_mut_ptr(), MAX_PATH as CFIndex)
+            };
+            return ok.then(|| representation(&buffer));
+        }
+
+        let string = object.downcast_ref::<CFString>()?;
+        let mut buffer = vec![0_u8; string.maximum_size_of_file_system_representation() as usize];
+        let ok = unsafe {
+            string.file_system_representation(buffer.as_mut_ptr().cast(), buffer.len() as CFIndex)
+        };
+        if ok {
+            return Some(representation(&buffer));
+        }
+        // Unpaired surrogates have no file system representation.
+        let length = string.length();
+        let mut units = vec![0; length as usize];
+        unsafe {
+            string.characters(
+                CFRange {
+                    location: 0,
+                    length,
+                },
+                units.as_mut_ptr(),
+            )
+        };
+        Some(path::from_utf16(&units))
+    }
+
+    unsafe fn set(dictionary: *mut c_void, key: &str, value: &AttrValue) {
+        if let Some(dict) = unsafe { borrow::<CFMutableDictionary<CFString, CFType>>(dictionary) } {
+            dict.set(&CFString::from_str(key), &cf_value(value));
+        }
+    }
+
+    unsafe fn log(_host: *mut c_void, level: Level, message: &str) {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            OsLogger::new("vin.je.minimal-importer")
+                .level_filter(LevelFilter::Debug)
+                .init()
+                // Someone else in the process set up logging first.
+                .ok();
+        });
+        log::log!(level, "{message}");
+    }
+}
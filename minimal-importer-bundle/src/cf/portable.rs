@@ -0,0 +1,142 @@
+//! [`CoreFoundation`] over [`abi`]'s portable types, for hosts without
+//! CoreFoundation. The factory's allocator argument is the
+//! [`PortableHost`], which does CFPlugIn's instance counting and logging.
+
+use std::ffi::c_void;
+
+use log::Level;
+use minimal_importer::AttrValue;
+use minimal_importer::abi::{
+    self, CFUUIDBytes, PortableDictionary, PortableHost, PortableString, PortableValue,
+};
+use minimal_importer::path::{self, Resolved};
+
+use super::CoreFoundation;
+
+pub struct Portable;
+
+impl CoreFoundation for Portable {
+    unsafe fn uuid_bytes(uuid: *const c_void) -> [u8; 16] {
+        unsafe { (*uuid.cast::<CFUUIDBytes>()).bytes }
+    }
+
+    fn create_uuid(bytes: [u8; 16]) -> *mut c_void {
+        Box::into_raw(Box::new(CFUUIDBytes { bytes })).cast()
+    }
+
+    unsafe fn release_uuid(uuid: *mut c_void) {
+        drop(unsafe { Box::from_raw(uuid.cast::<CFUUIDBytes>()) });
+    }
+
+    unsafe fn add_instance_for_factory(host: *mut c_void, factory: *const c_void) {
+        let host = unsafe { &*host.cast::<PortableHost>() };
+        unsafe { (host.add_instance_for_factory)(host.context, factory.cast()) };
+    }
+
+    unsafe fn remove_instance_for_factory(host: *mut c_void, factory: *const c_void) {
+        let host = unsafe { &*host.cast::<PortableHost>() };
+        unsafe { (host.remove_instance_for_factory)(host.context, factory.cast()) };
+    }
+
+    unsafe fn string(string: *const c_void) -> Option<String> {
+        let string = unsafe { string.cast::<PortableString>().as_ref() }?;
+        unsafe { string.as_str() }.map(str::to_owned)
+    }
+
+    unsafe fn path(path: *const c_void) -> Option<Resolved> {
+        // A path can contain `://` too, so only a `file:` scheme makes a URL.
+        let bytes = unsafe { (*path.cast::<PortableString>()).as_bytes() };
+        let is_url = bytes
+            .get(..5)
+            .is_some_and(|scheme| scheme.eq_ignore_ascii_case(b"file:"));
+        match std::str::from_utf8(bytes) {
+            Ok(url) if is_url => path::from_file_url(url),
+            _ => Some(path::from_bytes(bytes)),
+        }
+    }
+
+    unsafe fn set(dictionary: *mut c_void, key: &str, value: &AttrValue) {
+        let dict = unsafe { &*dictionary.cast::<PortableDictionary>() };
+        let key = PortableString::new(key);
+        PortableValue::with(value, |value| unsafe {
+            (dict.set)(dict.context, &key, value)
+        });
+    }
+
+    unsafe fn log(host: *mut c_void, level: Level, message: &str) {
+        let host = unsafe { &*host.cast::<PortableHost>() };
+        let level = match level {
+            Level::Error => abi::LOG_ERROR,
+            Level::Warn => abi::LOG_WARN,
+            Level::Info => abi::LOG_INFO,
+            Level::Debug | Level::Trace => abi::LOG_DEBUG,
+        };
+        unsafe { (host.log)(host.context, level, &PortableString::new(message)) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use minimal_importer::Attributes;
+
+    unsafe extern "C-unwind" fn set(
+        context: *mut c_void,
+        key: *const PortableString,
+        value: *const PortableValue,
+    ) {
+        let attrs = unsafe { &mut *context.cast::<Attributes>() };
+        let key = unsafe { (*key).as_str() }.unwrap();
+        attrs.insert(key, unsafe { (*value).to_attr_value() }.unwrap());
+    }
+
+    #[test]
+    fn reads_and_writes_portable_objects() {
+        let uuid = Portable::create_uuid([7; 16]);
+        assert_eq!(unsafe { Portable::uuid_bytes(uuid) }, [7; 16]);
+        unsafe { Portable::release_uuid(uuid) };
+
+        let text = PortableString::new("naïve");
+        let string = &text as *const PortableString as *const c_void;
+        assert_eq!(
+            unsafe { Portable::string(string) }.as_deref(),
+            Some("naïve")
+        );
+        let bad = PortableString {
+            ptr: [0xff].as_ptr(),
+            len: 1,
+        };
+        let string = &bad as *const PortableString as *const c_void;
+        assert_eq!(unsafe { Portable::string(string) }, None);
+        let resolved = unsafe { Portable::path(string) }.unwrap();
+        assert_eq!(
+            resolved.path.as_os_str().as_encoded_bytes(),
+            if cfg!(unix) {
+                &[0xff][..]
+            } else {
+                "\u{fffd}".as_bytes()
+            }
+        );
+
+        let url = PortableString::new("file:///nonexistent/a%20b");
+        let url = &url as *const PortableString as *const c_void;
+        let resolved = unsafe { Portable::path(url) }.unwrap();
+        assert_eq!(resolved.path, std::path::Path::new("/nonexistent/a b"));
+        let url = PortableString::new("FILE://server/a");
+        let url = &url as *const PortableString as *const c_void;
+        assert_eq!(unsafe { Portable::path(url) }, None);
+        let odd = PortableString::new("/tmp/a://b.great");
+        let odd = &odd as *const PortableString as *const c_void;
+        let resolved = unsafe { Portable::path(odd) }.unwrap();
+        assert_eq!(resolved.path, std::path::Path::new("/tmp/a://b.great"));
+
+        let mut attrs = Attributes::new();
+        let mut dict = PortableDictionary {
+            context: (&mut attrs as *mut Attributes).cast(),
+            set,
+        };
+        let value = AttrValue::Array(vec!["a".into(), 1_i64.into()]);
+        unsafe { Portable::set((&mut dict as *mut PortableDictionary).cast(), "k", &value) };
+        assert_eq!(attrs.get("k"), Some(&value));
+    }
+}
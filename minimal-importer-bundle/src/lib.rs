@@ -4,226 +4,162 @@
 // #![feature(box_as_ptr)]
 #![allow(non_snake_case)]
 #![allow(non_upper_case_globals)]
-#![feature(stmt_expr_attributes)]
 
-use log::{LevelFilter, info};
-use objc2_core_foundation::{
-    CFAllocator, CFMutableDictionary, CFPlugIn, CFRetained, CFString, CFUUID, HRESULT, LPVOID,
-    REFIID, ULONG, kCFAllocatorDefault,
-};
-use oslog::OsLogger;
+//! The Spotlight importer's CFPlugIn entry points.
+//!
+//! The factory, reference counting and import are the same everywhere; the
+//! CoreFoundation objects they're handed go through [`cf::Platform`]. On
+//! macOS those are real CF objects. Elsewhere they're
+//! [`minimal_importer::abi`]'s portable stand-ins, and a host like
+//! `minimal-importer-host` plays CFPlugIn's part.
+
+use log::Level;
+use minimal_importer::abi::{self, BuildInfoInterface, CFUUIDBytes, HRESULT, S_FALSE, S_OK};
+use minimal_importer::open;
+use minimal_importer::path::Strategy;
+use minimal_importer::plugin::build_info;
+use minimal_importer::{Attributes, plugin, registry};
 use std::ffi::c_void;
+use std::mem::offset_of;
 use std::ptr;
-use std::ptr::NonNull;
-
-/// This is the key Spotlight expects for the human-readable description.
-const kMDItemDescription: &str = "kMDItemDescription";
-
-fn kMDImporterTypeID() -> CFRetained<CFUUID> {
-    #[rustfmt::skip]
-    CFUUID::constant_uuid_with_bytes(unsafe { kCFAllocatorDefault },
-        0x8B, 0x08, 0xC4, 0xBF, 0x41, 0x5B, 0x11, 0xD8,
-        0xB3, 0xF9, 0x00, 0x03, 0x93, 0x67, 0x26, 0xFC,
-    ).unwrap()
-}
 
-fn kMDImporterInterfaceID() -> CFRetained<CFUUID> {
-    #[rustfmt::skip]
-    CFUUID::constant_uuid_with_bytes(unsafe { kCFAllocatorDefault },
-        0x6E, 0xBC, 0x27, 0xC4, 0x89, 0x9C, 0x11, 0xD8,
-        0x84, 0xAE, 0x00, 0x03, 0x93, 0x67, 0x26, 0xFC,
-    ).unwrap()
-}
+pub mod cf;
 
-fn MetadataImporterPluginFactoryUUID() -> CFRetained<CFUUID> {
-    #[rustfmt::skip]
-    CFUUID::constant_uuid_with_bytes(unsafe { kCFAllocatorDefault },
-        0xd8, 0x78, 0x57, 0xf7, 0xb0, 0xc0, 0x4c, 0x70,
-        0x9b, 0x8f, 0x2e, 0x3d, 0x8e, 0x55, 0x19, 0x8c,
-    ).unwrap()
-}
+use cf::{CoreFoundation, Platform};
 
-fn IUnknownUUID() -> CFRetained<CFUUID> {
-    #[rustfmt::skip]
-    CFUUID::constant_uuid_with_bytes(unsafe { kCFAllocatorDefault },
-        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-        0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46,
-    ).unwrap()
-}
+pub type MDImporterInterfaceStruct = abi::MDImporterInterface;
 
 #[repr(C)]
 #[derive(Debug)]
-pub struct MDImporterInterfaceStruct {
-    _reserved: *mut c_void,
-    query_interface: Option<
-        extern "C-unwind" fn(
-            this: *mut MetadataImporterPluginType,
-            iid: REFIID,
-            out: *mut LPVOID,
-        ) -> HRESULT,
-    >,
-    add_ref: Option<extern "C-unwind" fn(this: *mut MetadataImporterPluginType) -> ULONG>,
-    release: Option<extern "C-unwind" fn(this: *mut MetadataImporterPluginType) -> ULONG>,
-    importer_import_data: Option<
-        extern "C-unwind" fn(
-            this: *mut MetadataImporterPluginType,
-            attr: *mut CFMutableDictionary,
-            content_type_uti: *mut CFString,
-            path_to_file: *mut CFString,
-        ) -> bool,
-    >,
-}
-
-unsafe impl Send for MDImporterInterfaceStruct {}
-unsafe impl Sync for MDImporterInterfaceStruct {}
-
-impl AsRef<MDImporterInterfaceStruct> for MDImporterInterfaceStruct {
-    fn as_ref(&self) -> &MDImporterInterfaceStruct {
-        &self
-    }
+pub struct MetadataImporterPluginType {
+    conduitInterface: *const MDImporterInterfaceStruct,
+    factoryID: *mut c_void,
+    refCount: u32,
+    /// The factory's allocator argument, kept for [`CoreFoundation`] calls.
+    host: *mut c_void,
+    /// What `QueryInterface` hands out for the build-info interface.
+    buildInfoInterface: *const BuildInfoInterface,
 }
 
-impl AsMut<MDImporterInterfaceStruct> for MDImporterInterfaceStruct {
-    fn as_mut(&mut self) -> &mut MDImporterInterfaceStruct {
-        self
+impl MetadataImporterPluginType {
+    fn log(&self, level: Level, message: &str) {
+        unsafe { Platform::log(self.host, level, message) };
     }
-}
 
-impl MDImporterInterfaceStruct {
-    pub fn query_interface_safe(
-        &self,
-        handle: &mut MetadataImporterPluginType,
-        iid: CFRetained<CFUUID>,
-        out: *mut LPVOID,
-    ) -> HRESULT {
-        println!("query_interface_safe: handle: {handle:#?} iid: {iid:#?} out: {out:#?}");
-        if iid == kMDImporterInterfaceID() || iid == IUnknownUUID() {
-            handle.add_ref();
-            let out_typed = out.cast::<*mut MetadataImporterPluginType>();
+    fn query_interface(&mut self, iid: CFUUIDBytes, out: *mut *mut c_void) -> HRESULT {
+        if iid.bytes == plugin::kMDImporterInterfaceID || iid.bytes == plugin::IUnknownUUID {
+            self.add_ref();
+            unsafe { *out = (self as *mut Self).cast() };
+            S_OK
+        } else if iid.bytes == plugin::kMinimalImporterBuildInfoInterfaceID {
+            self.add_ref();
             unsafe {
-                *out_typed = handle.as_ptr();
+                *out = (&mut self.buildInfoInterface as *mut *const BuildInfoInterface).cast()
             };
-            0 // S_OK
+            S_OK
         } else {
             unsafe { *out = ptr::null_mut() };
-            1 // S_FALSE
+            S_FALSE
         }
     }
-}
 
-#[repr(C)]
-#[derive(Debug)]
-pub struct MetadataImporterPluginType {
-    conduitInterface: *const MDImporterInterfaceStruct,
-    factoryID: *mut CFUUID,
-    refCount: u32,
-}
-
-impl MetadataImporterPluginType {
-    pub fn as_ptr(&mut self) -> *mut MetadataImporterPluginType {
-        self
-    }
-    pub fn intf(&self) -> &MDImporterInterfaceStruct {
-        unsafe { self.conduitInterface.as_ref() }.unwrap()
-    }
-    pub fn factory_id(&self) -> CFRetained<CFUUID> {
-        println!("MetadataImporterPluginType::factory_id self: {self:#?}");
-        let nnfid = NonNull::new(self.factoryID).unwrap();
-        unsafe { CFRetained::from_raw(nnfid) }
-    }
-    pub fn query_interface(&mut self, iid: CFRetained<CFUUID>, out: *mut LPVOID) -> HRESULT {
-        println!("MetadataImporterPluginType::query_interface self: {self:#?}");
-        unsafe { self.conduitInterface.as_ref() }
-            .unwrap()
-            .query_interface_safe(self, iid, out)
-    }
-    pub fn add_ref(&mut self) -> ULONG {
-        println!("MetadataImporterPluginType::add_ref self: {self:#?}");
-        self.refCount.checked_add(1).unwrap()
+    pub fn add_ref(&mut self) -> u32 {
+        self.refCount = self.refCount.checked_add(1).unwrap();
+        self.refCount
     }
-    pub fn release(&mut self) -> ULONG {
-        println!("MetadataImporterPluginType::release self: {self:#?}");
+
+    /// Drops a reference. At zero the instance stops counting towards its
+    /// factory, and the caller has to free it.
+    pub fn release(&mut self) -> u32 {
         if self.refCount < 1 {
             panic!("ref count underflow");
         }
-        let rc = self.refCount.checked_sub(1).unwrap();
-        if rc != 0 {
-            rc
-        } else {
-            let fuuid = unsafe { CFRetained::from_raw(NonNull::new(self.factoryID).unwrap()) };
+        self.refCount -= 1;
+        if self.refCount == 0 {
+            unsafe {
+                Platform::remove_instance_for_factory(self.host, self.factoryID);
+                Platform::release_uuid(self.factoryID);
+            }
             self.factoryID = ptr::null_mut();
-            CFPlugIn::remove_instance_for_factory(Some(&fuuid));
-            rc
         }
+        self.refCount
     }
-}
 
-extern "C-unwind" fn com_query_interface(
-    this: *mut MetadataImporterPluginType,
-    iid: REFIID,
-    out: *mut LPVOID,
-) -> HRESULT {
-    let iuuid = unsafe { CFUUID::from_uuid_bytes(kCFAllocatorDefault, iid) }.unwrap();
-    unsafe { this.as_mut() }
-        .unwrap()
-        .query_interface(iuuid, out)
+    fn import_data(&self, attr: *mut c_void, uti: *const c_void, path: *const c_void) -> bool {
+        let uti = unsafe { Platform::string(uti) };
+        let path = unsafe { Platform::path(path) };
+        let (Some(uti), Some(path)) = (uti, path) else {
+            self.log(Level::Error, "import called with an unreadable path or UTI");
+            return false;
+        };
+        if path.strategy != Strategy::Exact {
+            let message = format!("found {} by {:?}", path.path.display(), path.strategy);
+            self.log(Level::Debug, &message);
+        }
+
+        let mut attrs = Attributes::new();
+        if let Err(err) = minimal_importer::import_file(&mut attrs, &uti, &path.path) {
+            match open::rejection(&err) {
+                Some(rejection) => {
+                    let message = format!("{}: {rejection}", path.path.display());
+                    self.log(Level::Warn, &message);
+                }
+                None => {
+                    let message = format!("import of {} failed: {err}", path.path.display());
+                    self.log(Level::Error, &message);
+                }
+            }
+            return false;
+        }
+        for (key, value) in attrs.iter() {
+            unsafe { Platform::set(attr, key, value) };
+        }
+        true
+    }
 }
 
-extern "C-unwind" fn com_add_ref(this: *mut MetadataImporterPluginType) -> ULONG {
-    unsafe { this.as_mut() }.unwrap().add_ref()
+/// The instance behind a pointer the host passed in. With
+/// `track-instances`, panics if it isn't live rather than reading freed
+/// memory.
+fn instance(this: *mut c_void) -> *mut MetadataImporterPluginType {
+    #[cfg(feature = "track-instances")]
+    minimal_importer::instances::check_live(this);
+    this.cast()
 }
 
-extern "C-unwind" fn com_release(this: *mut MetadataImporterPluginType) -> ULONG {
-    // let pt = &mut unsafe { *this };
-    // println!("com_release this: {this:#?} pt: {pt:#?}");
-    // unsafe { this.as_mut() }.unwrap().release()
-    let hndl = unsafe { this.as_mut() }.unwrap();
-    println!("com_release FAKE this: {this:#?} hndl: {hndl:#?}");
-    hndl.release()
+unsafe extern "C-unwind" fn com_query_interface(
+    this: *mut c_void,
+    iid: CFUUIDBytes,
+    out: *mut *mut c_void,
+) -> HRESULT {
+    let this = unsafe { instance(this).as_mut() }.unwrap();
+    this.query_interface(iid, out)
 }
 
-extern "C-unwind" fn com_importer_import_data(
-    this: *mut MetadataImporterPluginType,
-    attr: *mut CFMutableDictionary,
-    uti: *mut CFString,
-    path: *mut CFString,
-) -> bool {
-    println!("com_importer_import_data this: {this:#?}");
-    let path_cfstr = unsafe { CFRetained::retain(NonNull::new(path).unwrap()) };
-    let path_str = path_cfstr.to_string();
-    println!("com_importer_import_data path: {path_str}");
-    let attro = unsafe { CFRetained::retain(NonNull::new(attr).unwrap()) };
-    println!("com_importer_import_data attr: {attro:#?}");
-    let utio = unsafe { CFRetained::retain(NonNull::new(uti).unwrap()) };
-    println!("com_importer_import_data uti: {utio:#?}");
+unsafe extern "C-unwind" fn com_add_ref(this: *mut c_void) -> u32 {
+    unsafe { instance(this).as_mut() }.unwrap().add_ref()
+}
 
-    // if let Ok(file) = File::open(&path_str) {
-    //     let mut reader = BufReader::new(file);
-    //     let mut first_line = String::new();
-    //     if reader.read_line(&mut first_line).is_ok() {
-    //         let desc_cfstr = CFString::from_str(first_line.trim());
-    //         let key = CFString::from_str(kMDItemDescription);
-    //         // attr = desc_cfstr;
-    //         // unsafe {objc2_core_foundation::CFDictionarySetValue(Some(&attr), key, &c_void::from(desc_cfst.)) };
-    //         // unsafe { objc2_core_foundation::CFDictionarySetValue(attr as _, key.as_concrete_TypeRef() as _, desc_cfstr.as_concrete_TypeRef() as _) };
-    //         return true;
-    //     }
-    // }
-    if true {
-        let key = CFString::from_static_str(kMDItemDescription);
-        let val = CFString::from_static_str("this is GREAT");
-        CFMutableDictionary::<CFString, CFString>::add(
-            unsafe {
-                attr.cast::<CFMutableDictionary<CFString, CFString>>()
-                    .as_ref()
-            }
-            .unwrap(),
-            &key,
-            &val,
-        );
+unsafe extern "C-unwind" fn com_release(this: *mut c_void) -> u32 {
+    let this = instance(this);
+    let rc = unsafe { this.as_mut() }.unwrap().release();
+    if rc == 0 {
+        #[cfg(feature = "track-instances")]
+        minimal_importer::instances::freed(this.cast());
+        // The factory boxed it, and this was the last reference.
+        drop(unsafe { Box::from_raw(this) });
     }
+    rc
+}
 
-    false
+unsafe extern "C-unwind" fn com_importer_import_data(
+    this: *mut c_void,
+    attr: *mut c_void,
+    uti: *const c_void,
+    path: *const c_void,
+) -> bool {
+    let this = unsafe { instance(this).as_ref() }.unwrap();
+    this.import_data(attr, uti, path)
 }
 
 static INTERFACE: MDImporterInterfaceStruct = MDImporterInterfaceStruct {
@@ -234,36 +170,140 @@ static INTERFACE: MDImporterInterfaceStruct = MDImporterInterfaceStruct {
     importer_import_data: Some(com_importer_import_data),
 };
 
+/// What this build reports through the build-info interface.
+pub fn build_info() -> Attributes {
+    let mut attrs = Attributes::new();
+    attrs.insert(build_info::Version, minimal_importer::VERSION);
+    attrs.insert(build_info::GitHash, env!("MINIMAL_IMPORTER_GIT_HASH"));
+    attrs.insert(build_info::Profile, env!("MINIMAL_IMPORTER_PROFILE"));
+    let features: Vec<&str> = env!("MINIMAL_IMPORTER_FEATURES")
+        .split(',')
+        .filter(|f| !f.is_empty())
+        .collect();
+    attrs.insert(build_info::Features, features);
+    attrs.insert(
+        build_info::ContentTypes,
+        registry::utis().collect::<Vec<_>>(),
+    );
+    attrs
+}
+
+/// The instance a build-info interface pointer points into.
+fn from_build_info(this: *mut c_void) -> *mut MetadataImporterPluginType {
+    this.wrapping_byte_sub(offset_of!(MetadataImporterPluginType, buildInfoInterface))
+        .cast()
+}
+
+unsafe extern "C-unwind" fn build_info_query_interface(
+    this: *mut c_void,
+    iid: CFUUIDBytes,
+    out: *mut *mut c_void,
+) -> HRESULT {
+    unsafe { com_query_interface(from_build_info(this).cast(), iid, out) }
+}
+
+unsafe extern "C-unwind" fn build_info_add_ref(this: *mut c_void) -> u32 {
+    unsafe { com_add_ref(from_build_info(this).cast()) }
+}
+
+unsafe extern "C-unwind" fn build_info_release(this: *mut c_void) -> u32 {
+    unsafe { com_release(from_build_info(this).cast()) }
+}
+
+unsafe extern "C-unwind" fn build_info_copy(_this: *mut c_void, attr: *mut c_void) -> bool {
+    for (key, value) in build_info().iter() {
+        unsafe { Platform::set(attr, key, value) };
+    }
+    true
+}
+
+static BUILD_INFO_INTERFACE: BuildInfoInterface = BuildInfoInterface {
+    _reserved: ptr::null_mut(),
+    query_interface: Some(build_info_query_interface),
+    add_ref: Some(build_info_add_ref),
+    release: Some(build_info_release),
+    copy_build_info: Some(build_info_copy),
+};
+
+/// Creates an importer instance if `inFactoryID` is `kMDImporterTypeID`.
+///
+/// # Safety
+///
+/// `allocator` and `inFactoryID` must be a `CFAllocatorRef` and `CFUUIDRef`
+/// on macOS, and a [`abi::PortableHost`] and [`CFUUIDBytes`] elsewhere,
+/// where the host has to outlive every instance.
 #[unsafe(no_mangle)]
-pub extern "C-unwind" fn MetadataImporterPluginFactory(
-    allocator: *mut CFAllocator,
-    inFactoryID: *mut CFUUID,
+pub unsafe extern "C-unwind" fn MetadataImporterPluginFactory(
+    allocator: *mut c_void,
+    inFactoryID: *mut c_void,
 ) -> *mut MetadataImporterPluginType {
-    OsLogger::new("vin.je.minimal-importer")
-        .level_filter(LevelFilter::Debug)
-        .init()
-        .unwrap();
-    info!("MetadataImporterPluginFactory called");
-    println!("passed allocator: {allocator:#?}");
-    println!("passed uuid ptr: {inFactoryID:#?}");
-    println!("");
-    let uuid = unsafe { CFRetained::retain(NonNull::new(inFactoryID).unwrap()) };
-    let importer_uuid = kMDImporterTypeID();
-    println!("passed uuid: {uuid:#?} importer uuid: {importer_uuid:#?}");
-    if uuid != importer_uuid {
-        ptr::null_mut()
-    } else {
-        let ifu = MetadataImporterPluginFactoryUUID();
-        CFPlugIn::add_instance_for_factory(Some(&ifu));
-        let ifu_ptr = CFRetained::into_raw(ifu).as_ptr();
-        let br = Box::new(MetadataImporterPluginType {
+    unsafe {
+        Platform::log(
+            allocator,
+            Level::Info,
+            "MetadataImporterPluginFactory called",
+        )
+    };
+    if unsafe { Platform::uuid_bytes(inFactoryID) } != plugin::kMDImporterTypeID {
+        return ptr::null_mut();
+    }
+    let factoryID = Platform::create_uuid(plugin::MetadataImporterPluginFactoryUUID);
+    unsafe { Platform::add_instance_for_factory(allocator, factoryID) };
+    let instance = Box::into_raw(Box::new(MetadataImporterPluginType {
+        conduitInterface: &INTERFACE,
+        factoryID,
+        refCount: 1,
+        host: allocator,
+        buildInfoInterface: &BUILD_INFO_INTERFACE,
+    }));
+    #[cfg(feature = "track-instances")]
+    minimal_importer::instances::created(instance.cast(), "MetadataImporterPluginType");
+    instance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use minimal_importer::AttrValue;
+
+    #[test]
+    #[should_panic(expected = "ref count underflow")]
+    fn release_past_zero_panics() {
+        let mut plugin = MetadataImporterPluginType {
             conduitInterface: &INTERFACE,
-            factoryID: ifu_ptr,
+            factoryID: ptr::null_mut(),
+            refCount: 0,
+            host: ptr::null_mut(),
+            buildInfoInterface: &BUILD_INFO_INTERFACE,
+        };
+        plugin.release();
+    }
+
+    #[test]
+    fn add_ref_counts_up() {
+        let mut plugin = MetadataImporterPluginType {
+            conduitInterface: &INTERFACE,
+            factoryID: ptr::null_mut(),
             refCount: 1,
-        });
-        let r = Box::into_raw(br);
-        let dr = unsafe { r.as_ref() }.unwrap();
-        println!("MetadataImporterPluginFactory returning r: {r:#?} dr: {dr:#?}");
-        r
+            host: ptr::null_mut(),
+            buildInfoInterface: &BUILD_INFO_INTERFACE,
+        };
+        assert_eq!(plugin.add_ref(), 2);
+        assert_eq!(plugin.add_ref(), 3);
+        assert_eq!(plugin.refCount, 3);
+    }
+
+    #[test]
+    fn build_info_describes_this_build() {
+        let info = build_info();
+        assert_eq!(
+            info.get(build_info::Version),
+            Some(&minimal_importer::VERSION.into())
+        );
+        assert!(info.get(build_info::GitHash).is_some());
+        let Some(AttrValue::Array(types)) = info.get(build_info::ContentTypes) else {
+            panic!("no content types in {info:?}");
+        };
+        assert!(types.contains(&"vin.je.great".into()));
     }
 }
@@ -0,0 +1,400 @@
+//! The scenarios `junk/comtest.m` and `junk/bndltest.m` walk through by hand:
+//! call the exported factory, check the COM layout the host will rely on,
+//! then `QueryInterface`, import and release the way Spotlight does.
+//!
+//! The vtable is driven through [`minimal_importer::abi`]'s declarations,
+//! which are what the C headers say. Only the CF objects differ: on macOS
+//! they're real, elsewhere [`cf`] fakes them with the portable stand-ins.
+
+use std::ffi::c_void;
+use std::mem::{offset_of, size_of};
+
+use minimal_importer::Attributes;
+use minimal_importer::abi::{
+    self, BuildInfoInterface, MDImporterInterface, PluginInstanceHeader, S_FALSE, S_OK,
+};
+#[cfg(feature = "track-instances")]
+use minimal_importer::instances::Scope;
+use minimal_importer::keys::{
+    kMDItemDescription, kMDItemKeywords, kMDItemLanguages, kMDItemTextContent,
+};
+use minimal_importer::plugin::{
+    IUnknownUUID, kMDImporterInterfaceID, kMDImporterTypeID, kMinimalImporterBuildInfoInterfaceID,
+};
+use minimal_importer_bundle::{MDImporterInterfaceStruct, MetadataImporterPluginFactory};
+
+#[cfg(target_os = "macos")]
+mod cf {
+    use std::ffi::c_void;
+
+    use minimal_importer::{AttrValue, Attributes};
+    use objc2_core_foundation::{
+        CFArray, CFMutableDictionary, CFRetained, CFString, CFType, CFUUID, kCFAllocatorDefault,
+    };
+
+    use super::ImportFn;
+
+    pub struct Cf;
+
+    impl Cf {
+        pub fn new() -> Cf {
+            Cf
+        }
+
+        pub fn allocator(&self) -> *mut c_void {
+            let allocator = unsafe { kCFAllocatorDefault };
+            allocator.map_or(std::ptr::null_mut(), |a| a as *const _ as *mut c_void)
+        }
+
+        #[rustfmt::skip]
+        pub fn uuid(&self, bytes: [u8; 16]) -> *mut c_void {
+            let [b0, b1, b2, b3, b4, b5, b6, b7, b8, b9, b10, b11, b12, b13, b14, b15] = bytes;
+            let uuid = CFUUID::constant_uuid_with_bytes(unsafe { kCFAllocatorDefault },
+                b0, b1, b2, b3, b4, b5, b6, b7,
+                b8, b9, b10, b11, b12, b13, b14, b15,
+            ).unwrap();
+            // Constant UUIDs live forever anyway.
+            CFRetained::into_raw(uuid).as_ptr().cast()
+        }
+
+        /// CFPlugIn doesn't say how many instances a factory has.
+        pub fn instances(&self) -> Option<usize> {
+            None
+        }
+
+        /// Calls `import` with a fresh dictionary and returns whether it
+        /// succeeded and what it set.
+        pub unsafe fn import(
+            &self,
+            import: ImportFn,
+            this: *mut c_void,
+            uti: &str,
+            path: &str,
+        ) -> (bool, Attributes) {
+            let dict = CFMutableDictionary::<CFString, CFType>::empty();
+            let uti = CFString::from_str(uti);
+            let path = CFString::from_str(path);
+            let ok = unsafe {
+                import(
+                    this,
+                    CFRetained::as_ptr(&dict).as_ptr().cast(),
+                    CFRetained::as_ptr(&uti).as_ptr().cast(),
+                    CFRetained::as_ptr(&path).as_ptr().cast(),
+                )
+            };
+            let mut attrs = Attributes::new();
+            let (keys, values) = dict.to_vecs();
+            for (key, value) in keys.iter().zip(&values) {
+                attrs.insert(key.to_string(), attr_value(value));
+            }
+            (ok, attrs)
+        }
+    }
+
+    /// Only strings and arrays of them, which is all the importer sets.
+    fn attr_value(value: &CFType) -> AttrValue {
+        if let Some(string) = value.downcast_ref::<CFString>() {
+            return string.to_string().into();
+        }
+        let array = value
+            .downcast_ref::<CFArray>()
+            .expect("not a string or array");
+        let values = unsafe { array.cast_unchecked::<CFType>() }.to_vec();
+        AttrValue::Array(values.iter().map(|value| attr_value(value)).collect())
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+mod cf {
+    use std::cell::Cell;
+    use std::ffi::c_void;
+
+    use minimal_importer::Attributes;
+    use minimal_importer::abi::{
+        CFUUIDBytes, PortableDictionary, PortableHost, PortableString, PortableValue,
+    };
+
+    use super::ImportFn;
+
+    #[derive(Default)]
+    struct State {
+        instances: Cell<usize>,
+    }
+
+    /// A fake of the CF objects the importer is handed: the host stands in
+    /// for CFPlugIn's instance counters, UUIDs are plain bytes and the
+    /// dictionary is [`Attributes`].
+    pub struct Cf {
+        state: Box<State>,
+        host: Box<PortableHost>,
+    }
+
+    unsafe extern "C-unwind" fn add_instance(context: *mut c_void, _: *const CFUUIDBytes) {
+        let state = unsafe { &*(context as *const State) };
+        state.instances.set(state.instances.get() + 1);
+    }
+
+    unsafe extern "C-unwind" fn remove_instance(context: *mut c_void, _: *const CFUUIDBytes) {
+        let state = unsafe { &*(context as *const State) };
+        state.instances.set(state.instances.get() - 1);
+    }
+
+    unsafe extern "C-unwind" fn log(_: *mut c_void, _: u32, _: *const PortableString) {}
+
+    unsafe extern "C-unwind" fn set(
+        context: *mut c_void,
+        key: *const PortableString,
+        value: *const PortableValue,
+    ) {
+        let attrs = unsafe { &mut *(context as *mut Attributes) };
+        let key = unsafe { (*key).as_str() }.unwrap();
+        attrs.insert(key, unsafe { (*value).to_attr_value() }.unwrap());
+    }
+
+    impl Cf {
+        pub fn new() -> Cf {
+            let state = Box::new(State::default());
+            let host = Box::new(PortableHost {
+                context: &*state as *const State as *mut c_void,
+                add_instance_for_factory: add_instance,
+                remove_instance_for_factory: remove_instance,
+                log,
+            });
+            Cf { state, host }
+        }
+
+        pub fn allocator(&self) -> *mut c_void {
+            &*self.host as *const PortableHost as *mut c_void
+        }
+
+        pub fn uuid(&self, bytes: [u8; 16]) -> *mut c_void {
+            // Like CF's constant UUIDs, these live forever.
+            let uuid: &mut CFUUIDBytes = Box::leak(Box::new(CFUUIDBytes { bytes }));
+            uuid as *mut CFUUIDBytes as *mut c_void
+        }
+
+        pub fn instances(&self) -> Option<usize> {
+            Some(self.state.instances.get())
+        }
+
+        /// Calls `import` with a fresh dictionary and returns whether it
+        /// succeeded and what it set.
+        pub unsafe fn import(
+            &self,
+            import: ImportFn,
+            this: *mut c_void,
+            uti: &str,
+            path: &str,
+        ) -> (bool, Attributes) {
+            let mut attrs = Attributes::new();
+            let mut dict = PortableDictionary {
+                context: &mut attrs as *mut Attributes as *mut c_void,
+                set,
+            };
+            let uti = PortableString::new(uti);
+            let path = PortableString::new(path);
+            let ok = unsafe {
+                import(
+                    this,
+                    &mut dict as *mut PortableDictionary as *mut c_void,
+                    &uti as *const PortableString as *const c_void,
+                    &path as *const PortableString as *const c_void,
+                )
+            };
+            (ok, attrs)
+        }
+    }
+}
+
+type ImportFn =
+    unsafe extern "C-unwind" fn(*mut c_void, *mut c_void, *const c_void, *const c_void) -> bool;
+
+fn create(cf: &cf::Cf, type_id: [u8; 16]) -> *mut c_void {
+    unsafe { MetadataImporterPluginFactory(cf.allocator(), cf.uuid(type_id)) }.cast()
+}
+
+fn header<'a>(instance: *mut c_void) -> &'a PluginInstanceHeader {
+    unsafe { &*(instance as *const PluginInstanceHeader) }
+}
+
+fn vtable<'a>(instance: *mut c_void) -> &'a MDImporterInterface {
+    unsafe { &*header(instance).interface }
+}
+
+fn query(instance: *mut c_void, iid: [u8; 16]) -> (i32, *mut c_void) {
+    let mut out = std::ptr::dangling_mut();
+    let hres = unsafe {
+        vtable(instance).query_interface.unwrap()(
+            instance,
+            abi::CFUUIDBytes { bytes: iid },
+            &mut out,
+        )
+    };
+    (hres, out)
+}
+
+fn release(instance: *mut c_void) -> u32 {
+    unsafe { vtable(instance).release.unwrap()(instance) }
+}
+
+/// With `track-instances`, fails the test if it left an instance live.
+fn assert_no_leaks() {
+    #[cfg(feature = "track-instances")]
+    minimal_importer::instances::assert_none_live(Scope::Thread);
+}
+
+#[test]
+fn factory_rejects_other_types() {
+    let cf = cf::Cf::new();
+    assert!(create(&cf, IUnknownUUID).is_null());
+    assert!(create(&cf, kMDImporterInterfaceID).is_null());
+    assert_eq!(cf.instances().unwrap_or(0), 0);
+}
+
+#[test]
+fn vtable_matches_the_com_headers() {
+    let ptr = size_of::<*const c_void>();
+    // IUnknownVTbl, then MDImporterInterfaceStruct's one method.
+    assert_eq!(offset_of!(MDImporterInterface, _reserved), 0);
+    assert_eq!(offset_of!(MDImporterInterface, query_interface), ptr);
+    assert_eq!(offset_of!(MDImporterInterface, add_ref), 2 * ptr);
+    assert_eq!(offset_of!(MDImporterInterface, release), 3 * ptr);
+    assert_eq!(
+        offset_of!(MDImporterInterface, importer_import_data),
+        4 * ptr
+    );
+    assert_eq!(size_of::<MDImporterInterfaceStruct>(), 5 * ptr);
+    // comtest printed these as pr[0] and pr[2].
+    assert_eq!(offset_of!(PluginInstanceHeader, interface), 0);
+    assert_eq!(offset_of!(PluginInstanceHeader, ref_count), 2 * ptr);
+
+    let cf = cf::Cf::new();
+    let instance = create(&cf, kMDImporterTypeID);
+    assert!(!instance.is_null());
+    let vtable = vtable(instance);
+    assert!(vtable._reserved.is_null());
+    assert!(vtable.query_interface.is_some());
+    assert!(vtable.add_ref.is_some());
+    assert!(vtable.release.is_some());
+    assert!(vtable.importer_import_data.is_some());
+    assert!(!header(instance).factory_id.is_null());
+    assert_eq!(header(instance).ref_count, 1);
+    assert_eq!(release(instance), 0);
+}
+
+#[test]
+fn query_interface_counts_references() {
+    let cf = cf::Cf::new();
+    let iunknown = create(&cf, kMDImporterTypeID);
+    assert_eq!(cf.instances().unwrap_or(1), 1);
+
+    assert_eq!(query(iunknown, [0; 16]), (S_FALSE, std::ptr::null_mut()));
+    assert_eq!(header(iunknown).ref_count, 1);
+
+    let (hres, unknown) = query(iunknown, IUnknownUUID);
+    assert_eq!((hres, unknown), (S_OK, iunknown));
+    assert_eq!(header(iunknown).ref_count, 2);
+    assert_eq!(unsafe { vtable(iunknown).add_ref.unwrap()(iunknown) }, 3);
+    assert_eq!(release(unknown), 2);
+    assert_eq!(release(unknown), 1);
+
+    // bndltest: take the importer interface, then drop the IUnknown.
+    let (hres, mdi) = query(iunknown, kMDImporterInterfaceID);
+    assert_eq!((hres, mdi), (S_OK, iunknown));
+    assert_eq!(release(iunknown), 1);
+    assert_eq!(header(mdi).ref_count, 1);
+    assert_eq!(release(mdi), 0);
+    assert_eq!(cf.instances().unwrap_or(0), 0);
+    assert_no_leaks();
+}
+
+#[test]
+fn build_info_interface_shares_the_count() {
+    let cf = cf::Cf::new();
+    let iunknown = create(&cf, kMDImporterTypeID);
+    let (hres, info) = query(iunknown, kMinimalImporterBuildInfoInterfaceID);
+    assert_eq!(hres, S_OK);
+    assert_ne!(info, iunknown);
+    assert_eq!(header(iunknown).ref_count, 2);
+
+    let vtable = unsafe { &**info.cast::<*const BuildInfoInterface>() };
+    let (hres, mdi) = unsafe {
+        let mut out = std::ptr::null_mut();
+        let hres = vtable.query_interface.unwrap()(
+            info,
+            abi::CFUUIDBytes {
+                bytes: kMDImporterInterfaceID,
+            },
+            &mut out,
+        );
+        (hres, out)
+    };
+    assert_eq!((hres, mdi), (S_OK, iunknown));
+    assert_eq!(unsafe { vtable.add_ref.unwrap()(info) }, 4);
+    assert_eq!(unsafe { vtable.release.unwrap()(info) }, 3);
+    assert_eq!(unsafe { vtable.release.unwrap()(info) }, 2);
+    assert_eq!(release(mdi), 1);
+    assert_eq!(release(iunknown), 0);
+    assert_eq!(cf.instances().unwrap_or(0), 0);
+    assert_no_leaks();
+}
+
+#[test]
+fn import_writes_the_dictionary() {
+    let tmp = tempfile::tempdir().unwrap();
+    let file = tmp.path().join("test.great");
+    std::fs::write(&file, include_str!("../../test.great")).unwrap();
+    let file = file.to_str().unwrap();
+
+    let cf = cf::Cf::new();
+    let iunknown = create(&cf, kMDImporterTypeID);
+    let (_, mdi) = query(iunknown, kMDImporterInterfaceID);
+    release(iunknown);
+    let import = vtable(mdi).importer_import_data.unwrap();
+
+    let (ok, attrs) = unsafe { cf.import(import, mdi, "vin.je.great", file) };
+    assert!(ok);
+    assert_eq!(attrs.get(kMDItemDescription), Some(&"hello".into()));
+    assert_eq!(
+        attrs.get(kMDItemKeywords),
+        Some(&vec!["world", "blank", "hello"].into())
+    );
+    assert_eq!(attrs.get(kMDItemLanguages), Some(&vec!["en"].into()));
+    assert_eq!(
+        attrs.get(kMDItemTextContent),
+        Some(&include_str!("../../test.great").into())
+    );
+
+    assert_eq!(
+        unsafe { cf.import(import, mdi, "public.nothing", file) },
+        (false, Attributes::new())
+    );
+    let missing = tmp.path().join("missing.great");
+    let (ok, _) = unsafe { cf.import(import, mdi, "vin.je.great", missing.to_str().unwrap()) };
+    assert!(!ok);
+    assert_eq!(release(mdi), 0);
+    assert_no_leaks();
+}
+
+#[test]
+#[cfg(feature = "track-instances")]
+#[should_panic(expected = "use after release of MetadataImporterPluginType")]
+fn double_release_is_caught() {
+    let cf = cf::Cf::new();
+    let instance = create(&cf, kMDImporterTypeID);
+    let release = vtable(instance).release.unwrap();
+    assert_eq!(unsafe { release(instance) }, 0);
+    unsafe { release(instance) };
+}
+
+#[test]
+#[cfg(feature = "track-instances")]
+#[should_panic(expected = "leaked instances:\nMetadataImporterPluginType at")]
+fn leaks_are_caught() {
+    let cf = cf::Cf::new();
+    let iunknown = create(&cf, kMDImporterTypeID);
+    let (_, mdi) = query(iunknown, kMDImporterInterfaceID);
+    release(iunknown);
+    assert_ne!(mdi, std::ptr::null_mut());
+    assert_no_leaks();
+}
@@ -0,0 +1,145 @@
+//! Loads the built importer into `minimal-importer-host`, the way Spotlight
+//! loads the real bundle: Info.plist, `dlopen`, factory, vtable and all.
+//!
+//! Only off macOS, where the cdylib speaks the portable ABI the host does.
+#![cfg(not(target_os = "macos"))]
+
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+use std::path::{Path, PathBuf};
+
+use minimal_importer::Attributes;
+use minimal_importer::keys::{
+    kMDItemDescription, kMDItemKeywords, kMDItemLanguages, kMDItemTextContent,
+};
+use minimal_importer::plugin::{kMDImporterInterfaceID, kMDImporterTypeID};
+use minimal_importer_host::{PlugIn, bndltest};
+
+/// Lays out `dir/minimal-importer.mdimporter` around the cdylib cargo built
+/// next to this test.
+fn bundle(dir: &Path) -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    let cdylib = exe
+        .parent()
+        .unwrap()
+        .join(format!("{DLL_PREFIX}minimal_importer_bundle{DLL_SUFFIX}"));
+    let bundle = dir.join("minimal-importer.mdimporter");
+    let macos = bundle.join("Contents/MacOS");
+    std::fs::create_dir_all(&macos).unwrap();
+    std::fs::copy(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/resources/Info.plist.xml"),
+        bundle.join("Contents/Info.plist"),
+    )
+    .unwrap();
+    std::fs::copy(&cdylib, macos.join("minimal-importer-bundle")).unwrap();
+    bundle
+}
+
+#[test]
+fn imports_through_the_host() {
+    let tmp = tempfile::tempdir().unwrap();
+    let file = tmp.path().join("test.great");
+    std::fs::write(&file, include_str!("../../test.great")).unwrap();
+    let bundle = bundle(tmp.path());
+
+    let mut out = Vec::new();
+    let attrs = bndltest(&bundle, file.to_str().unwrap(), "vin.je.great", &mut out).unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(
+        attrs.get(kMDItemDescription),
+        Some(&"hello".into()),
+        "{out}"
+    );
+    assert_eq!(
+        attrs.get(kMDItemKeywords),
+        Some(&vec!["world", "blank", "hello"].into()),
+        "{out}"
+    );
+    assert_eq!(
+        attrs.get(kMDItemLanguages),
+        Some(&vec!["en"].into()),
+        "{out}"
+    );
+    assert_eq!(
+        attrs.get(kMDItemTextContent),
+        Some(&include_str!("../../test.great").into()),
+        "{out}"
+    );
+    assert!(out.contains("after refCount: 1\n"), "{out}");
+    assert!(out.contains("build info:\n"), "{out}");
+    let version = format!("    Version = String({:?})\n", minimal_importer::VERSION);
+    assert!(out.contains(&version), "{out}");
+    assert!(out.contains("instances: 0\n"), "{out}");
+    assert!(
+        out.contains("log 3: MetadataImporterPluginFactory called"),
+        "{out}"
+    );
+
+    let err = bndltest(&bundle, "missing.great", "vin.je.great", &mut Vec::new()).unwrap_err();
+    assert_eq!(err.to_string(), "ImporterImportData failed");
+}
+
+/// Names `to_string` on a `CFString` would get wrong: asked for in the other
+/// normalization form, not UTF-8, or not even valid UTF-16.
+#[test]
+#[cfg(unix)]
+fn imports_tricky_filenames() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let plugin = PlugIn::create(&bundle(tmp.path())).unwrap();
+    let [factory] = plugin.factories_for_type(&kMDImporterTypeID)[..] else {
+        panic!("expected one factory");
+    };
+    let nfc = "Caf\u{e9}.great".as_bytes();
+    let nfd = "Cafe\u{301}.great".as_bytes();
+    let names: [(&[u8], &[u8]); 5] = [
+        (nfd, nfc),
+        (nfc, nfd),
+        (b"caf\xE9 latin-1.great", b"caf\xE9 latin-1.great"),
+        (b"lone \xED\xA0\x80.great", b"lone \xED\xA0\x80.great"),
+        (b"new\nline.great", b"new\nline.great"),
+    ];
+    for (on_disk, asked) in names {
+        std::fs::write(tmp.path().join(OsStr::from_bytes(on_disk)), "hello").unwrap();
+        let asked = tmp.path().join(OsStr::from_bytes(asked));
+        let iunknown = plugin
+            .instance_create(&factory, &kMDImporterTypeID)
+            .unwrap()
+            .unwrap();
+        unsafe {
+            let (_, mdi) = iunknown.query_interface(&kMDImporterInterfaceID);
+            iunknown.release();
+            let mdi = mdi.unwrap();
+            let mut attrs = Attributes::new();
+            assert!(
+                mdi.import_data(&mut attrs, "vin.je.great", &asked),
+                "{asked:?}: {:?}",
+                plugin.take_log()
+            );
+            assert_eq!(attrs.get(kMDItemTextContent), Some(&"hello".into()));
+            mdi.release();
+        }
+        std::fs::remove_file(tmp.path().join(OsStr::from_bytes(on_disk))).unwrap();
+    }
+}
+
+#[test]
+fn logs_refused_files() {
+    let tmp = tempfile::tempdir().unwrap();
+    let bundle = bundle(tmp.path());
+    let mut out = Vec::new();
+    let err = bndltest(
+        &bundle,
+        tmp.path().to_str().unwrap(),
+        "vin.je.great",
+        &mut out,
+    );
+    assert_eq!(err.unwrap_err().to_string(), "ImporterImportData failed");
+    let out = String::from_utf8(out).unwrap();
+    let refused = format!(
+        "log 2: {}: refused a directory (directory)\n",
+        tmp.path().display()
+    );
+    assert!(out.contains(&refused), "{out}");
+}
@@ -0,0 +1,599 @@
+//! A CFPlugIn host for running importers where Spotlight isn't.
+//!
+//! This does what `mdworker` (and `junk/bndltest.m`) does with an importer
+//! bundle: read `Contents/Info.plist`, load the executable in
+//! `Contents/MacOS`, find the factories registered for a plug-in type,
+//! create an instance, `QueryInterface` it for the importer interface and
+//! call `ImporterImportData`. Loading goes through `dlopen`, so the
+//! executable can be a Linux shared object built against the portable
+//! [`abi`], and the exported factory and vtable get driven the same way
+//! CoreFoundation would.
+//!
+//! [`abi`]: minimal_importer::abi
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ffi::c_void;
+use std::fmt;
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use minimal_importer::Attributes;
+use minimal_importer::abi::{
+    BuildInfoInterface, CFUUIDBytes, FactoryFn, HRESULT, MDImporterInterface, PluginInstanceHeader,
+    PortableDictionary, PortableHost, PortableString, PortableValue, S_OK,
+};
+use minimal_importer::plugin::{
+    kMDImporterInterfaceID, kMDImporterTypeID, kMinimalImporterBuildInfoInterfaceID,
+    uuid_from_string, uuid_string,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Plist(plist::Error),
+    Load(libloading::Error),
+    /// A bundle whose Info.plist doesn't describe a loadable plug-in.
+    Invalid(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Plist(e) => write!(f, "{e}"),
+            Error::Load(e) => write!(f, "{e}"),
+            Error::Invalid(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<plist::Error> for Error {
+    fn from(e: plist::Error) -> Self {
+        Error::Plist(e)
+    }
+}
+
+impl From<libloading::Error> for Error {
+    fn from(e: libloading::Error) -> Self {
+        Error::Load(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// What the plug-in reports back through its [`PortableHost`].
+#[derive(Debug, Default)]
+struct HostState {
+    instances: RefCell<BTreeMap<[u8; 16], usize>>,
+    log: RefCell<Vec<(u32, String)>>,
+}
+
+unsafe extern "C-unwind" fn add_instance_for_factory(
+    context: *mut c_void,
+    factory: *const CFUUIDBytes,
+) {
+    let state = unsafe { &*(context as *const HostState) };
+    let factory = unsafe { (*factory).bytes };
+    *state.instances.borrow_mut().entry(factory).or_default() += 1;
+}
+
+unsafe extern "C-unwind" fn remove_instance_for_factory(
+    context: *mut c_void,
+    factory: *const CFUUIDBytes,
+) {
+    let state = unsafe { &*(context as *const HostState) };
+    let factory = unsafe { (*factory).bytes };
+    let mut instances = state.instances.borrow_mut();
+    let count = instances.entry(factory).or_default();
+    // Like CF, an unbalanced remove is the plug-in's bug; don't wrap.
+    *count = count.saturating_sub(1);
+}
+
+unsafe extern "C-unwind" fn log(context: *mut c_void, level: u32, message: *const PortableString) {
+    let state = unsafe { &*(context as *const HostState) };
+    let message = unsafe { (*message).as_str() }.unwrap_or("<invalid UTF-8>");
+    state.log.borrow_mut().push((level, message.to_owned()));
+}
+
+unsafe extern "C-unwind" fn dictionary_set(
+    context: *mut c_void,
+    key: *const PortableString,
+    value: *const PortableValue,
+) {
+    let attrs = unsafe { &mut *(context as *mut Attributes) };
+    let key = unsafe { (*key).as_str() };
+    let value = unsafe { (*value).to_attr_value() };
+    if let (Some(key), Some(value)) = (key, value) {
+        attrs.insert(key, value);
+    }
+}
+
+/// A loaded importer bundle; `CFPlugInRef`.
+pub struct PlugIn {
+    bundle: PathBuf,
+    /// Factory UUID to the name of the function that implements it.
+    factories: BTreeMap<[u8; 16], String>,
+    /// Plug-in type UUID to the factories that create it.
+    types: BTreeMap<[u8; 16], Vec<[u8; 16]>>,
+    // Boxed so the plug-in can hold on to their addresses.
+    state: Box<HostState>,
+    host: Box<PortableHost>,
+    library: libloading::Library,
+}
+
+impl fmt::Debug for PlugIn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PlugIn")
+            .field("bundle", &self.bundle)
+            .field("factories", &self.factories)
+            .finish_non_exhaustive()
+    }
+}
+
+fn parse_uuid(s: &str) -> Result<[u8; 16]> {
+    uuid_from_string(s).ok_or_else(|| Error::Invalid(format!("{s:?} is not a UUID")))
+}
+
+impl PlugIn {
+    /// Loads the bundle at `bundle`, as `CFPlugInCreate` does.
+    pub fn create(bundle: &Path) -> Result<PlugIn> {
+        let info: plist::Dictionary = plist::from_file(bundle.join("Contents/Info.plist"))?;
+        let string = |key: &str| {
+            info.get(key)
+                .and_then(plist::Value::as_string)
+                .ok_or_else(|| Error::Invalid(format!("Info.plist has no {key}")))
+        };
+        let executable = bundle
+            .join("Contents/MacOS")
+            .join(string("CFBundleExecutable")?);
+
+        let mut factories = BTreeMap::new();
+        if let Some(dict) = info
+            .get("CFPlugInFactories")
+            .and_then(plist::Value::as_dictionary)
+        {
+            for (uuid, name) in dict {
+                let name = name.as_string().ok_or_else(|| {
+                    Error::Invalid(format!("factory {uuid} doesn't name a function"))
+                })?;
+                factories.insert(parse_uuid(uuid)?, name.to_owned());
+            }
+        }
+        let mut types = BTreeMap::new();
+        if let Some(dict) = info
+            .get("CFPlugInTypes")
+            .and_then(plist::Value::as_dictionary)
+        {
+            for (uuid, list) in dict {
+                let list = list
+                    .as_array()
+                    .ok_or_else(|| Error::Invalid(format!("type {uuid} doesn't list factories")))?;
+                let ids = list
+                    .iter()
+                    .map(|f| {
+                        f.as_string()
+                            .ok_or_else(|| {
+                                Error::Invalid(format!("type {uuid} lists a non-string"))
+                            })
+                            .and_then(parse_uuid)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                types.insert(parse_uuid(uuid)?, ids);
+            }
+        }
+
+        let library = unsafe { libloading::Library::new(&executable) }?;
+        let state = Box::new(HostState::default());
+        let host = Box::new(PortableHost {
+            context: &*state as *const HostState as *mut c_void,
+            add_instance_for_factory,
+            remove_instance_for_factory,
+            log,
+        });
+        Ok(PlugIn {
+            bundle: bundle.to_owned(),
+            factories,
+            types,
+            state,
+            host,
+            library,
+        })
+    }
+
+    pub fn bundle(&self) -> &Path {
+        &self.bundle
+    }
+
+    /// `CFPlugInFindFactoriesForPlugInTypeInPlugIn`: the registered
+    /// factories that create `type_id`, if the bundle implements them.
+    pub fn factories_for_type(&self, type_id: &[u8; 16]) -> Vec<[u8; 16]> {
+        self.types
+            .get(type_id)
+            .into_iter()
+            .flatten()
+            .filter(|f| self.factories.contains_key(*f))
+            .copied()
+            .collect()
+    }
+
+    /// `CFPlugInInstanceCreate`: calls `factory`'s function with `type_id`.
+    /// `None` means the factory declined, which it should for types it
+    /// doesn't make.
+    pub fn instance_create(
+        &self,
+        factory: &[u8; 16],
+        type_id: &[u8; 16],
+    ) -> Result<Option<Instance<'_>>> {
+        let name = self.factories.get(factory).ok_or_else(|| {
+            Error::Invalid(format!(
+                "{} is not a registered factory",
+                uuid_string(factory)
+            ))
+        })?;
+        let function = unsafe { self.library.get::<FactoryFn>(name.as_bytes()) }?;
+        let type_id = CFUUIDBytes { bytes: *type_id };
+        let instance = unsafe {
+            function(
+                &*self.host as *const PortableHost as *mut c_void,
+                &type_id as *const CFUUIDBytes as *mut c_void,
+            )
+        };
+        Ok((!instance.is_null()).then_some(Instance {
+            ptr: instance,
+            _plugin: PhantomData,
+        }))
+    }
+
+    /// Creates an importer instance to ask it what build it is, through its
+    /// build-info interface. `None` if it doesn't have one.
+    pub fn build_info(&self) -> Result<Option<Attributes>> {
+        let [factory] = self.factories_for_type(&kMDImporterTypeID)[..] else {
+            return Err(Error::Invalid(
+                "expected exactly one importer factory".to_owned(),
+            ));
+        };
+        let instance = self
+            .instance_create(&factory, &kMDImporterTypeID)?
+            .ok_or_else(|| Error::Invalid("the factory returned NULL".to_owned()))?;
+        let info = unsafe { instance.build_info() };
+        unsafe { instance.release() };
+        Ok(info)
+    }
+
+    /// Live instances `factory` has reported creating.
+    pub fn instance_count(&self, factory: &[u8; 16]) -> usize {
+        self.state
+            .instances
+            .borrow()
+            .get(factory)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Takes the messages the plug-in has logged so far, with their
+    /// `LOG_*` levels.
+    pub fn take_log(&self) -> Vec<(u32, String)> {
+        self.state.log.take()
+    }
+}
+
+/// A COM interface pointer into a plug-in: a pointer to a pointer to a
+/// vtable. Copying it doesn't touch the reference count; that's up to the
+/// caller, as in C.
+#[derive(Clone, Copy, Debug)]
+pub struct Instance<'a> {
+    ptr: *mut c_void,
+    _plugin: PhantomData<&'a PlugIn>,
+}
+
+impl Instance<'_> {
+    pub fn as_ptr(&self) -> *mut c_void {
+        self.ptr
+    }
+
+    /// The instance's vtable.
+    ///
+    /// # Safety
+    ///
+    /// The instance must still be alive.
+    pub unsafe fn interface(&self) -> &MDImporterInterface {
+        unsafe { &*(*(self.ptr as *const PluginInstanceHeader)).interface }
+    }
+
+    /// The reference count the instance keeps in its header.
+    ///
+    /// # Safety
+    ///
+    /// The instance must still be alive and lay out a
+    /// [`PluginInstanceHeader`].
+    pub unsafe fn ref_count(&self) -> u32 {
+        unsafe { (*(self.ptr as *const PluginInstanceHeader)).ref_count }
+    }
+
+    /// `QueryInterface`, returning its `HRESULT` and, on success, the new
+    /// reference.
+    ///
+    /// # Safety
+    ///
+    /// The instance must still be alive.
+    pub unsafe fn query_interface(&self, iid: &[u8; 16]) -> (HRESULT, Option<Self>) {
+        let query_interface = unsafe { self.interface() }
+            .query_interface
+            .expect("QueryInterface slot is empty");
+        let mut out = ptr::null_mut();
+        let hres = unsafe { query_interface(self.ptr, CFUUIDBytes { bytes: *iid }, &mut out) };
+        let instance = (hres == S_OK && !out.is_null()).then_some(Instance {
+            ptr: out,
+            _plugin: PhantomData,
+        });
+        (hres, instance)
+    }
+
+    /// # Safety
+    ///
+    /// The instance must still be alive.
+    pub unsafe fn add_ref(&self) -> u32 {
+        let add_ref = unsafe { self.interface() }
+            .add_ref
+            .expect("AddRef slot is empty");
+        unsafe { add_ref(self.ptr) }
+    }
+
+    /// # Safety
+    ///
+    /// The instance must still be alive, and isn't after releasing the
+    /// last reference.
+    pub unsafe fn release(&self) -> u32 {
+        let release = unsafe { self.interface() }
+            .release
+            .expect("Release slot is empty");
+        unsafe { release(self.ptr) }
+    }
+
+    /// `ImporterImportData`, with `attrs` as the dictionary the importer
+    /// fills in. `path` goes over as its bytes, UTF-8 or not.
+    ///
+    /// # Safety
+    ///
+    /// The instance must still be alive and be an importer interface.
+    pub unsafe fn import_data(&self, attrs: &mut Attributes, uti: &str, path: &Path) -> bool {
+        let import_data = unsafe { self.interface() }
+            .importer_import_data
+            .expect("ImporterImportData slot is empty");
+        let mut dictionary = dictionary(attrs);
+        let uti = PortableString::new(uti);
+        let path = PortableString::from_bytes(path.as_os_str().as_encoded_bytes());
+        unsafe {
+            import_data(
+                self.ptr,
+                &mut dictionary as *mut PortableDictionary as *mut c_void,
+                &uti as *const PortableString as *const c_void,
+                &path as *const PortableString as *const c_void,
+            )
+        }
+    }
+
+    /// What the importer's build-info interface reports, or `None` if it
+    /// doesn't have one.
+    ///
+    /// # Safety
+    ///
+    /// The instance must still be alive.
+    pub unsafe fn build_info(&self) -> Option<Attributes> {
+        let (_, info) = unsafe { self.query_interface(&kMinimalImporterBuildInfoInterfaceID) };
+        let info = info?;
+        let vtable = unsafe { &**(info.ptr as *const *const BuildInfoInterface) };
+        let mut attrs = Attributes::new();
+        let copied = match vtable.copy_build_info {
+            Some(copy) => unsafe {
+                copy(
+                    info.ptr,
+                    &mut dictionary(&mut attrs) as *mut PortableDictionary as *mut c_void,
+                )
+            },
+            None => false,
+        };
+        let release = vtable.release.expect("Release slot is empty");
+        unsafe { release(info.ptr) };
+        copied.then_some(attrs)
+    }
+}
+
+/// A dictionary the importer fills `attrs` through.
+fn dictionary(attrs: &mut Attributes) -> PortableDictionary {
+    PortableDictionary {
+        context: attrs as *mut Attributes as *mut c_void,
+        set: dictionary_set,
+    }
+}
+
+/// Runs `bndltest`'s sequence against `bundle`, importing `file` as `uti`,
+/// and narrates it to `out`. Returns the imported attributes, or an error
+/// at the first step that doesn't go the way Spotlight needs it to.
+pub fn bndltest(bundle: &Path, file: &str, uti: &str, out: &mut impl Write) -> Result<Attributes> {
+    let invalid = |msg: &str| Error::Invalid(msg.to_owned());
+    writeln!(out, "bundle: {}", bundle.display())?;
+    writeln!(out, "file_to_import: {file}")?;
+    let plugin = PlugIn::create(bundle)?;
+
+    let factories = plugin.factories_for_type(&kMDImporterTypeID);
+    let names: Vec<String> = factories.iter().map(uuid_string).collect();
+    writeln!(out, "factories: {names:?}")?;
+    let [factory] = factories[..] else {
+        return Err(invalid("expected exactly one importer factory"));
+    };
+
+    let iunknown = plugin
+        .instance_create(&factory, &kMDImporterTypeID)?
+        .ok_or_else(|| invalid("the factory returned NULL"))?;
+    writeln!(out, "instances: {}", plugin.instance_count(&factory))?;
+    let vtable = unsafe { iunknown.interface() };
+    writeln!(
+        out,
+        "iunknown->QueryInterface: {}",
+        vtable.query_interface.is_some()
+    )?;
+    writeln!(out, "iunknown->AddRef: {}", vtable.add_ref.is_some())?;
+    writeln!(out, "iunknown->Release: {}", vtable.release.is_some())?;
+
+    let (hres, mdi) = unsafe { iunknown.query_interface(&kMDImporterInterfaceID) };
+    writeln!(out, "hres: {hres} {:x}", hres as u32)?;
+    let mdi = mdi.ok_or_else(|| invalid("no importer interface"))?;
+    writeln!(out, "before refCount: {}", unsafe { iunknown.ref_count() })?;
+    unsafe { iunknown.release() };
+    writeln!(out, "after refCount: {}", unsafe { mdi.ref_count() })?;
+    writeln!(
+        out,
+        "mdi ImporterImportData: {}",
+        unsafe { mdi.interface() }.importer_import_data.is_some()
+    )?;
+    match unsafe { mdi.build_info() } {
+        Some(info) => {
+            writeln!(out, "build info:")?;
+            for (key, value) in info.iter() {
+                writeln!(out, "    {key} = {value:?}")?;
+            }
+        }
+        None => writeln!(out, "build info: none")?,
+    }
+
+    let mut attrs = Attributes::new();
+    let imported = unsafe { mdi.import_data(&mut attrs, uti, Path::new(file)) };
+    writeln!(out, "import_res: {}", u8::from(imported))?;
+    for (key, value) in attrs.iter() {
+        writeln!(out, "    {key} = {value:?}")?;
+    }
+    unsafe { mdi.release() };
+    writeln!(out, "mdi final release done")?;
+    writeln!(out, "instances: {}", plugin.instance_count(&factory))?;
+    for (level, message) in plugin.take_log() {
+        writeln!(out, "log {level}: {message}")?;
+    }
+    if !imported {
+        return Err(invalid("ImporterImportData failed"));
+    }
+    Ok(attrs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use minimal_importer::AttrValue;
+    use minimal_importer::plugin::{IUnknownUUID, MetadataImporterPluginFactoryUUID};
+    use std::process::Command;
+
+    /// Builds `testdata/plugin.c` into a bundle laid out like the real one,
+    /// with the real bundle's Info.plist.
+    fn fixture_bundle(dir: &Path) -> PathBuf {
+        let bundle = dir.join("fixture.mdimporter");
+        let macos = bundle.join("Contents/MacOS");
+        std::fs::create_dir_all(&macos).unwrap();
+        std::fs::copy(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/../minimal-importer-bundle/resources/Info.plist.xml"
+            ),
+            bundle.join("Contents/Info.plist"),
+        )
+        .unwrap();
+        let status = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".to_owned()))
+            .args(["-shared", "-fPIC", "-o"])
+            .arg(macos.join("minimal-importer-bundle"))
+            .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/plugin.c"))
+            .status()
+            .expect("a C compiler to build the fixture plug-in");
+        assert!(status.success());
+        bundle
+    }
+
+    #[test]
+    fn drives_the_com_sequence() {
+        let tmp = tempfile::tempdir().unwrap();
+        let plugin = PlugIn::create(&fixture_bundle(tmp.path())).unwrap();
+        let factory = MetadataImporterPluginFactoryUUID;
+        assert_eq!(plugin.factories_for_type(&kMDImporterTypeID), [factory]);
+        assert!(plugin.factories_for_type(&IUnknownUUID).is_empty());
+        assert!(
+            plugin
+                .instance_create(&factory, &IUnknownUUID)
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(plugin.instance_count(&factory), 0);
+
+        let iunknown = plugin
+            .instance_create(&factory, &kMDImporterTypeID)
+            .unwrap()
+            .unwrap();
+        assert_eq!(plugin.instance_count(&factory), 1);
+        unsafe {
+            assert_eq!(iunknown.ref_count(), 1);
+            let (hres, none) = iunknown.query_interface(&[0; 16]);
+            assert_ne!(hres, S_OK);
+            assert!(none.is_none());
+            let (hres, unknown) = iunknown.query_interface(&IUnknownUUID);
+            assert_eq!((hres, iunknown.ref_count()), (S_OK, 2));
+            assert_eq!(unknown.unwrap().release(), 1);
+            let (hres, mdi) = iunknown.query_interface(&kMDImporterInterfaceID);
+            assert_eq!(hres, S_OK);
+            let mdi = mdi.unwrap();
+            assert_eq!(iunknown.release(), 1);
+
+            let mut attrs = Attributes::new();
+            assert!(mdi.import_data(&mut attrs, "vin.je.great", Path::new("/tmp/x.great")));
+            assert_eq!(
+                attrs.get("kMDItemTitle"),
+                Some(&AttrValue::from("/tmp/x.great"))
+            );
+            assert_eq!(
+                attrs.get("kMDItemKeywords"),
+                Some(&AttrValue::Array(vec!["vin.je.great".into(), 7_i64.into()]))
+            );
+            assert!(!mdi.import_data(&mut attrs, "public.data", Path::new("/tmp/x")));
+            assert_eq!(mdi.release(), 0);
+        }
+        assert_eq!(plugin.instance_count(&factory), 0);
+        assert_eq!(
+            plugin.take_log(),
+            [(
+                minimal_importer::abi::LOG_INFO,
+                "importing /tmp/x.great".to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn narrates_bndltest() {
+        let tmp = tempfile::tempdir().unwrap();
+        let bundle = fixture_bundle(tmp.path());
+        let mut out = Vec::new();
+        let attrs = bndltest(&bundle, "a.great", "vin.je.great", &mut out).unwrap();
+        assert_eq!(attrs.len(), 2);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("factories: [\"D87857F7-B0C0-4C70-9B8F-2E3D8E55198C\"]"));
+        assert!(out.contains("before refCount: 2\n"));
+        assert!(out.contains("after refCount: 1\n"));
+        assert!(out.contains("import_res: 1\n"));
+        assert!(out.contains("build info: none\n"));
+        assert!(out.ends_with("instances: 0\nlog 3: importing a.great\n"));
+        assert_eq!(PlugIn::create(&bundle).unwrap().build_info().unwrap(), None);
+
+        let err = bndltest(&bundle, "a", "public.data", &mut Vec::new()).unwrap_err();
+        assert_eq!(err.to_string(), "ImporterImportData failed");
+        assert!(matches!(
+            PlugIn::create(tmp.path()),
+            Err(Error::Io(_) | Error::Plist(_))
+        ));
+    }
+}
@@ -0,0 +1,136 @@
+// A minimal importer written against minimal_importer::abi, for testing the
+// host without building the real bundle. It follows the Apple sample code's
+// shape: one instance struct, one vtable, QueryInterface handing out the
+// same instance for IUnknown and the importer interface.
+
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct { uint8_t bytes[16]; } CFUUIDBytes;
+typedef struct { const uint8_t *ptr; size_t len; } PortableString;
+
+typedef struct PortableValue {
+    uint32_t kind;
+    int64_t integer;
+    double real;
+    PortableString string;
+    const struct PortableValue *items;
+    size_t count;
+} PortableValue;
+
+typedef struct {
+    void *context;
+    void (*set)(void *context, const PortableString *key, const PortableValue *value);
+} PortableDictionary;
+
+typedef struct {
+    void *context;
+    void (*add_instance_for_factory)(void *context, const CFUUIDBytes *factory);
+    void (*remove_instance_for_factory)(void *context, const CFUUIDBytes *factory);
+    void (*log)(void *context, uint32_t level, const PortableString *message);
+} PortableHost;
+
+typedef struct {
+    void *_reserved;
+    int32_t (*QueryInterface)(void *this, CFUUIDBytes iid, void **out);
+    uint32_t (*AddRef)(void *this);
+    uint32_t (*Release)(void *this);
+    bool (*ImporterImportData)(void *this, void *attributes, const void *uti, const void *path);
+} MDImporterInterface;
+
+typedef struct {
+    const MDImporterInterface *interface;
+    CFUUIDBytes *factory_id;
+    uint32_t ref_count;
+    const PortableHost *host;
+    CFUUIDBytes factory;
+} Plugin;
+
+static const CFUUIDBytes kMDImporterTypeID = {{0x8B, 0x08, 0xC4, 0xBF, 0x41, 0x5B, 0x11, 0xD8,
+                                               0xB3, 0xF9, 0x00, 0x03, 0x93, 0x67, 0x26, 0xFC}};
+static const CFUUIDBytes kMDImporterInterfaceID = {{0x6E, 0xBC, 0x27, 0xC4, 0x89, 0x9C, 0x11,
+                                                    0xD8, 0x84, 0xAE, 0x00, 0x03, 0x93, 0x67,
+                                                    0x26, 0xFC}};
+static const CFUUIDBytes IUnknownUUID = {{0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xC0,
+                                          0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46}};
+static const CFUUIDBytes kFactoryID = {{0xd8, 0x78, 0x57, 0xf7, 0xb0, 0xc0, 0x4c, 0x70, 0x9b,
+                                        0x8f, 0x2e, 0x3d, 0x8e, 0x55, 0x19, 0x8c}};
+
+static bool same(const CFUUIDBytes *a, const CFUUIDBytes *b) {
+    return memcmp(a->bytes, b->bytes, 16) == 0;
+}
+
+static PortableString str(const char *s) {
+    PortableString p = {(const uint8_t *)s, strlen(s)};
+    return p;
+}
+
+static uint32_t add_ref(void *this) {
+    return ++((Plugin *)this)->ref_count;
+}
+
+static uint32_t release(void *this) {
+    Plugin *plugin = this;
+    uint32_t count = --plugin->ref_count;
+    if (count == 0) {
+        plugin->host->remove_instance_for_factory(plugin->host->context, &plugin->factory);
+        free(plugin);
+    }
+    return count;
+}
+
+static int32_t query_interface(void *this, CFUUIDBytes iid, void **out) {
+    if (same(&iid, &kMDImporterInterfaceID) || same(&iid, &IUnknownUUID)) {
+        add_ref(this);
+        *out = this;
+        return 0;
+    }
+    *out = NULL;
+    return 1;
+}
+
+static bool import_data(void *this, void *attributes, const void *uti, const void *path) {
+    Plugin *plugin = this;
+    const PortableString *type = uti;
+    if (type->len != strlen("vin.je.great") || memcmp(type->ptr, "vin.je.great", type->len)) {
+        return false;
+    }
+    PortableDictionary *dict = attributes;
+
+    char message[256] = "importing ";
+    const PortableString *p = path;
+    size_t n = p->len < sizeof message - 11 ? p->len : sizeof message - 11;
+    memcpy(message + 10, p->ptr, n);
+    message[10 + n] = 0;
+    PortableString line = str(message);
+    plugin->host->log(plugin->host->context, 3, &line);
+
+    PortableString key = str("kMDItemTitle");
+    PortableValue title = {.kind = 0, .string = *p};
+    dict->set(dict->context, &key, &title);
+
+    PortableValue items[2] = {{.kind = 0, .string = *type}, {.kind = 1, .integer = 7}};
+    PortableValue keywords = {.kind = 5, .items = items, .count = 2};
+    key = str("kMDItemKeywords");
+    dict->set(dict->context, &key, &keywords);
+    return true;
+}
+
+static const MDImporterInterface vtable = {NULL, query_interface, add_ref, release, import_data};
+
+void *MetadataImporterPluginFactory(void *allocator, void *type_id) {
+    const PortableHost *host = allocator;
+    if (!same(type_id, &kMDImporterTypeID)) {
+        return NULL;
+    }
+    Plugin *plugin = calloc(1, sizeof *plugin);
+    plugin->interface = &vtable;
+    plugin->factory = kFactoryID;
+    plugin->factory_id = &plugin->factory;
+    plugin->ref_count = 1;
+    plugin->host = host;
+    host->add_instance_for_factory(host->context, &plugin->factory);
+    return plugin;
+}
@@ -0,0 +1,129 @@
+//! Linker driver for the importer crate's Apple builds.
+//!
+//! rustc always links a cdylib with `-dynamiclib`, but Spotlight importers
+//! have to be `MH_BUNDLE`s. This reads `[package.metadata.link]` from the
+//! crate being linked (see [`rewrite::LinkConfig`]), rewrites the arguments
+//! and runs `cc`. Crates without the table link exactly as they would have.
+//!
+//! The driver is built from this workspace, so it can't be the linker for
+//! every Apple target: nothing could be linked before it existed.
+//! `minimal-importer-util bundle` builds it with the system linker, then
+//! passes it to the importer crate's link only, as in:
+//!
+//! ```text
+//! cargo build --release -p minimal-importer-ld
+//! cargo rustc --release -p minimal-importer-bundle --lib -- \
+//!     -C linker="$PWD/target/release/minimal-importer-ld"
+//! ```
+//!
+//! `MINIMAL_IMPORTER_LD_CC` overrides the real linker and
+//! `MINIMAL_IMPORTER_LD_LOG` names a file to append each link line to, on
+//! top of the copy printed to stderr.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+
+mod rewrite;
+
+use rewrite::LinkConfig;
+
+const CC_VAR: &str = "MINIMAL_IMPORTER_LD_CC";
+const LOG_VAR: &str = "MINIMAL_IMPORTER_LD_LOG";
+
+/// Replaces `@file` arguments with their contents, returning the file names
+/// so the rewritten arguments can go back into one.
+fn expand_response_files(args: Vec<OsString>) -> Result<(Vec<OsString>, Vec<PathBuf>), String> {
+    let mut expanded = Vec::with_capacity(args.len());
+    let mut files = Vec::new();
+    for arg in args {
+        match arg.to_str().and_then(|a| a.strip_prefix('@')) {
+            Some(path) => {
+                let contents = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+                expanded.extend(rewrite::parse_response_file(&contents));
+                files.push(PathBuf::from(path));
+            }
+            None => expanded.push(arg),
+        }
+    }
+    Ok((expanded, files))
+}
+
+fn log(line: &str) -> Result<(), String> {
+    eprintln!("minimal-importer-ld: {line}");
+    if let Some(path) = env::var_os(LOG_VAR) {
+        let mut log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("{}: {e}", Path::new(&path).display()))?;
+        writeln!(log, "{line}").map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Writes the exported symbols list for `plist` next to the link output.
+fn generate_exports(plist: &Path, args: &[OsString]) -> Result<PathBuf, String> {
+    let symbols = plist::Value::from_file(plist)
+        .map(|plist| rewrite::factory_symbols(&plist))
+        .map_err(|e| format!("{}: {e}", plist.display()))?;
+    if symbols.is_empty() {
+        return Err(format!("{} names no CFPlugInFactories", plist.display()));
+    }
+    let output = rewrite::output(args).ok_or("link line has no -o")?;
+    let mut path = output.as_os_str().to_owned();
+    path.push(".exports");
+    let path = PathBuf::from(path);
+    let contents: String = symbols.iter().map(|s| format!("{s}\n")).collect();
+    fs::write(&path, contents).map_err(|e| format!("{}: {e}", path.display()))?;
+    Ok(path)
+}
+
+fn run() -> Result<ExitCode, String> {
+    let mut config = match env::var_os("CARGO_MANIFEST_DIR") {
+        Some(dir) => LinkConfig::load(Path::new(&dir))?,
+        None => LinkConfig::default(),
+    };
+    let (args, response_files) = expand_response_files(env::args_os().skip(1).collect())?;
+    if let Some(plist) = config.exports_from_plist.take()
+        && rewrite::is_dylib_link(&args)
+    {
+        config.exported_symbols_list = Some(generate_exports(&plist, &args)?);
+    }
+    let args = rewrite::rewrite(args, &config);
+    let cc = env::var_os(CC_VAR).unwrap_or_else(|| "cc".into());
+    log(&rewrite::command_line(&cc, &args))?;
+
+    // rustc only uses a response file when the line is too long to pass
+    // directly, so keep using one.
+    let contents = rewrite::write_response_file(&args);
+    let args = match (response_files.first(), contents) {
+        (Some(original), Some(contents)) => {
+            let path = original.with_extension("rewritten");
+            fs::write(&path, contents).map_err(|e| format!("{}: {e}", path.display()))?;
+            let mut arg = OsString::from("@");
+            arg.push(&path);
+            vec![arg]
+        }
+        _ => args,
+    };
+    let status = Command::new(&cc)
+        .args(&args)
+        .status()
+        .map_err(|e| format!("running {}: {e}", Path::new(&cc).display()))?;
+    Ok(match status.code() {
+        Some(0) => ExitCode::SUCCESS,
+        Some(code) => ExitCode::from(u8::try_from(code).unwrap_or(1)),
+        None => ExitCode::FAILURE,
+    })
+}
+
+fn main() -> ExitCode {
+    run().unwrap_or_else(|err| {
+        eprintln!("minimal-importer-ld: error: {err}");
+        ExitCode::FAILURE
+    })
+}
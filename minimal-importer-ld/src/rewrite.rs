@@ -0,0 +1,396 @@
+//! Turning rustc's link line into the one a crate asked for.
+//!
+//! Everything here is pure so it can be tested without a linker.
+
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+/// The `[package.metadata.link]` table of a crate's `Cargo.toml`.
+///
+/// Paths are relative to the crate directory. Nothing applies to links that
+/// don't produce a dylib, such as the crate's build script.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LinkConfig {
+    /// Link `-bundle` instead of `-dynamiclib`, as CFPlugIn expects.
+    #[serde(default)]
+    pub bundle: bool,
+    /// Executable the bundle is loaded into, to resolve symbols against.
+    pub bundle_loader: Option<PathBuf>,
+    /// File listing the only symbols the image may export.
+    pub exported_symbols_list: Option<PathBuf>,
+    /// `Info.plist` whose `CFPlugInFactories` functions are the only symbols
+    /// the image may export; the list is generated next to the output.
+    pub exports_from_plist: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    package: Package,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    metadata: Option<Metadata>,
+}
+
+#[derive(Deserialize)]
+struct Metadata {
+    link: Option<LinkConfig>,
+}
+
+impl LinkConfig {
+    /// Reads the config of the crate in `manifest_dir`; crates without a
+    /// `[package.metadata.link]` table get the default, which changes nothing.
+    pub fn load(manifest_dir: &Path) -> Result<LinkConfig, String> {
+        let path = manifest_dir.join("Cargo.toml");
+        let manifest = fs::read_to_string(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+        LinkConfig::parse(&manifest, manifest_dir).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    pub fn parse(manifest: &str, manifest_dir: &Path) -> Result<LinkConfig, String> {
+        let manifest: Manifest = toml::from_str(manifest).map_err(|e| e.to_string())?;
+        let mut config = manifest
+            .package
+            .metadata
+            .and_then(|m| m.link)
+            .unwrap_or_default();
+        if config.bundle_loader.is_some() && !config.bundle {
+            return Err("bundle-loader needs bundle = true".to_owned());
+        }
+        if config.exported_symbols_list.is_some() && config.exports_from_plist.is_some() {
+            return Err(
+                "exported-symbols-list and exports-from-plist can't both be set".to_owned(),
+            );
+        }
+        for path in [
+            &mut config.bundle_loader,
+            &mut config.exported_symbols_list,
+            &mut config.exports_from_plist,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *path = manifest_dir.join(&*path);
+        }
+        Ok(config)
+    }
+}
+
+/// Appends `flag value` as linker arguments, via `-Xlinker` so the value can
+/// hold commas, which `-Wl,` would split on.
+fn push_linker_arg(args: &mut Vec<OsString>, flag: &str, value: &Path) {
+    for arg in [OsStr::new(flag), value.as_os_str()] {
+        args.push("-Xlinker".into());
+        args.push(arg.to_owned());
+    }
+}
+
+/// The `CFPlugInFactories` function names in `plist`, as linker symbols.
+pub fn factory_symbols(plist: &plist::Value) -> Vec<String> {
+    let mut symbols: Vec<String> = plist
+        .as_dictionary()
+        .and_then(|d| d.get("CFPlugInFactories"))
+        .and_then(plist::Value::as_dictionary)
+        .into_iter()
+        .flat_map(|factories| factories.values())
+        .filter_map(plist::Value::as_string)
+        .map(|name| format!("_{name}"))
+        .collect();
+    symbols.sort();
+    symbols.dedup();
+    symbols
+}
+
+pub fn is_dylib_link(args: &[OsString]) -> bool {
+    args.iter().any(|a| a == "-dynamiclib")
+}
+
+/// The `-o` argument.
+pub fn output(args: &[OsString]) -> Option<&Path> {
+    let i = args.iter().position(|a| a == "-o")?;
+    args.get(i + 1).map(Path::new)
+}
+
+/// Rewrites the arguments rustc passes to `cc`. Only dylib links change.
+///
+/// A configured exported symbols list replaces the one rustc passes, which
+/// lists every `#[no_mangle]` function; ld64 would export the union.
+pub fn rewrite(args: Vec<OsString>, config: &LinkConfig) -> Vec<OsString> {
+    if !is_dylib_link(&args) {
+        return args;
+    }
+    let mut out = Vec::with_capacity(args.len() + 4);
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if config.bundle {
+            if arg == "-dynamiclib" {
+                out.push("-bundle".into());
+                continue;
+            }
+            // ld64 refuses an install name for anything but a dylib.
+            if arg == "-install_name" {
+                args.next();
+                continue;
+            }
+            if arg
+                .to_str()
+                .is_some_and(|a| a.starts_with("-Wl,-install_name,"))
+            {
+                continue;
+            }
+        }
+        if config.exported_symbols_list.is_some() {
+            if arg == "-exported_symbols_list" {
+                args.next();
+                continue;
+            }
+            if arg
+                .to_str()
+                .is_some_and(|a| a.starts_with("-Wl,-exported_symbols_list,"))
+            {
+                continue;
+            }
+        }
+        out.push(arg);
+    }
+    if let Some(loader) = &config.bundle_loader {
+        push_linker_arg(&mut out, "-bundle_loader", loader);
+    }
+    if let Some(list) = &config.exported_symbols_list {
+        push_linker_arg(&mut out, "-exported_symbols_list", list);
+    }
+    out
+}
+
+/// Splits a gcc-style response file, one argument per line with `\`
+/// escaping the next character, the way rustc writes them.
+pub fn parse_response_file(contents: &str) -> Vec<OsString> {
+    contents
+        .lines()
+        .map(|line| {
+            let mut arg = String::with_capacity(line.len());
+            let mut chars = line.chars();
+            while let Some(c) = chars.next() {
+                arg.push(if c == '\\' {
+                    chars.next().unwrap_or(c)
+                } else {
+                    c
+                });
+            }
+            arg.into()
+        })
+        .collect()
+}
+
+/// The inverse of [`parse_response_file`], or `None` if an argument isn't
+/// UTF-8 or has a newline and so can't be written to one.
+pub fn write_response_file(args: &[OsString]) -> Option<String> {
+    let mut contents = String::new();
+    for arg in args {
+        let arg = arg.to_str().filter(|a| !a.contains('\n'))?;
+        for c in arg.chars() {
+            if c == '\\' || c == ' ' {
+                contents.push('\\');
+            }
+            contents.push(c);
+        }
+        contents.push('\n');
+    }
+    Some(contents)
+}
+
+/// Quotes `arg` for a POSIX shell, leaving plain words alone.
+pub fn shell_quote(arg: &OsStr) -> String {
+    let arg = arg.to_string_lossy();
+    let plain = |c: char| c.is_ascii_alphanumeric() || "@%+=:,./_-".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.into_owned()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// A link line that can be pasted back into a shell.
+pub fn command_line(program: &OsStr, args: &[OsString]) -> String {
+    std::iter::once(program)
+        .chain(args.iter().map(OsString::as_os_str))
+        .map(shell_quote)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    fn bundle() -> LinkConfig {
+        LinkConfig {
+            bundle: true,
+            ..LinkConfig::default()
+        }
+    }
+
+    #[test]
+    fn converts_dylib_links_to_bundles() {
+        let rewritten = rewrite(
+            args(&[
+                "-arch",
+                "arm64",
+                "/tmp/with space/a.o",
+                "-dynamiclib",
+                "-Wl,-install_name,@rpath/liba.dylib",
+                "-o",
+                "liba.dylib",
+            ]),
+            &bundle(),
+        );
+        assert_eq!(
+            rewritten,
+            args(&[
+                "-arch",
+                "arm64",
+                "/tmp/with space/a.o",
+                "-bundle",
+                "-o",
+                "liba.dylib"
+            ])
+        );
+        assert_eq!(
+            rewrite(
+                args(&["-dynamiclib", "-install_name", "x", "a.o"]),
+                &bundle()
+            ),
+            args(&["-bundle", "a.o"])
+        );
+    }
+
+    #[test]
+    fn leaves_other_links_alone() {
+        let config = LinkConfig {
+            bundle: true,
+            bundle_loader: Some("/usr/bin/mdimport".into()),
+            exported_symbols_list: Some("syms.txt".into()),
+            exports_from_plist: None,
+        };
+        let build_script = args(&["a.o", "-o", "build_script_build"]);
+        assert_eq!(rewrite(build_script.clone(), &config), build_script);
+        let dylib = args(&["-dynamiclib", "a.o"]);
+        assert_eq!(rewrite(dylib.clone(), &LinkConfig::default()), dylib);
+    }
+
+    #[test]
+    fn injects_loader_and_symbol_list() {
+        let config = LinkConfig {
+            bundle: true,
+            bundle_loader: Some("/Applications/My App.app/Contents/MacOS/app".into()),
+            exported_symbols_list: Some("/src/a,b/syms.txt".into()),
+            exports_from_plist: None,
+        };
+        assert_eq!(
+            rewrite(
+                args(&[
+                    "-dynamiclib",
+                    "a.o",
+                    "-Wl,-exported_symbols_list,/tmp/rustc/list"
+                ]),
+                &config
+            ),
+            args(&[
+                "-bundle",
+                "a.o",
+                "-Xlinker",
+                "-bundle_loader",
+                "-Xlinker",
+                "/Applications/My App.app/Contents/MacOS/app",
+                "-Xlinker",
+                "-exported_symbols_list",
+                "-Xlinker",
+                "/src/a,b/syms.txt",
+            ])
+        );
+    }
+
+    #[test]
+    fn reads_config_from_manifest() {
+        let dir = Path::new("/src/crate");
+        let config = LinkConfig::parse(
+            "[package]\nname = \"x\"\n\n[package.metadata.link]\nbundle = true\n\
+             exported-symbols-list = \"exports.txt\"\n",
+            dir,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            LinkConfig {
+                bundle: true,
+                bundle_loader: None,
+                exported_symbols_list: Some(dir.join("exports.txt")),
+                exports_from_plist: None,
+            }
+        );
+        assert_eq!(
+            LinkConfig::parse("[package]\nname = \"x\"\n", dir).unwrap(),
+            LinkConfig::default()
+        );
+        assert!(
+            LinkConfig::parse(
+                "[package.metadata.link]\nbundle-loader = \"/bin/app\"\n",
+                dir
+            )
+            .is_err()
+        );
+        assert!(LinkConfig::parse("[package.metadata.link]\nbundel = true\n", dir).is_err());
+        assert!(
+            LinkConfig::parse(
+                "[package.metadata.link]\nexported-symbols-list = \"a\"\n\
+                 exports-from-plist = \"b\"\n",
+                dir
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn exports_come_from_the_plist() {
+        let plist = plist::Value::from_reader_xml(
+            &include_bytes!("../../minimal-importer-bundle/resources/Info.plist.xml")[..],
+        )
+        .unwrap();
+        assert_eq!(factory_symbols(&plist), ["_MetadataImporterPluginFactory"]);
+        assert!(factory_symbols(&plist::Value::Boolean(true)).is_empty());
+        let link = args(&["-dynamiclib", "a.o", "-o", "/target/liba.dylib"]);
+        assert!(is_dylib_link(&link));
+        assert_eq!(output(&link), Some(Path::new("/target/liba.dylib")));
+    }
+
+    #[test]
+    fn response_files_round_trip() {
+        let original = args(&["-dynamiclib", "/tmp/with space/a.o", r"C:\odd\path", ""]);
+        let contents = write_response_file(&original).unwrap();
+        assert_eq!(
+            contents,
+            "-dynamiclib\n/tmp/with\\ space/a.o\nC:\\\\odd\\\\path\n\n"
+        );
+        assert_eq!(parse_response_file(&contents), original);
+        assert_eq!(write_response_file(&args(&["a\nb"])), None);
+    }
+
+    #[test]
+    fn quotes_for_the_shell() {
+        assert_eq!(
+            command_line(
+                OsStr::new("cc"),
+                &args(&["-o", "liba.dylib", "with space", "it's", ""])
+            ),
+            r"cc -o liba.dylib 'with space' 'it'\''s' ''"
+        );
+    }
+}
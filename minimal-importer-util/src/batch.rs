@@ -0,0 +1,470 @@
+//! Importing every file under a directory, the way `mdimport -r` would.
+//!
+//! Each import runs on its own thread so a panic is caught and reported
+//! rather than taking the batch down. A timed-out import can't be stopped;
+//! its thread is abandoned and keeps running until the process exits.
+
+use std::any::Any;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use minimal_importer::open::{self, Rejection};
+use minimal_importer::{Attributes, registry};
+use serde_json::json;
+
+use crate::{Result, uti};
+
+/// How a batch is run.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// Number of imports in flight at once.
+    pub workers: usize,
+    /// Longest a single import may take, or `None` to wait forever.
+    pub timeout: Option<Duration>,
+    /// How each file is opened.
+    pub open: open::Options,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            workers: thread::available_parallelism().map_or(1, |n| n.get()),
+            timeout: Some(Duration::from_secs(30)),
+            open: open::Options::default(),
+        }
+    }
+}
+
+/// What happened to one file.
+#[derive(Debug)]
+pub enum Outcome {
+    Imported(Attributes),
+    /// Not a regular file, so never opened for reading.
+    Refused(Rejection),
+    Failed(String),
+    Panicked(String),
+    TimedOut,
+}
+
+impl Outcome {
+    /// The name used for this outcome in reports.
+    pub fn status(&self) -> &'static str {
+        match self {
+            Outcome::Imported(_) => "imported",
+            Outcome::Refused(_) => "refused",
+            Outcome::Failed(_) => "failed",
+            Outcome::Panicked(_) => "panicked",
+            Outcome::TimedOut => "timed_out",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub uti: &'static str,
+    pub outcome: Outcome,
+    pub duration: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Files with a handler, and whatever the walk couldn't read, in path
+    /// order.
+    pub files: Vec<FileReport>,
+    /// Files no handler is registered for.
+    pub skipped: Vec<PathBuf>,
+    pub elapsed: Duration,
+}
+
+impl Report {
+    /// Number of files with the given [`Outcome::status`].
+    pub fn count(&self, status: &str) -> usize {
+        self.files
+            .iter()
+            .filter(|f| f.outcome.status() == status)
+            .count()
+    }
+
+    /// True if every file with a handler was imported.
+    pub fn is_success(&self) -> bool {
+        self.count("imported") == self.files.len()
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let files: Vec<_> = self
+            .files
+            .iter()
+            .map(|f| {
+                let mut entry = json!({
+                    "path": f.path.to_string_lossy(),
+                    "uti": f.uti,
+                    "status": f.outcome.status(),
+                    "duration_ms": millis(f.duration),
+                });
+                match &f.outcome {
+                    Outcome::Imported(attrs) => entry["attributes"] = attrs.len().into(),
+                    Outcome::Refused(rejection) => {
+                        entry["reason"] = rejection.code().into();
+                        entry["error"] = rejection.to_string().into();
+                    }
+                    Outcome::Failed(e) | Outcome::Panicked(e) => entry["error"] = e.as_str().into(),
+                    Outcome::TimedOut => {}
+                }
+                entry
+            })
+            .collect();
+        json!({
+            "summary": {
+                "files": self.files.len(),
+                "imported": self.count("imported"),
+                "refused": self.count("refused"),
+                "failed": self.count("failed"),
+                "panicked": self.count("panicked"),
+                "timed_out": self.count("timed_out"),
+                "skipped": self.skipped.len(),
+                "elapsed_ms": millis(self.elapsed),
+            },
+            "files": files,
+            "skipped": self.skipped.iter().map(|p| p.to_string_lossy()).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Milliseconds with microsecond precision, for reports.
+pub fn millis(d: Duration) -> f64 {
+    d.as_micros() as f64 / 1000.0
+}
+
+/// What [`walk`] found under a directory.
+#[derive(Debug, Default)]
+pub struct Walk {
+    /// Every file, sorted.
+    pub files: Vec<PathBuf>,
+    /// Directories and entries that couldn't be read, and why.
+    pub errors: Vec<(PathBuf, io::Error)>,
+}
+
+impl Walk {
+    /// [`Walk::errors`] as failed files, so a report says what was missed.
+    pub fn error_reports(&self) -> Vec<FileReport> {
+        self.errors
+            .iter()
+            .map(|(path, err)| FileReport {
+                path: path.clone(),
+                uti: if path.is_dir() {
+                    "public.folder"
+                } else {
+                    "public.item"
+                },
+                outcome: Outcome::Failed(err.to_string()),
+                duration: Duration::ZERO,
+            })
+            .collect()
+    }
+}
+
+/// Every file under `root`. Symlinks to directories aren't followed. Only
+/// an unreadable `root` is an error; anything below it that can't be read
+/// is noted in [`Walk::errors`] and the walk goes on.
+pub fn walk(root: &Path) -> io::Result<Walk> {
+    let mut walk = Walk::default();
+    let mut dirs = vec![(root.to_owned(), fs::read_dir(root)?)];
+    while let Some((dir, entries)) = dirs.pop() {
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    walk.errors.push((dir.clone(), err));
+                    continue;
+                }
+            };
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => match fs::read_dir(&path) {
+                    Ok(entries) => dirs.push((path, entries)),
+                    Err(err) => walk.errors.push((path, err)),
+                },
+                Ok(_) => walk.files.push(path),
+                Err(err) => walk.errors.push((path, err)),
+            }
+        }
+    }
+    walk.files.sort();
+    walk.errors.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(walk)
+}
+
+/// Imports the file at `path` as `uti` into `attrs`, opening it with the
+/// options given.
+pub type ImportFn = fn(&mut Attributes, &str, &Path, open::Options) -> io::Result<()>;
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(s) => *s,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(s) => (*s).to_owned(),
+            Err(_) => "panicked with a non-string payload".to_owned(),
+        },
+    }
+}
+
+fn import_one(path: &Path, uti: &'static str, options: &Options, import: ImportFn) -> FileReport {
+    let (timeout, open) = (options.timeout, options.open);
+    let start = Instant::now();
+    let (tx, rx) = mpsc::channel();
+    let owned = path.to_owned();
+    let spawned = thread::Builder::new()
+        .name(format!("import {}", path.display()))
+        .spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                let mut attrs = Attributes::new();
+                import(&mut attrs, uti, &owned, open).map(|()| attrs)
+            }));
+            // The batch stops listening once the import times out.
+            let _ = tx.send(result);
+        });
+    let received = match spawned {
+        Err(e) => Ok(Ok(Err(e))),
+        Ok(_) => match timeout {
+            Some(timeout) => rx.recv_timeout(timeout),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        },
+    };
+    let outcome = match received {
+        Ok(Ok(Ok(attrs))) => Outcome::Imported(attrs),
+        Ok(Ok(Err(e))) => match open::rejection(&e) {
+            Some(rejection) => Outcome::Refused(rejection),
+            None => Outcome::Failed(e.to_string()),
+        },
+        Ok(Err(payload)) => Outcome::Panicked(panic_message(payload)),
+        Err(RecvTimeoutError::Timeout) => Outcome::TimedOut,
+        Err(RecvTimeoutError::Disconnected) => {
+            Outcome::Panicked("import thread exited without a result".to_owned())
+        }
+    };
+    FileReport {
+        path: path.to_owned(),
+        uti,
+        outcome,
+        duration: start.elapsed(),
+    }
+}
+
+/// Imports `files`, each as the UTI paired with it, with `import`.
+pub fn import_files(
+    files: Vec<(PathBuf, &'static str)>,
+    options: &Options,
+    import: ImportFn,
+) -> Vec<FileReport> {
+    let count = files.len();
+    let queue = Mutex::new(files.into_iter().enumerate());
+    let done = Mutex::new(Vec::with_capacity(count));
+    thread::scope(|s| {
+        for _ in 0..options.workers.clamp(1, count.max(1)) {
+            s.spawn(|| {
+                loop {
+                    let Some((i, (path, uti))) = queue.lock().unwrap().next() else {
+                        break;
+                    };
+                    let report = import_one(&path, uti, options, import);
+                    done.lock().unwrap().push((i, report));
+                }
+            });
+        }
+    });
+    let mut done = done.into_inner().unwrap();
+    done.sort_by_key(|&(i, _)| i);
+    done.into_iter().map(|(_, report)| report).collect()
+}
+
+/// Imports every file under `root` that a registered handler claims, going
+/// by the type [`uti::database`] gives each file.
+pub fn run(root: &Path, options: &Options) -> Result<Report> {
+    let start = Instant::now();
+    let mut matched = Vec::new();
+    let mut skipped = Vec::new();
+    let walk = walk(root)?;
+    for path in &walk.files {
+        match registry::handler_for_path(uti::database(), path) {
+            Some(handler) => matched.push((path.clone(), handler.uti)),
+            None => skipped.push(path.clone()),
+        }
+    }
+    let mut files = import_files(matched, options, minimal_importer::import_file_with);
+    files.extend(walk.error_reports());
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(Report {
+        files,
+        skipped,
+        elapsed: start.elapsed(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corpus() -> tempfile::TempDir {
+        let tmp = tempfile::tempdir().unwrap();
+        let nested = tmp.path().join("nested/deeper");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(
+            tmp.path().join("test.great"),
+            include_str!("../../test.great"),
+        )
+        .unwrap();
+        fs::write(nested.join("notes.TXT"), "Some plain notes.\n").unwrap();
+        fs::write(nested.join("image.png"), [0x89, b'P', b'N', b'G']).unwrap();
+        tmp
+    }
+
+    #[test]
+    fn imports_a_tree() {
+        let tmp = corpus();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("missing", tmp.path().join("dangling.great")).unwrap();
+        let report = run(
+            tmp.path(),
+            &Options {
+                workers: 2,
+                ..Options::default()
+            },
+        )
+        .unwrap();
+        let names: Vec<_> = report
+            .files
+            .iter()
+            .map(|f| (f.path.strip_prefix(tmp.path()).unwrap(), f.uti))
+            .collect();
+        let mut expected = vec![
+            (Path::new("nested/deeper/notes.TXT"), "public.plain-text"),
+            (Path::new("test.great"), "vin.je.great"),
+        ];
+        #[cfg(unix)]
+        expected.insert(0, (Path::new("dangling.great"), "vin.je.great"));
+        assert_eq!(names, expected);
+        assert_eq!(report.skipped, [tmp.path().join("nested/deeper/image.png")]);
+        assert_eq!(report.count("imported"), 2);
+        #[cfg(unix)]
+        assert!(matches!(report.files[0].outcome, Outcome::Failed(_)));
+
+        let json = report.to_json();
+        assert_eq!(json["summary"]["imported"], 2);
+        assert_eq!(json["summary"]["skipped"], 1);
+        assert_eq!(json["files"].as_array().unwrap().len(), report.files.len());
+    }
+
+    fn misbehave(
+        attrs: &mut Attributes,
+        _uti: &str,
+        path: &Path,
+        _: open::Options,
+    ) -> io::Result<()> {
+        match path.file_stem().and_then(|s| s.to_str()) {
+            Some("panic") => panic!("importer bug"),
+            Some("slow") => thread::sleep(Duration::from_secs(5)),
+            Some("error") => return Err(io::Error::other("bad file")),
+            _ => attrs.insert("kMDItemDescription", "fine"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn reports_panics_timeouts_and_errors() {
+        let files = ["ok", "panic", "slow", "error"]
+            .map(|name| (PathBuf::from(format!("{name}.great")), "vin.je.great"))
+            .to_vec();
+        let options = Options {
+            workers: 4,
+            timeout: Some(Duration::from_millis(200)),
+            ..Options::default()
+        };
+        let reports = import_files(files, &options, misbehave);
+        let statuses: Vec<_> = reports.iter().map(|r| r.outcome.status()).collect();
+        assert_eq!(statuses, ["imported", "panicked", "timed_out", "failed"]);
+        assert!(matches!(&reports[1].outcome, Outcome::Panicked(m) if m == "importer bug"));
+        assert!(reports[2].duration < Duration::from_secs(5));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn refuses_special_files() {
+        let tmp = corpus();
+        let _socket = std::os::unix::net::UnixListener::bind(tmp.path().join("socket.great"));
+        std::os::unix::fs::symlink("test.great", tmp.path().join("link.great")).unwrap();
+
+        let report = run(tmp.path(), &Options::default()).unwrap();
+        let statuses: Vec<_> = report
+            .files
+            .iter()
+            .map(|f| (f.path.strip_prefix(tmp.path()).unwrap(), f.outcome.status()))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                (Path::new("link.great"), "imported"),
+                (Path::new("nested/deeper/notes.TXT"), "imported"),
+                (Path::new("socket.great"), "refused"),
+                (Path::new("test.great"), "imported"),
+            ]
+        );
+        let json = report.to_json();
+        assert_eq!(json["summary"]["refused"], 1);
+        assert_eq!(json["files"][2]["reason"], "socket");
+
+        let options = Options {
+            open: open::Options {
+                follow_symlinks: false,
+            },
+            ..Options::default()
+        };
+        let report = run(tmp.path(), &options).unwrap();
+        assert!(matches!(
+            report.files[0].outcome,
+            Outcome::Refused(Rejection::Symlink)
+        ));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn reports_unreadable_directories_and_goes_on() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = corpus();
+        let locked = tmp.path().join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::write(locked.join("hidden.great"), "hidden\n").unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        if fs::read_dir(&locked).is_ok() {
+            // Running as root, which reads anything.
+            return;
+        }
+
+        let report = run(tmp.path(), &Options::default());
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        let report = report.unwrap();
+        let statuses: Vec<_> = report
+            .files
+            .iter()
+            .map(|f| (f.path.strip_prefix(tmp.path()).unwrap(), f.outcome.status()))
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                (Path::new("locked"), "failed"),
+                (Path::new("nested/deeper/notes.TXT"), "imported"),
+                (Path::new("test.great"), "imported"),
+            ]
+        );
+        assert_eq!(report.files[0].uti, "public.folder");
+        assert!(!report.is_success());
+        assert!(run(&tmp.path().join("missing"), &Options::default()).is_err());
+    }
+}
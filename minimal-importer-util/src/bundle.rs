@@ -0,0 +1,508 @@
+//! Assembling `.mdimporter` and `.app` bundle directories from build output.
+//!
+//! Spotlight only loads importers laid out as
+//!
+//! ```text
+//! Foo.mdimporter/Contents/Info.plist
+//! Foo.mdimporter/Contents/MacOS/<CFBundleExecutable>
+//! Foo.mdimporter/Contents/Resources/schema.xml
+//! Foo.mdimporter/Contents/Resources/<region>.lproj/schema.strings
+//! ```
+//!
+//! and ships them inside the app that declares their UTIs, under
+//! `Foo.app/Contents/Library/Spotlight/`. Everything here is plain file
+//! copying, so it works the same on Linux.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use minimal_importer::uti::Database;
+use minimal_importer::{keys, plugin};
+use plist::Value;
+
+use crate::info_plist::{self, BundleInfo, PACKAGE_TYPE_APP, PACKAGE_TYPE_BUNDLE};
+use crate::{Error, Result};
+
+pub const IMPORTER_EXTENSION: &str = "mdimporter";
+
+pub const APP_EXTENSION: &str = "app";
+
+/// Where importers live inside an app bundle.
+pub const APP_IMPORTER_DIR: &str = "Contents/Library/Spotlight";
+
+/// Where `cargo build --release` puts the crate's cdylib or executable.
+pub fn default_binary(info: &BundleInfo) -> PathBuf {
+    let release = Path::new("target/release");
+    if info.is_importer() {
+        let lib = info.package_name.replace('-', "_");
+        release.join(format!("lib{lib}.dylib"))
+    } else {
+        release.join(&info.package_name)
+    }
+}
+
+/// Generates `schema.xml`, listing the attributes set for each claimed UTI.
+pub fn schema_xml(content_types: &[String]) -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<schema version="1.0" xmlns="http://www.apple.com/metadata" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="http://www.apple.com/metadata file:///System/Library/Frameworks/CoreServices.framework/Frameworks/Metadata.framework/Resources/MetadataSchema.xsd">
+	<types>
+"#,
+    );
+    for uti in content_types {
+        xml.push_str(&format!("\t\t<type name=\"{uti}\">\n\t\t\t<allattrs>\n"));
+        for key in keys::ALL {
+            xml.push_str(&format!("\t\t\t\t{key}\n"));
+        }
+        xml.push_str("\t\t\t</allattrs>\n\t\t\t<displayattrs>\n");
+        for key in keys::DISPLAYED {
+            xml.push_str(&format!("\t\t\t\t{key}\n"));
+        }
+        xml.push_str("\t\t\t</displayattrs>\n\t\t</type>\n");
+    }
+    xml.push_str("\t</types>\n</schema>\n");
+    xml
+}
+
+/// Generates `schema.strings`. The importer only sets standard `kMDItem*`
+/// attributes, which Spotlight already has names for, so there is nothing to
+/// localize yet.
+pub fn schema_strings() -> String {
+    "/* Localized names of custom attributes declared in schema.xml. */\n".to_owned()
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    Ok(fs::set_permissions(path, fs::Permissions::from_mode(mode))?)
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: u32) -> Result<()> {
+    Ok(())
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<()> {
+    fs::write(path, contents)?;
+    set_mode(path, 0o644)
+}
+
+fn create_dir(path: &Path) -> Result<()> {
+    fs::create_dir_all(path)?;
+    set_mode(path, 0o755)
+}
+
+fn check_binary(binary: &Path) -> Result<()> {
+    if binary.is_file() {
+        Ok(())
+    } else {
+        Err(Error::Invalid(format!(
+            "{} is not a built binary",
+            binary.display()
+        )))
+    }
+}
+
+/// Creates an empty `<out_dir>/<name>.<extension>`, replacing an existing
+/// bundle but nothing else.
+fn replace_bundle_dir(out_dir: &Path, name: &str, extension: &str) -> Result<PathBuf> {
+    let bundle = out_dir.join(format!("{name}.{extension}"));
+    if bundle.exists() {
+        if !bundle.join("Contents/Info.plist").is_file() {
+            return Err(Error::Invalid(format!(
+                "refusing to replace {}, it doesn't look like a bundle",
+                bundle.display()
+            )));
+        }
+        fs::remove_dir_all(&bundle)?;
+    }
+    create_dir(&bundle)?;
+    Ok(bundle)
+}
+
+/// Writes `Contents/Info.plist` and `Contents/MacOS/<executable>`, the part
+/// every bundle has, and returns the `Contents` directory.
+fn write_contents(bundle: &Path, info: &BundleInfo, binary: &Path) -> Result<PathBuf> {
+    let plist = info_plist::to_xml(&info_plist::info_plist(info)?)?;
+    let contents = bundle.join("Contents");
+    let macos = contents.join("MacOS");
+    for dir in [&contents, &macos, &contents.join("Resources")] {
+        create_dir(dir)?;
+    }
+    write_file(&contents.join("Info.plist"), &plist)?;
+    let executable = macos.join(info.executable());
+    fs::copy(binary, &executable)?;
+    set_mode(&executable, 0o755)?;
+    Ok(contents)
+}
+
+/// Builds `<out_dir>/<name>.mdimporter` from the built `binary` and returns
+/// its path. An existing bundle at that path is replaced.
+pub fn assemble_importer(
+    info: &BundleInfo,
+    binary: &Path,
+    out_dir: &Path,
+    name: &str,
+) -> Result<PathBuf> {
+    if !info.is_importer() {
+        return Err(Error::Invalid(format!(
+            "{} is not an importer, its package type is {}",
+            info.package_name, info.bundle.package_type
+        )));
+    }
+    check_binary(binary)?;
+    let bundle = replace_bundle_dir(out_dir, name, IMPORTER_EXTENSION)?;
+    let contents = write_contents(&bundle, info, binary)?;
+    let resources = contents.join("Resources");
+    let lproj = resources.join(format!("{}.lproj", info.bundle.development_region));
+    create_dir(&lproj)?;
+    write_file(
+        &resources.join("schema.xml"),
+        &schema_xml(&info.content_types()),
+    )?;
+    write_file(&lproj.join("schema.strings"), &schema_strings())?;
+
+    validate_importer(&bundle)?;
+    Ok(bundle)
+}
+
+/// Builds `<out_dir>/<name>.app` with the importer nested under
+/// [`APP_IMPORTER_DIR`] as `<importer_name>.mdimporter`.
+///
+/// Fails before touching the filesystem if the app doesn't declare every
+/// UTI the importer claims.
+pub fn assemble_app(
+    app: &BundleInfo,
+    app_binary: &Path,
+    importer: &BundleInfo,
+    importer_binary: &Path,
+    out_dir: &Path,
+    name: &str,
+    importer_name: &str,
+) -> Result<PathBuf> {
+    if app.bundle.package_type != PACKAGE_TYPE_APP {
+        return Err(Error::Invalid(format!(
+            "{} is not an app, its package type is {}",
+            app.package_name, app.bundle.package_type
+        )));
+    }
+    let uncovered = uncovered_utis(
+        &info_plist::info_plist(app)?,
+        &info_plist::info_plist(importer)?,
+    );
+    if !uncovered.is_empty() {
+        return Err(Error::Invalid(format!(
+            "{} claims {} but {} doesn't declare them",
+            importer.package_name,
+            uncovered.join(", "),
+            app.package_name
+        )));
+    }
+    check_binary(app_binary)?;
+    check_binary(importer_binary)?;
+    let bundle = replace_bundle_dir(out_dir, name, APP_EXTENSION)?;
+    write_contents(&bundle, app, app_binary)?;
+    let spotlight = bundle.join(APP_IMPORTER_DIR);
+    create_dir(&bundle.join("Contents/Library"))?;
+    create_dir(&spotlight)?;
+    assemble_importer(importer, importer_binary, &spotlight, importer_name)?;
+
+    validate_app(&bundle)?;
+    Ok(bundle)
+}
+
+fn string_array<'a>(dict: Option<&'a plist::Dictionary>, key: &str) -> Vec<&'a str> {
+    dict.and_then(|d| d.get(key))
+        .and_then(Value::as_array)
+        .map(|values| values.iter().filter_map(Value::as_string).collect())
+        .unwrap_or_default()
+}
+
+/// The UTIs an app declares in `UTExportedTypeDeclarations` and
+/// `UTImportedTypeDeclarations`.
+pub fn declared_utis(app_plist: &Value) -> Vec<&str> {
+    let dict = app_plist.as_dictionary();
+    ["UTExportedTypeDeclarations", "UTImportedTypeDeclarations"]
+        .into_iter()
+        .filter_map(|key| dict?.get(key)?.as_array())
+        .flatten()
+        .filter_map(|decl| decl.as_dictionary()?.get("UTTypeIdentifier")?.as_string())
+        .collect()
+}
+
+/// The UTIs an importer claims in its `CFBundleDocumentTypes`.
+pub fn claimed_utis(importer_plist: &Value) -> Vec<&str> {
+    importer_plist
+        .as_dictionary()
+        .and_then(|d| d.get("CFBundleDocumentTypes"))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .flat_map(|doc| string_array(doc.as_dictionary(), "LSItemContentTypes"))
+        .collect()
+}
+
+/// UTIs the importer claims that neither the app nor the system declares.
+pub fn uncovered_utis(app_plist: &Value, importer_plist: &Value) -> Vec<String> {
+    let declared = declared_utis(app_plist);
+    let system = Database::with_system_types();
+    claimed_utis(importer_plist)
+        .into_iter()
+        .filter(|uti| system.get(uti).is_none())
+        .filter(|uti| !declared.contains(uti))
+        .map(str::to_owned)
+        .collect()
+}
+
+pub(crate) fn plist_string<'a>(plist: &'a Value, key: &str) -> Option<&'a str> {
+    plist.as_dictionary()?.get(key)?.as_string()
+}
+
+fn check_executable(contents: &Path, plist: &Value, problems: &mut Vec<String>) {
+    match plist_string(plist, "CFBundleExecutable") {
+        None | Some("") => problems.push("CFBundleExecutable is missing".to_owned()),
+        Some(exe) => {
+            let path = contents.join("MacOS").join(exe);
+            match fs::metadata(&path) {
+                Ok(meta) if meta.is_file() => {
+                    #[cfg(unix)]
+                    {
+                        use std::os::unix::fs::PermissionsExt;
+                        if meta.permissions().mode() & 0o111 == 0 {
+                            problems.push(format!("MacOS/{exe} is not executable"));
+                        }
+                    }
+                }
+                _ => problems.push(format!("MacOS/{exe} is missing")),
+            }
+        }
+    }
+}
+
+pub(crate) fn report(bundle: &Path, problems: Vec<String>) -> Result<()> {
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Invalid(format!(
+            "{}: {}",
+            bundle.display(),
+            problems.join("; ")
+        )))
+    }
+}
+
+/// Checks that `bundle` has the layout and `Info.plist` Spotlight needs,
+/// reporting every problem found.
+pub fn validate_importer(bundle: &Path) -> Result<()> {
+    let mut problems = Vec::new();
+    if bundle.extension().and_then(|e| e.to_str()) != Some(IMPORTER_EXTENSION) {
+        problems.push(format!("bundle name doesn't end in .{IMPORTER_EXTENSION}"));
+    }
+    let contents = bundle.join("Contents");
+    let plist = Value::from_file(contents.join("Info.plist"))?;
+
+    if plist_string(&plist, "CFBundlePackageType") != Some(PACKAGE_TYPE_BUNDLE) {
+        problems.push(format!("CFBundlePackageType is not {PACKAGE_TYPE_BUNDLE}"));
+    }
+    check_executable(&contents, &plist, &mut problems);
+
+    let dict = plist.as_dictionary();
+    let factories = dict
+        .and_then(|d| d.get("CFPlugInFactories"))
+        .and_then(Value::as_dictionary);
+    let importer_type = plugin::uuid_string(&plugin::kMDImporterTypeID);
+    let registered: Vec<&str> = dict
+        .and_then(|d| d.get("CFPlugInTypes"))
+        .and_then(Value::as_dictionary)
+        .and_then(|types| types.get(&importer_type))
+        .and_then(Value::as_array)
+        .map(|ids| ids.iter().filter_map(Value::as_string).collect())
+        .unwrap_or_default();
+    match factories {
+        Some(factories) if !factories.is_empty() => {
+            for uuid in factories.keys() {
+                if !registered.contains(&uuid.as_str()) {
+                    problems.push(format!(
+                        "factory {uuid} is not registered for the importer type {importer_type}"
+                    ));
+                }
+            }
+        }
+        _ => problems.push("CFPlugInFactories is empty".to_owned()),
+    }
+    let claims_types = dict
+        .and_then(|d| d.get("CFBundleDocumentTypes"))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_dictionary)
+        .filter_map(|d| d.get("LSItemContentTypes")?.as_array())
+        .any(|types| !types.is_empty());
+    if !claims_types {
+        problems.push("no LSItemContentTypes are claimed".to_owned());
+    }
+    if !contents.join("Resources/schema.xml").is_file() {
+        problems.push("Resources/schema.xml is missing".to_owned());
+    }
+    report(bundle, problems)
+}
+
+/// The bundles under an app's [`APP_IMPORTER_DIR`], sorted by name.
+pub fn nested_importers(app: &Path) -> Result<Vec<PathBuf>> {
+    let mut importers: Vec<PathBuf> = match fs::read_dir(app.join(APP_IMPORTER_DIR)) {
+        Ok(entries) => entries
+            .map(|e| e.map(|e| e.path()))
+            .collect::<std::io::Result<_>>()?,
+        Err(_) => Vec::new(),
+    };
+    importers.sort();
+    Ok(importers)
+}
+
+/// Checks an app bundle and every importer nested in it, including that the
+/// app declares the UTIs each importer claims.
+pub fn validate_app(bundle: &Path) -> Result<()> {
+    let mut problems = Vec::new();
+    if bundle.extension().and_then(|e| e.to_str()) != Some(APP_EXTENSION) {
+        problems.push(format!("bundle name doesn't end in .{APP_EXTENSION}"));
+    }
+    let contents = bundle.join("Contents");
+    let plist = Value::from_file(contents.join("Info.plist"))?;
+    if plist_string(&plist, "CFBundlePackageType") != Some(PACKAGE_TYPE_APP) {
+        problems.push(format!("CFBundlePackageType is not {PACKAGE_TYPE_APP}"));
+    }
+    check_executable(&contents, &plist, &mut problems);
+
+    let importers = nested_importers(bundle)?;
+    if importers.is_empty() {
+        problems.push(format!("{APP_IMPORTER_DIR} has no importers"));
+    }
+    for importer in importers {
+        if let Err(e) = validate_importer(&importer) {
+            problems.push(e.to_string());
+            continue;
+        }
+        let importer_plist = Value::from_file(importer.join("Contents/Info.plist"))?;
+        let uncovered = uncovered_utis(&plist, &importer_plist);
+        if !uncovered.is_empty() {
+            problems.push(format!(
+                "{} claims {} which the app doesn't declare",
+                importer.display(),
+                uncovered.join(", ")
+            ));
+        }
+    }
+    report(bundle, problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::info_plist::read_manifest;
+
+    fn bundle_info() -> BundleInfo {
+        read_manifest(&Path::new(env!("CARGO_MANIFEST_DIR")).join("../minimal-importer-bundle"))
+            .unwrap()
+    }
+
+    fn app_info() -> BundleInfo {
+        read_manifest(&Path::new(env!("CARGO_MANIFEST_DIR")).join("../minimal-importer-app"))
+            .unwrap()
+    }
+
+    fn dummy_binary(dir: &Path) -> PathBuf {
+        let binary = dir.join("libminimal_importer_bundle.dylib");
+        fs::write(&binary, b"not really mach-o").unwrap();
+        binary
+    }
+
+    #[test]
+    fn assembles_and_validates() {
+        let tmp = tempfile::tempdir().unwrap();
+        let binary = dummy_binary(tmp.path());
+        let bundle = assemble_importer(&bundle_info(), &binary, tmp.path(), "Minimal").unwrap();
+        assert_eq!(bundle, tmp.path().join("Minimal.mdimporter"));
+        let contents = bundle.join("Contents");
+        assert_eq!(
+            fs::read(contents.join("MacOS/minimal-importer-bundle")).unwrap(),
+            b"not really mach-o"
+        );
+        let schema = fs::read_to_string(contents.join("Resources/schema.xml")).unwrap();
+        assert!(schema.contains("<type name=\"vin.je.great\">"));
+        assert!(
+            contents
+                .join("Resources/English.lproj/schema.strings")
+                .is_file()
+        );
+
+        // Assembling again replaces the previous bundle.
+        assemble_importer(&bundle_info(), &binary, tmp.path(), "Minimal").unwrap();
+    }
+
+    #[test]
+    fn validation_reports_problems() {
+        let tmp = tempfile::tempdir().unwrap();
+        let binary = dummy_binary(tmp.path());
+        let bundle = assemble_importer(&bundle_info(), &binary, tmp.path(), "Minimal").unwrap();
+        fs::remove_file(bundle.join("Contents/MacOS/minimal-importer-bundle")).unwrap();
+        fs::remove_file(bundle.join("Contents/Resources/schema.xml")).unwrap();
+        let Err(Error::Invalid(msg)) = validate_importer(&bundle) else {
+            panic!("broken bundle validated");
+        };
+        assert!(
+            msg.contains("MacOS/minimal-importer-bundle is missing"),
+            "{msg}"
+        );
+        assert!(msg.contains("schema.xml is missing"), "{msg}");
+    }
+
+    #[test]
+    fn refuses_to_replace_other_directories() {
+        let tmp = tempfile::tempdir().unwrap();
+        let binary = dummy_binary(tmp.path());
+        fs::create_dir(tmp.path().join("Minimal.mdimporter")).unwrap();
+        assert!(assemble_importer(&bundle_info(), &binary, tmp.path(), "Minimal").is_err());
+    }
+
+    #[test]
+    fn assembles_app_with_nested_importer() {
+        let tmp = tempfile::tempdir().unwrap();
+        let binary = dummy_binary(tmp.path());
+        let app = assemble_app(
+            &app_info(),
+            &binary,
+            &bundle_info(),
+            &binary,
+            tmp.path(),
+            "Minimal",
+            "Minimal",
+        )
+        .unwrap();
+        assert!(app.join("Contents/MacOS/minimal-importer-app").is_file());
+        assert!(
+            app.join("Contents/Library/Spotlight/Minimal.mdimporter/Contents/Info.plist")
+                .is_file()
+        );
+    }
+
+    #[test]
+    fn app_must_declare_claimed_utis() {
+        let tmp = tempfile::tempdir().unwrap();
+        let binary = dummy_binary(tmp.path());
+        let mut app = app_info();
+        app.bundle.exported_types.clear();
+        let Err(Error::Invalid(msg)) = assemble_app(
+            &app,
+            &binary,
+            &bundle_info(),
+            &binary,
+            tmp.path(),
+            "Minimal",
+            "Minimal",
+        ) else {
+            panic!("app without the importer's UTIs assembled");
+        };
+        assert!(msg.contains("vin.je.great"), "{msg}");
+        assert!(!tmp.path().join("Minimal.app").exists());
+    }
+}
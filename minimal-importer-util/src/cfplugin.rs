@@ -0,0 +1,135 @@
+//! Loading an importer bundle through the real CFPlugIn, the way `mdworker`
+//! does. Bundles built on macOS take real CoreFoundation objects, so
+//! `minimal-importer-host`'s portable stand-ins can't drive them.
+
+use std::ffi::c_void;
+use std::path::Path;
+use std::ptr;
+
+use minimal_importer::abi::{BuildInfoInterface, CFUUIDBytes, MDImporterInterface, S_OK};
+use minimal_importer::plugin::{kMDImporterTypeID, kMinimalImporterBuildInfoInterfaceID};
+use minimal_importer::{AttrValue, Attributes};
+use objc2_core_foundation::{
+    CFArray, CFBoolean, CFDate, CFMutableDictionary, CFNumber, CFPlugIn, CFPlugInInstance,
+    CFRetained, CFString, CFType, CFURL, CFUUID, kCFAllocatorDefault,
+};
+
+use crate::{Error, Result};
+
+#[rustfmt::skip]
+fn constant_uuid(bytes: [u8; 16]) -> CFRetained<CFUUID> {
+    let [b0, b1, b2, b3, b4, b5, b6, b7, b8, b9, b10, b11, b12, b13, b14, b15] = bytes;
+    CFUUID::constant_uuid_with_bytes(unsafe { kCFAllocatorDefault },
+        b0, b1, b2, b3, b4, b5, b6, b7,
+        b8, b9, b10, b11, b12, b13, b14, b15,
+    ).unwrap()
+}
+
+/// Loads `bundle` with `CFPlugInCreate`, creates an importer instance and
+/// asks it what build it is through its build-info interface. `None` if it
+/// doesn't have one.
+pub fn build_info(bundle: &Path) -> Result<Option<Attributes>> {
+    let invalid = |msg: &str| Error::Invalid(format!("{}: {msg}", bundle.display()));
+    let url = CFURL::from_directory_path(bundle).ok_or_else(|| invalid("not a file URL"))?;
+    let plugin = CFPlugIn::new(None, Some(&url)).ok_or_else(|| invalid("CFPlugInCreate failed"))?;
+
+    let type_id = constant_uuid(kMDImporterTypeID);
+    let factories =
+        CFPlugIn::find_factories_for_plug_in_type_in_plug_in(Some(&type_id), Some(&plugin))
+            // Factories are registered by their CFUUIDs.
+            .map(|factories| unsafe { CFArray::cast_unchecked::<CFUUID>(&factories) }.to_vec())
+            .unwrap_or_default();
+    let [factory] = &factories[..] else {
+        return Err(invalid("expected exactly one importer factory"));
+    };
+
+    let iunknown = CFPlugInInstance::create(None, Some(factory), Some(&type_id));
+    if iunknown.is_null() {
+        return Err(invalid("the factory returned NULL"));
+    }
+    let info = unsafe { copy_build_info(iunknown) };
+    if let Some(release) = unsafe { interface(iunknown) }.release {
+        unsafe { release(iunknown) };
+    }
+    // Only now can `plugin` unload the executable.
+    drop(plugin);
+    Ok(info)
+}
+
+/// The IUnknown-compatible vtable an importer instance starts with.
+///
+/// # Safety
+///
+/// `instance` must be a live importer instance.
+unsafe fn interface<'a>(instance: *mut c_void) -> &'a MDImporterInterface {
+    unsafe { &**(instance as *const *const MDImporterInterface) }
+}
+
+/// `QueryInterface`s `iunknown` for the build-info interface and copies
+/// what it reports.
+///
+/// # Safety
+///
+/// `iunknown` must be a live importer instance.
+unsafe fn copy_build_info(iunknown: *mut c_void) -> Option<Attributes> {
+    let query_interface = unsafe { interface(iunknown) }.query_interface?;
+    let mut info = ptr::null_mut();
+    let iid = CFUUIDBytes {
+        bytes: kMinimalImporterBuildInfoInterfaceID,
+    };
+    let hres = unsafe { query_interface(iunknown, iid, &mut info) };
+    if hres != S_OK || info.is_null() {
+        return None;
+    }
+
+    let vtable = unsafe { &**(info as *const *const BuildInfoInterface) };
+    let dict = CFMutableDictionary::<CFString, CFType>::empty();
+    let copied = match vtable.copy_build_info {
+        Some(copy) => unsafe { copy(info, CFRetained::as_ptr(&dict).as_ptr().cast()) },
+        None => false,
+    };
+    if let Some(release) = vtable.release {
+        unsafe { release(info) };
+    }
+    copied.then(|| attributes(&dict))
+}
+
+fn attributes(dict: &CFMutableDictionary<CFString, CFType>) -> Attributes {
+    let mut attrs = Attributes::new();
+    let (keys, values) = dict.to_vecs();
+    for (key, value) in keys.iter().zip(&values) {
+        if let Some(value) = attr_value(value) {
+            attrs.insert(key.to_string(), value);
+        }
+    }
+    attrs
+}
+
+/// The importer attribute a CoreFoundation value stands for, or `None` for
+/// types importers don't set.
+fn attr_value(value: &CFType) -> Option<AttrValue> {
+    if let Some(string) = value.downcast_ref::<CFString>() {
+        return Some(AttrValue::String(string.to_string()));
+    }
+    if let Some(boolean) = value.downcast_ref::<CFBoolean>() {
+        return Some(AttrValue::Bool(boolean.as_bool()));
+    }
+    if let Some(number) = value.downcast_ref::<CFNumber>() {
+        return if number.is_float_type() {
+            number.as_f64().map(AttrValue::Real)
+        } else {
+            number.as_i64().map(AttrValue::Integer)
+        };
+    }
+    if let Some(date) = value.downcast_ref::<CFDate>() {
+        return date.to_system_time().map(AttrValue::Date);
+    }
+    let array = value.downcast_ref::<CFArray>()?;
+    // Whatever an array holds is a CF object.
+    let values = unsafe { array.cast_unchecked::<CFType>() }.to_vec();
+    values
+        .iter()
+        .map(|value| attr_value(value))
+        .collect::<Option<Vec<_>>>()
+        .map(AttrValue::Array)
+}
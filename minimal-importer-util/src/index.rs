@@ -0,0 +1,525 @@
+//! A local attribute index standing in for Spotlight's, so search
+//! expectations can be checked with [`Query`] where `mdfind` isn't
+//! available.
+//!
+//! The batch importer feeds it. [`Index::refresh`] walks a directory and
+//! only imports files that are new or whose size, modification time or,
+//! optionally, content hash changed since they were indexed, or that an
+//! older importer version indexed. Entries for files that went away are
+//! removed. Attributes are kept in the JSON form [`output::to_json`] prints,
+//! keyed by path as walked, so refresh a tree by the same path each time.
+//! The index is saved as one JSON file.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::{Instant, UNIX_EPOCH};
+
+use minimal_importer::{open, registry};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::batch::{self, Outcome};
+use crate::query::Query;
+use crate::{Error, Result, output, uti};
+
+/// Bumped when the index format changes incompatibly.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Where the util keeps its index unless told otherwise.
+pub const DEFAULT_PATH: &str = "target/attribute-index.json";
+
+/// What a file looked like when it was imported.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stamp {
+    pub size: u64,
+    /// Nanoseconds since the Unix epoch.
+    pub mtime_ns: u64,
+    /// [`content_hash`] of the file, if hashing was asked for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl Stamp {
+    /// Recorded for a file that imported but couldn't be stamped. No file
+    /// looks like it, so the next refresh imports the file again.
+    pub const UNKNOWN: Stamp = Stamp {
+        size: u64::MAX,
+        mtime_ns: u64::MAX,
+        hash: None,
+    };
+
+    /// Stamps `path`, opening it the way an import would, so a file the
+    /// importer refuses is refused here too rather than read.
+    pub fn of(path: &Path, hash: bool, options: open::Options) -> io::Result<Stamp> {
+        let mut file = open::open(path, options)?;
+        let meta = file.metadata()?;
+        let mtime = meta
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let hash = if hash {
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            Some(content_hash(&bytes))
+        } else {
+            None
+        };
+        Ok(Stamp {
+            size: meta.len(),
+            mtime_ns: u64::try_from(mtime.as_nanos()).unwrap_or(u64::MAX),
+            hash,
+        })
+    }
+}
+
+/// 64-bit FNV-1a of `bytes` in hex. It only has to notice edits, not resist
+/// tampering, and unlike `DefaultHasher` it's stable across Rust releases.
+pub fn content_hash(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
+
+/// What the index knows about one file.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    #[serde(flatten)]
+    pub stamp: Stamp,
+    /// The [`minimal_importer::VERSION`] that imported the file.
+    pub importer: String,
+    pub attributes: BTreeMap<String, Value>,
+}
+
+impl Record {
+    /// Whether the file still looks like `stamp` says and the current
+    /// importer indexed it. Without a hash in `stamp`, hashes aren't
+    /// compared.
+    fn is_current(&self, stamp: &Stamp) -> bool {
+        self.importer == minimal_importer::VERSION
+            && self.stamp.size == stamp.size
+            && self.stamp.mtime_ns == stamp.mtime_ns
+            && (stamp.hash.is_none() || self.stamp.hash == stamp.hash)
+    }
+}
+
+/// How [`Index::refresh`] runs.
+#[derive(Clone, Debug, Default)]
+pub struct RefreshOptions {
+    pub batch: batch::Options,
+    /// Also compare content hashes, catching edits that keep the size and
+    /// modification time, at the cost of reading every file.
+    pub hash: bool,
+}
+
+/// What a refresh did to the index, by path.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Changes {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    /// Files deleted, no longer claimed by a handler, or failing to import.
+    pub removed: Vec<String>,
+    /// Unchanged files that weren't imported again.
+    pub skipped: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Index {
+    pub version: u32,
+    pub files: BTreeMap<String, Record>,
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Index {
+            version: FORMAT_VERSION,
+            files: BTreeMap::new(),
+        }
+    }
+}
+
+fn key(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+impl Changes {
+    pub fn to_json(&self) -> Value {
+        serde_json::json!({
+            "added": self.added,
+            "updated": self.updated,
+            "removed": self.removed,
+            "skipped": self.skipped,
+        })
+    }
+}
+
+impl Index {
+    /// Loads the index at `path`, or an empty one if there's none yet.
+    pub fn load_or_default(path: &Path) -> Result<Index> {
+        if path.exists() {
+            Index::load(path)
+        } else {
+            Ok(Index::default())
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Index> {
+        let index: Index = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| Error::Invalid(format!("{}: {e}", path.display())))?;
+        if index.version != FORMAT_VERSION {
+            return Err(Error::Invalid(format!(
+                "{}: index format {} isn't the supported {FORMAT_VERSION}; delete it to rebuild",
+                path.display(),
+                index.version
+            )));
+        }
+        Ok(index)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self).expect("indexes always serialize");
+        Ok(fs::write(path, json + "\n")?)
+    }
+
+    /// Brings the entries for files under `root` up to date and reports
+    /// the imports it ran, along with how the index changed. Files that fail
+    /// to import are dropped, as Spotlight would drop them, and retried next
+    /// time. Entries under a directory that can't be read are kept as they
+    /// are, and the directory is reported as failed.
+    pub fn refresh(
+        &mut self,
+        root: &Path,
+        options: &RefreshOptions,
+    ) -> Result<(batch::Report, Changes)> {
+        let start = Instant::now();
+        let mut changes = Changes::default();
+        let mut found = BTreeSet::new();
+        let mut stamps = BTreeMap::new();
+        let mut stale = Vec::new();
+        let mut unhandled = Vec::new();
+        let walk = batch::walk(root)?;
+        for path in &walk.files {
+            let Some(handler) = registry::handler_for_path(uti::database(), path) else {
+                unhandled.push(path.clone());
+                continue;
+            };
+            let key = key(path);
+            found.insert(key.clone());
+            // A file that can't be stamped is imported anyway, so the report
+            // says why if it fails and it's indexed as [`Stamp::UNKNOWN`]
+            // if it doesn't.
+            let stamp = Stamp::of(path, options.hash, options.batch.open).ok();
+            match (self.files.get(&key), &stamp) {
+                (Some(record), Some(stamp)) if record.is_current(stamp) => {
+                    changes.skipped.push(key);
+                    continue;
+                }
+                _ => {}
+            }
+            stamps.insert(key, stamp);
+            stale.push((path.clone(), handler.uti));
+        }
+
+        let gone: Vec<String> = self
+            .files
+            .keys()
+            .filter(|k| {
+                let path = Path::new(k);
+                path.starts_with(root)
+                    && !found.contains(*k)
+                    && !walk.errors.iter().any(|(dir, _)| path.starts_with(dir))
+            })
+            .cloned()
+            .collect();
+        for key in gone {
+            self.files.remove(&key);
+            changes.removed.push(key);
+        }
+
+        let mut files =
+            batch::import_files(stale, &options.batch, minimal_importer::import_file_with);
+        for file in &files {
+            let key = key(&file.path);
+            let stamp = stamps.remove(&key).flatten();
+            match &file.outcome {
+                Outcome::Imported(attrs) => {
+                    let attributes = match output::to_json(attrs) {
+                        Value::Object(map) => map.into_iter().collect(),
+                        _ => unreachable!("attributes serialize as an object"),
+                    };
+                    let record = Record {
+                        stamp: stamp.unwrap_or(Stamp::UNKNOWN),
+                        importer: minimal_importer::VERSION.to_owned(),
+                        attributes,
+                    };
+                    match self.files.insert(key.clone(), record) {
+                        Some(_) => changes.updated.push(key),
+                        None => changes.added.push(key),
+                    }
+                }
+                _ => {
+                    if self.files.remove(&key).is_some() {
+                        changes.removed.push(key);
+                    }
+                }
+            }
+        }
+        changes.removed.sort();
+        files.extend(walk.error_reports());
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        let report = batch::Report {
+            files,
+            skipped: unhandled,
+            elapsed: start.elapsed(),
+        };
+        Ok((report, changes))
+    }
+
+    /// Paths of the files matching `query`, sorted.
+    pub fn query(&self, query: &Query) -> Vec<&str> {
+        self.files
+            .iter()
+            .filter(|(_, record)| query.matches(&record.attributes))
+            .map(|(path, _)| path.as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refresh(index: &mut Index, root: &Path, hash: bool) -> Changes {
+        let options = RefreshOptions {
+            hash,
+            ..RefreshOptions::default()
+        };
+        let (_, changes) = index.refresh(root, &options).unwrap();
+        let names = |keys: Vec<String>| -> Vec<String> {
+            keys.iter()
+                .map(|k| {
+                    Path::new(k)
+                        .strip_prefix(root)
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect()
+        };
+        Changes {
+            added: names(changes.added),
+            updated: names(changes.updated),
+            removed: names(changes.removed),
+            skipped: names(changes.skipped),
+        }
+    }
+
+    fn changes(added: &[&str], updated: &[&str], removed: &[&str], skipped: &[&str]) -> Changes {
+        let owned = |names: &[&str]| names.iter().map(|&n| n.to_owned()).collect();
+        Changes {
+            added: owned(added),
+            updated: owned(updated),
+            removed: owned(removed),
+            skipped: owned(skipped),
+        }
+    }
+
+    #[test]
+    fn indexes_and_queries_a_batch() {
+        let tmp = tempfile::tempdir().unwrap();
+        let corpus = tmp.path().join("corpus");
+        fs::create_dir(&corpus).unwrap();
+        fs::write(corpus.join("great.great"), "This is GREAT\n").unwrap();
+        let french = include_str!("../../minimal-importer/testdata/lang/fr.txt");
+        fs::write(corpus.join("café.txt"), format!("Un café.\n{french}")).unwrap();
+        fs::write(corpus.join("plain.txt"), "Nothing to see.\n").unwrap();
+
+        let mut index = Index::default();
+        refresh(&mut index, &corpus, false);
+        let path = tmp.path().join("index/attributes.json");
+        index.save(&path).unwrap();
+        let index = Index::load(&path).unwrap();
+
+        let find = |query: &str| -> Vec<String> {
+            index
+                .query(&Query::parse(query).unwrap())
+                .into_iter()
+                .map(|p| {
+                    Path::new(p)
+                        .file_name()
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned()
+                })
+                .collect()
+        };
+        assert_eq!(
+            find(r#"kMDItemDescription == "*GREAT*"cd"#),
+            ["great.great"]
+        );
+        assert_eq!(find(r#"kMDItemTextContent == "cafe"cdw"#), ["café.txt"]);
+        assert_eq!(
+            find(r#"kMDItemLanguages == "fr" || kMDItemDescription == "Nothing*""#),
+            ["café.txt", "plain.txt"]
+        );
+        assert!(find(r#"kMDItemDescription == "*great*""#).is_empty());
+    }
+
+    #[test]
+    fn refreshes_incrementally() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path();
+        fs::write(root.join("a.great"), "alpha\n").unwrap();
+        fs::write(root.join("b.great"), "beta\n").unwrap();
+        fs::write(root.join("c.txt"), "gamma\n").unwrap();
+        let mut index = Index::default();
+        assert_eq!(
+            refresh(&mut index, root, false),
+            changes(&["a.great", "b.great", "c.txt"], &[], &[], &[])
+        );
+        assert_eq!(
+            refresh(&mut index, root, false),
+            changes(&[], &[], &[], &["a.great", "b.great", "c.txt"])
+        );
+
+        fs::write(root.join("a.great"), "alpha, again\n").unwrap();
+        fs::remove_file(root.join("b.great")).unwrap();
+        fs::write(root.join("d.great"), "delta\n").unwrap();
+        assert_eq!(
+            refresh(&mut index, root, false),
+            changes(&["d.great"], &["a.great"], &["b.great"], &["c.txt"])
+        );
+
+        // An importer upgrade re-imports everything.
+        for record in index.files.values_mut() {
+            record.importer = "0.0.0".to_owned();
+        }
+        assert_eq!(
+            refresh(&mut index, root, false),
+            changes(&[], &["a.great", "c.txt", "d.great"], &[], &[])
+        );
+    }
+
+    #[test]
+    fn hashes_catch_edits_that_keep_the_stamp() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("test.great");
+        fs::write(&file, "hello\n").unwrap();
+        let mut index = Index::default();
+        refresh(&mut index, tmp.path(), true);
+
+        // Same size and, as far as the index knows, the same mtime.
+        fs::write(&file, "jello\n").unwrap();
+        let key = key(&file);
+        index.files.get_mut(&key).unwrap().stamp.mtime_ns =
+            Stamp::of(&file, false, open::Options::default())
+                .unwrap()
+                .mtime_ns;
+        assert_eq!(
+            refresh(&mut index, tmp.path(), false),
+            changes(&[], &[], &[], &["test.great"])
+        );
+        assert_eq!(
+            refresh(&mut index, tmp.path(), true),
+            changes(&[], &["test.great"], &[], &[])
+        );
+        assert_eq!(index.files[&key].attributes["kMDItemDescription"], "jello");
+        assert_eq!(content_hash(b""), "cbf29ce484222325");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn drops_files_that_stop_importing() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("test.great");
+        fs::write(&file, "hello\n").unwrap();
+        let mut index = Index::default();
+        refresh(&mut index, tmp.path(), false);
+        assert_eq!(index.files.len(), 1);
+
+        fs::remove_file(&file).unwrap();
+        std::os::unix::fs::symlink("missing", &file).unwrap();
+        let (report, changes) = index
+            .refresh(tmp.path(), &RefreshOptions::default())
+            .unwrap();
+        assert_eq!(report.count("failed"), 1);
+        assert_eq!(changes.removed, [key(&file)]);
+        assert!(index.files.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_fifos_without_reading_them() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(tmp.path().join("a.great"), "hello\n").unwrap();
+        let fifo = tmp.path().join("fifo.great");
+        let status = std::process::Command::new("mkfifo")
+            .arg(&fifo)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        // Hashing reads every file, so this would block on the FIFO.
+        let mut index = Index::default();
+        let options = RefreshOptions {
+            hash: true,
+            ..RefreshOptions::default()
+        };
+        let (report, changes) = index.refresh(tmp.path(), &options).unwrap();
+        assert_eq!(report.count("refused"), 1);
+        assert_eq!(changes.added, [key(&tmp.path().join("a.great"))]);
+        assert!(!index.files.contains_key(&key(&fifo)));
+        let err = Stamp::of(&fifo, true, open::Options::default()).unwrap_err();
+        assert_eq!(open::rejection(&err), Some(open::Rejection::Fifo));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_entries_under_unreadable_directories() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let locked = tmp.path().join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::write(locked.join("a.great"), "alpha\n").unwrap();
+        fs::write(tmp.path().join("b.great"), "beta\n").unwrap();
+        let mut index = Index::default();
+        refresh(&mut index, tmp.path(), false);
+        assert_eq!(index.files.len(), 2);
+
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        if fs::read_dir(&locked).is_ok() {
+            // Running as root, which reads anything.
+            return;
+        }
+        let refreshed = index.refresh(tmp.path(), &RefreshOptions::default());
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        let (report, changes) = refreshed.unwrap();
+        assert_eq!(report.count("failed"), 1);
+        assert_eq!(report.files[0].path, locked);
+        assert!(changes.removed.is_empty());
+        assert_eq!(index.files.len(), 2);
+    }
+
+    #[test]
+    fn unknown_stamps_are_never_current() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("a.great");
+        fs::write(&file, "alpha\n").unwrap();
+        let record = Record {
+            stamp: Stamp::UNKNOWN,
+            importer: minimal_importer::VERSION.to_owned(),
+            ..Record::default()
+        };
+        for hash in [false, true] {
+            let stamp = Stamp::of(&file, hash, open::Options::default()).unwrap();
+            assert!(!record.is_current(&stamp));
+        }
+    }
+}
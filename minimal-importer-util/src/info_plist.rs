@@ -0,0 +1,303 @@
+//! Generating `Info.plist` files from `[package.metadata.bundle]`.
+//!
+//! The importer bundle's plist also picks up the UTIs registered in
+//! [`minimal_importer::registry`] and the factory UUIDs from
+//! [`minimal_importer::plugin`], so none of those have to be kept in sync by
+//! hand.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use minimal_importer::plugin::{self, uuid_string};
+use minimal_importer::registry;
+use plist::{Dictionary, Value};
+use serde::Deserialize;
+
+use crate::{Error, Result};
+
+/// `CFBundlePackageType` of a loadable bundle such as an `.mdimporter`.
+pub const PACKAGE_TYPE_BUNDLE: &str = "BNDL";
+
+/// `CFBundlePackageType` of an application.
+pub const PACKAGE_TYPE_APP: &str = "APPL";
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    package: Package,
+}
+
+#[derive(Debug, Deserialize)]
+struct Package {
+    name: String,
+    version: String,
+    #[serde(default)]
+    metadata: Option<PackageMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PackageMetadata {
+    bundle: Option<BundleMetadata>,
+}
+
+/// The `[package.metadata.bundle]` table of a crate's `Cargo.toml`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct BundleMetadata {
+    pub identifier: String,
+    pub package_type: String,
+    #[serde(default = "default_development_region")]
+    pub development_region: String,
+    /// `CFBundleExecutable`; the package name if not set.
+    pub executable: Option<String>,
+    /// UTIs an importer claims; every registered UTI if not set.
+    pub content_types: Option<Vec<String>>,
+    /// `CFBundleTypeRole` of an app's document types.
+    #[serde(default = "default_document_role")]
+    pub document_role: String,
+    #[serde(default)]
+    pub exported_types: Vec<TypeDeclaration>,
+    #[serde(default)]
+    pub imported_types: Vec<TypeDeclaration>,
+}
+
+fn default_development_region() -> String {
+    "English".to_owned()
+}
+
+fn default_document_role() -> String {
+    "Viewer".to_owned()
+}
+
+/// A `UTExportedTypeDeclarations` or `UTImportedTypeDeclarations` entry.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TypeDeclaration {
+    pub identifier: String,
+    pub description: String,
+    #[serde(default)]
+    pub conforms_to: Vec<String>,
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    #[serde(default)]
+    pub mime_types: Vec<String>,
+}
+
+/// Everything needed to describe one crate's bundle.
+#[derive(Clone, Debug)]
+pub struct BundleInfo {
+    pub package_name: String,
+    pub version: String,
+    pub bundle: BundleMetadata,
+}
+
+impl BundleInfo {
+    pub fn executable(&self) -> &str {
+        self.bundle
+            .executable
+            .as_deref()
+            .unwrap_or(&self.package_name)
+    }
+
+    pub fn is_importer(&self) -> bool {
+        self.bundle.package_type == PACKAGE_TYPE_BUNDLE
+    }
+
+    /// The UTIs an importer bundle claims in `LSItemContentTypes`.
+    pub fn content_types(&self) -> Vec<String> {
+        match &self.bundle.content_types {
+            Some(types) => types.clone(),
+            None => registry::utis().map(str::to_owned).collect(),
+        }
+    }
+}
+
+/// Reads the bundle description from a crate directory or its `Cargo.toml`.
+pub fn read_manifest(path: &Path) -> Result<BundleInfo> {
+    let path: PathBuf = if path.is_dir() {
+        path.join("Cargo.toml")
+    } else {
+        path.to_owned()
+    };
+    parse_manifest(&fs::read_to_string(&path)?)
+        .map_err(|e| Error::Invalid(format!("{}: {e}", path.display())))
+}
+
+pub fn parse_manifest(manifest: &str) -> Result<BundleInfo> {
+    let manifest: Manifest = toml::from_str(manifest)?;
+    let bundle = manifest
+        .package
+        .metadata
+        .and_then(|m| m.bundle)
+        .ok_or_else(|| Error::Invalid("no [package.metadata.bundle] table".to_owned()))?;
+    Ok(BundleInfo {
+        package_name: manifest.package.name,
+        version: manifest.package.version,
+        bundle,
+    })
+}
+
+fn strings(values: &[String]) -> Value {
+    Value::Array(values.iter().map(|s| s.as_str().into()).collect())
+}
+
+/// Builds a dictionary with its keys sorted, the way Xcode writes plists.
+fn dict(entries: BTreeMap<&str, Value>) -> Value {
+    Value::Dictionary(
+        entries
+            .into_iter()
+            .map(|(k, v)| (k.to_owned(), v))
+            .collect::<Dictionary>(),
+    )
+}
+
+fn type_declaration(decl: &TypeDeclaration) -> Value {
+    let mut tags = BTreeMap::new();
+    if !decl.extensions.is_empty() {
+        tags.insert("public.filename-extension", strings(&decl.extensions));
+    }
+    if !decl.mime_types.is_empty() {
+        tags.insert("public.mime-type", strings(&decl.mime_types));
+    }
+    let mut entries = BTreeMap::new();
+    entries.insert("UTTypeConformsTo", strings(&decl.conforms_to));
+    entries.insert("UTTypeDescription", decl.description.as_str().into());
+    entries.insert("UTTypeIdentifier", decl.identifier.as_str().into());
+    entries.insert("UTTypeTagSpecification", dict(tags));
+    dict(entries)
+}
+
+/// Generates the `Info.plist` contents for `info`.
+///
+/// Importer bundles fail to generate if they claim a UTI that has no handler
+/// in the registry.
+pub fn info_plist(info: &BundleInfo) -> Result<Value> {
+    let b = &info.bundle;
+    let mut entries: BTreeMap<&str, Value> = BTreeMap::new();
+    entries.insert(
+        "CFBundleDevelopmentRegion",
+        b.development_region.as_str().into(),
+    );
+    entries.insert("CFBundleExecutable", info.executable().into());
+    entries.insert("CFBundleIconFile", "".into());
+    entries.insert("CFBundleIdentifier", b.identifier.as_str().into());
+    entries.insert("CFBundleInfoDictionaryVersion", "6.0".into());
+    entries.insert("CFBundlePackageType", b.package_type.as_str().into());
+    entries.insert("CFBundleSignature", "????".into());
+    entries.insert("CFBundleVersion", info.version.as_str().into());
+
+    let document_types: Vec<Value> = if info.is_importer() {
+        let content_types = info.content_types();
+        if let Some(uti) = content_types
+            .iter()
+            .find(|uti| registry::handler_for_uti(uti).is_none())
+        {
+            return Err(Error::Invalid(format!(
+                "{} claims {uti} but no importer is registered for it",
+                info.package_name
+            )));
+        }
+        let factory = uuid_string(&plugin::MetadataImporterPluginFactoryUUID);
+        let mut factories = BTreeMap::new();
+        factories.insert(
+            factory.as_str(),
+            plugin::MetadataImporterPluginFactoryName.into(),
+        );
+        let type_id = uuid_string(&plugin::kMDImporterTypeID);
+        let mut types = BTreeMap::new();
+        types.insert(type_id.as_str(), strings(std::slice::from_ref(&factory)));
+        entries.insert("CFPlugInDynamicRegisterFunction", "".into());
+        entries.insert("CFPlugInDynamicRegistration", "NO".into());
+        entries.insert("CFPlugInFactories", dict(factories));
+        entries.insert("CFPlugInTypes", dict(types));
+        entries.insert("CFPlugInUnloadFunction", "".into());
+
+        let mut document = BTreeMap::new();
+        document.insert("CFBundleTypeRole", "MDImporter".into());
+        document.insert("LSItemContentTypes", strings(&content_types));
+        vec![dict(document)]
+    } else {
+        b.exported_types
+            .iter()
+            .map(|decl| {
+                let mut document = BTreeMap::new();
+                document.insert("CFBundleTypeName", decl.description.as_str().into());
+                document.insert("CFBundleTypeRole", b.document_role.as_str().into());
+                document.insert(
+                    "LSItemContentTypes",
+                    strings(std::slice::from_ref(&decl.identifier)),
+                );
+                dict(document)
+            })
+            .collect()
+    };
+    if !document_types.is_empty() {
+        entries.insert("CFBundleDocumentTypes", Value::Array(document_types));
+    }
+    if !b.exported_types.is_empty() {
+        let decls = b.exported_types.iter().map(type_declaration).collect();
+        entries.insert("UTExportedTypeDeclarations", Value::Array(decls));
+    }
+    if !b.imported_types.is_empty() {
+        let decls = b.imported_types.iter().map(type_declaration).collect();
+        entries.insert("UTImportedTypeDeclarations", Value::Array(decls));
+    }
+    Ok(dict(entries))
+}
+
+/// Serializes a plist as XML, newline-terminated.
+pub fn to_xml(value: &Value) -> Result<String> {
+    let mut out = Vec::new();
+    value.to_writer_xml(&mut out)?;
+    out.push(b'\n');
+    String::from_utf8(out).map_err(|e| Error::Invalid(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate(manifest: &str) -> String {
+        to_xml(&info_plist(&parse_manifest(manifest).unwrap()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn golden_importer() {
+        assert_eq!(
+            generate(include_str!("../testdata/info-plist/importer.toml")),
+            include_str!("../testdata/info-plist/importer.plist.xml")
+        );
+    }
+
+    #[test]
+    fn golden_app() {
+        assert_eq!(
+            generate(include_str!("../testdata/info-plist/app.toml")),
+            include_str!("../testdata/info-plist/app.plist.xml")
+        );
+    }
+
+    #[test]
+    fn checked_in_plists_are_current() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+        for krate in ["minimal-importer-bundle", "minimal-importer-app"] {
+            let dir = root.join(krate);
+            let info = read_manifest(&dir).unwrap();
+            let generated = to_xml(&info_plist(&info).unwrap()).unwrap();
+            let checked_in = fs::read_to_string(dir.join("resources/Info.plist.xml")).unwrap();
+            assert_eq!(
+                generated, checked_in,
+                "{krate}/resources/Info.plist.xml is stale, regenerate it with \
+                 `minimal-importer-util info-plist {krate} -o {krate}/resources/Info.plist.xml`"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unregistered_content_type() {
+        let manifest = include_str!("../testdata/info-plist/importer.toml")
+            .replace("\"vin.je.great\"", "\"vin.je.rich\"");
+        let info = parse_manifest(&manifest).unwrap();
+        assert!(matches!(info_plist(&info), Err(Error::Invalid(_))));
+    }
+}
@@ -0,0 +1,63 @@
+//! Library side of `minimal-importer-util`, for build scripts and tests that
+//! want the same bundle tooling as the command line.
+
+use std::fmt;
+use std::io;
+
+pub mod batch;
+pub mod bundle;
+#[cfg(target_os = "macos")]
+pub mod cfplugin;
+pub mod index;
+pub mod info_plist;
+pub mod macho;
+pub mod mdls;
+pub mod output;
+pub mod query;
+pub mod search;
+pub mod snapshot;
+pub mod uti;
+pub mod verify;
+pub mod watch;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Plist(plist::Error),
+    Toml(toml::de::Error),
+    /// Input that parsed fine but doesn't describe a valid bundle.
+    Invalid(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Plist(e) => write!(f, "{e}"),
+            Error::Toml(e) => write!(f, "{e}"),
+            Error::Invalid(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<plist::Error> for Error {
+    fn from(e: plist::Error) -> Self {
+        Error::Plist(e)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(e: toml::de::Error) -> Self {
+        Error::Toml(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,333 @@
+//! Just enough Mach-O parsing to check what the linker produced.
+//!
+//! Reads thin and fat (universal) files of either word size and byte order,
+//! and pulls out the file type, `LC_UUID`, `LC_ID_DYLIB` and the symbol
+//! table. Nothing here depends on the host, so the verifier runs on Linux.
+
+use crate::{Error, Result};
+
+const MH_MAGIC: u32 = 0xfeed_face;
+const MH_MAGIC_64: u32 = 0xfeed_facf;
+const FAT_MAGIC: u32 = 0xcafe_babe;
+const FAT_MAGIC_64: u32 = 0xcafe_babf;
+
+pub const MH_EXECUTE: u32 = 0x2;
+pub const MH_DYLIB: u32 = 0x6;
+pub const MH_BUNDLE: u32 = 0x8;
+
+const LC_SYMTAB: u32 = 0x2;
+const LC_ID_DYLIB: u32 = 0xd;
+const LC_UUID: u32 = 0x1b;
+
+const N_STAB: u8 = 0xe0;
+const N_PEXT: u8 = 0x10;
+const N_TYPE: u8 = 0x0e;
+const N_EXT: u8 = 0x01;
+const N_SECT: u8 = 0x0e;
+
+/// A symbol table entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    /// The mangled name, with the leading underscore.
+    pub name: String,
+    /// Defined in this image and visible to `dlsym`/`CFBundleGetFunctionPointerForName`.
+    pub exported: bool,
+}
+
+/// One architecture's image; a thin file has exactly one.
+#[derive(Clone, Debug)]
+pub struct MachO {
+    pub cpu_type: u32,
+    pub file_type: u32,
+    pub uuid: Option<[u8; 16]>,
+    /// The `LC_ID_DYLIB` install name; only dylibs have one.
+    pub install_name: Option<String>,
+    pub symbols: Vec<Symbol>,
+}
+
+impl MachO {
+    pub fn arch_name(&self) -> String {
+        match self.cpu_type {
+            0x7 => "i386".to_owned(),
+            0xc => "arm".to_owned(),
+            0x12 => "ppc".to_owned(),
+            0x0100_0007 => "x86_64".to_owned(),
+            0x0100_000c => "arm64".to_owned(),
+            0x0200_000c => "arm64_32".to_owned(),
+            other => format!("cputype {other:#x}"),
+        }
+    }
+
+    pub fn is_exported(&self, name: &str) -> bool {
+        self.symbols.iter().any(|s| s.exported && s.name == name)
+    }
+
+    pub fn exported_symbols(&self) -> impl Iterator<Item = &str> {
+        self.symbols
+            .iter()
+            .filter(|s| s.exported)
+            .map(|s| s.name.as_str())
+    }
+}
+
+/// The `MH_*` name of a file type, for messages.
+pub fn file_type_name(file_type: u32) -> String {
+    match file_type {
+        0x1 => "MH_OBJECT".to_owned(),
+        MH_EXECUTE => "MH_EXECUTE".to_owned(),
+        MH_DYLIB => "MH_DYLIB".to_owned(),
+        MH_BUNDLE => "MH_BUNDLE".to_owned(),
+        other => format!("file type {other:#x}"),
+    }
+}
+
+fn truncated(what: &str) -> Error {
+    Error::Invalid(format!("truncated Mach-O: {what} runs past the end"))
+}
+
+/// Bounds-checked reads in one byte order.
+#[derive(Clone, Copy)]
+struct Reader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&self, offset: usize, len: usize, what: &str) -> Result<&'a [u8]> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| truncated(what))
+    }
+
+    fn u32(&self, offset: usize, what: &str) -> Result<u32> {
+        let b: [u8; 4] = self.bytes(offset, 4, what)?.try_into().unwrap();
+        Ok(if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    }
+
+    fn u64(&self, offset: usize, what: &str) -> Result<u64> {
+        let b: [u8; 8] = self.bytes(offset, 8, what)?.try_into().unwrap();
+        Ok(if self.big_endian {
+            u64::from_be_bytes(b)
+        } else {
+            u64::from_le_bytes(b)
+        })
+    }
+
+    /// A NUL-terminated string starting at `offset`.
+    fn c_str(&self, offset: usize, what: &str) -> Result<String> {
+        let rest = self.data.get(offset..).ok_or_else(|| truncated(what))?;
+        let len = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+        Ok(String::from_utf8_lossy(&rest[..len]).into_owned())
+    }
+}
+
+/// Parses a thin or fat Mach-O file into its images.
+pub fn parse(data: &[u8]) -> Result<Vec<MachO>> {
+    let magic = Reader {
+        data,
+        big_endian: true,
+    }
+    .u32(0, "magic")?;
+    match magic {
+        FAT_MAGIC | FAT_MAGIC_64 => parse_fat(data, magic == FAT_MAGIC_64),
+        _ => Ok(vec![parse_thin(data)?]),
+    }
+}
+
+fn parse_fat(data: &[u8], is_64: bool) -> Result<Vec<MachO>> {
+    // Fat headers are always big-endian.
+    let r = Reader {
+        data,
+        big_endian: true,
+    };
+    let count = r.u32(4, "fat header")? as usize;
+    let entry_size = if is_64 { 32 } else { 20 };
+    let mut images = Vec::with_capacity(count.min(16));
+    for i in 0..count {
+        let entry = 8 + i * entry_size;
+        let (offset, size) = if is_64 {
+            (
+                r.u64(entry + 8, "fat_arch_64")?,
+                r.u64(entry + 16, "fat_arch_64")?,
+            )
+        } else {
+            (
+                u64::from(r.u32(entry + 8, "fat_arch")?),
+                u64::from(r.u32(entry + 12, "fat_arch")?),
+            )
+        };
+        let slice = r.bytes(offset as usize, size as usize, "fat slice")?;
+        images.push(parse_thin(slice)?);
+    }
+    if images.is_empty() {
+        return Err(Error::Invalid("fat Mach-O has no architectures".to_owned()));
+    }
+    Ok(images)
+}
+
+fn parse_thin(data: &[u8]) -> Result<MachO> {
+    let le = Reader {
+        data,
+        big_endian: false,
+    };
+    let magic = le.u32(0, "magic")?;
+    let (big_endian, is_64) = match magic {
+        MH_MAGIC => (false, false),
+        MH_MAGIC_64 => (false, true),
+        m if m.swap_bytes() == MH_MAGIC => (true, false),
+        m if m.swap_bytes() == MH_MAGIC_64 => (true, true),
+        m => {
+            return Err(Error::Invalid(format!(
+                "not a Mach-O file (magic {m:#010x})"
+            )));
+        }
+    };
+    let r = Reader { data, big_endian };
+    let mut image = MachO {
+        cpu_type: r.u32(4, "mach header")?,
+        file_type: r.u32(12, "mach header")?,
+        uuid: None,
+        install_name: None,
+        symbols: Vec::new(),
+    };
+    let ncmds = r.u32(16, "mach header")?;
+    let mut cmd = if is_64 { 32 } else { 28 };
+    for _ in 0..ncmds {
+        let kind = r.u32(cmd, "load command")?;
+        let size = r.u32(cmd + 4, "load command")? as usize;
+        if size < 8 {
+            return Err(Error::Invalid(format!(
+                "load command {kind:#x} has size {size}"
+            )));
+        }
+        match kind {
+            LC_UUID => {
+                image.uuid = Some(r.bytes(cmd + 8, 16, "LC_UUID")?.try_into().unwrap());
+            }
+            LC_ID_DYLIB => {
+                let name = r.u32(cmd + 8, "LC_ID_DYLIB")? as usize;
+                image.install_name = Some(r.c_str(cmd + name, "LC_ID_DYLIB")?);
+            }
+            LC_SYMTAB => {
+                let symoff = r.u32(cmd + 8, "LC_SYMTAB")? as usize;
+                let nsyms = r.u32(cmd + 12, "LC_SYMTAB")? as usize;
+                let stroff = r.u32(cmd + 16, "LC_SYMTAB")? as usize;
+                image.symbols = symbols(r, is_64, symoff, nsyms, stroff)?;
+            }
+            _ => {}
+        }
+        cmd += size;
+    }
+    Ok(image)
+}
+
+fn symbols(
+    r: Reader,
+    is_64: bool,
+    symoff: usize,
+    nsyms: usize,
+    stroff: usize,
+) -> Result<Vec<Symbol>> {
+    let entry_size = if is_64 { 16 } else { 12 };
+    r.bytes(symoff, nsyms.saturating_mul(entry_size), "symbol table")?;
+    (0..nsyms)
+        .map(|i| {
+            let entry = symoff + i * entry_size;
+            let strx = r.u32(entry, "nlist")? as usize;
+            let n_type = r.bytes(entry + 4, 1, "nlist")?[0];
+            let exported = n_type & N_STAB == 0
+                && n_type & N_EXT != 0
+                && n_type & N_PEXT == 0
+                && n_type & N_TYPE == N_SECT;
+            Ok(Symbol {
+                name: r.c_str(stroff + strx, "string table")?,
+                exported,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use minimal_importer::plugin::uuid_string;
+
+    fn fixture(name: &str) -> Vec<u8> {
+        std::fs::read(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("testdata/macho")
+                .join(name),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn thin_bundle() {
+        let images = parse(&fixture("bundle-arm64")).unwrap();
+        let [image] = &images[..] else {
+            panic!("{images:?}")
+        };
+        assert_eq!(image.arch_name(), "arm64");
+        assert_eq!(image.file_type, MH_BUNDLE);
+        assert!(image.uuid.is_some());
+        assert_eq!(image.install_name, None);
+        let exported: Vec<_> = image.exported_symbols().collect();
+        assert_eq!(exported, ["_MetadataImporterPluginFactory"]);
+        assert!(
+            image
+                .symbols
+                .iter()
+                .any(|s| s.name == "_helper" && !s.exported)
+        );
+        let stray = &parse(&fixture("stray-export-arm64")).unwrap()[0];
+        assert!(stray.is_exported("_helper"));
+    }
+
+    #[test]
+    fn fat_bundle() {
+        let images = parse(&fixture("bundle-fat")).unwrap();
+        let arches: Vec<_> = images.iter().map(MachO::arch_name).collect();
+        assert_eq!(arches, ["x86_64", "arm64"]);
+        assert!(images.iter().all(|i| i.file_type == MH_BUNDLE));
+        assert!(
+            images
+                .iter()
+                .all(|i| i.is_exported("_MetadataImporterPluginFactory"))
+        );
+        let uuids: Vec<_> = images
+            .iter()
+            .map(|i| uuid_string(&i.uuid.unwrap()))
+            .collect();
+        assert_ne!(uuids[0], uuids[1]);
+    }
+
+    #[test]
+    fn dylib_and_hidden_symbols() {
+        let dylib = &parse(&fixture("dylib-arm64")).unwrap()[0];
+        assert_eq!(file_type_name(dylib.file_type), "MH_DYLIB");
+        assert_eq!(
+            dylib.install_name.as_deref(),
+            Some("@rpath/libfactory.dylib")
+        );
+        let hidden = &parse(&fixture("hidden-factory-arm64")).unwrap()[0];
+        assert!(!hidden.is_exported("_MetadataImporterPluginFactory"));
+        assert!(hidden.is_exported("_helper"));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(parse(b"#!/bin/sh\n").is_err());
+        assert!(parse(&[]).is_err());
+        let mut data = fixture("bundle-fat");
+        data.truncate(20_000);
+        assert!(parse(&data).is_err());
+        let mut data = fixture("bundle-arm64");
+        data.truncate(100);
+        assert!(parse(&data).is_err());
+    }
+}
@@ -1,3 +1,705 @@
-fn main() {
-    println!("Hello, world!");
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+use minimal_importer::plugin::uuid_string;
+use minimal_importer::{Attributes, registry};
+use minimal_importer_util::output::{self, Format};
+use minimal_importer_util::{
+    batch, bundle, index, info_plist, macho, mdls, query, search, snapshot, uti, verify, watch,
+};
+
+/// Development tools for the minimal Spotlight importer.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run the importer on a file and print the attributes it sets.
+    Import {
+        file: PathBuf,
+        /// Content type to import as; guessed from the extension if omitted.
+        #[arg(long)]
+        uti: Option<String>,
+        #[arg(long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+        /// Only print this attribute; repeatable. With `--format mdls`,
+        /// attributes that aren't set print as `(null)`.
+        #[arg(long)]
+        name: Vec<String>,
+        /// Refuse FILE if it's a symlink rather than importing its target.
+        #[arg(long)]
+        no_follow_symlinks: bool,
+    },
+    /// Resolve a file name, extension, MIME type or UTI to its types.
+    Uti {
+        name: String,
+        /// Treat NAME as a MIME type.
+        #[arg(long)]
+        mime: bool,
+        /// Only check whether the preferred type conforms to this one.
+        #[arg(long)]
+        conforms_to: Option<String>,
+        /// Also load the type declarations in these Info.plists.
+        #[arg(long)]
+        plist: Vec<PathBuf>,
+    },
+    /// Generate a crate's Info.plist from its `[package.metadata.bundle]`.
+    InfoPlist {
+        /// Crate directory or Cargo.toml.
+        manifest: PathBuf,
+        /// Write to this file instead of stdout.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Build the importer through minimal-importer-ld and assemble it into
+    /// an .mdimporter bundle, optionally nested in an app under
+    /// Contents/Library/Spotlight.
+    Bundle {
+        /// Importer crate directory or Cargo.toml.
+        #[arg(default_value = "minimal-importer-bundle")]
+        manifest: PathBuf,
+        /// The built cdylib. Without it the importer is built for release,
+        /// linked through minimal-importer-ld.
+        #[arg(long)]
+        binary: Option<PathBuf>,
+        #[arg(short, long, default_value = "target/bundle")]
+        out_dir: PathBuf,
+        /// Bundle name without the extension; defaults to the package name.
+        #[arg(long)]
+        name: Option<String>,
+        /// App crate directory or Cargo.toml to package the importer in.
+        #[arg(long)]
+        app: Option<PathBuf>,
+        /// The built app executable; defaults to the release build in ./target.
+        #[arg(long, requires = "app")]
+        app_binary: Option<PathBuf>,
+        /// App name without the extension; defaults to the app's package name.
+        #[arg(long, requires = "app")]
+        app_name: Option<String>,
+    },
+    /// Import every file under a directory and summarize how it went.
+    Batch {
+        dir: PathBuf,
+        /// Imports to run at once; defaults to the number of CPUs.
+        #[arg(short, long)]
+        jobs: Option<usize>,
+        /// Seconds a single import may take; 0 waits forever.
+        #[arg(long, default_value_t = 30.0)]
+        timeout: f64,
+        /// Write a JSON report to this file, or `-` for stdout.
+        #[arg(long)]
+        report: Option<PathBuf>,
+        /// Record the imported attributes in this index for `query`, only
+        /// importing files that changed since the last run.
+        #[arg(long, num_args = 0..=1, default_missing_value = index::DEFAULT_PATH)]
+        index: Option<PathBuf>,
+        /// Also compare content hashes to decide what changed.
+        #[arg(long, requires = "index")]
+        hash: bool,
+        /// Refuse symlinked files rather than importing their targets.
+        #[arg(long)]
+        no_follow_symlinks: bool,
+    },
+    /// List the indexed files matching a Spotlight query, as `mdfind` would.
+    Query {
+        /// For example `kMDItemDescription == "*GREAT*"cd`.
+        query: String,
+        /// The index `batch --index` wrote.
+        #[arg(long, default_value = index::DEFAULT_PATH)]
+        index: PathBuf,
+    },
+    /// Rank the indexed files by how well their text content matches words,
+    /// `prefix*` words and "quoted phrases".
+    Search {
+        query: String,
+        /// The index `batch --index` wrote.
+        #[arg(long, default_value = index::DEFAULT_PATH)]
+        index: PathBuf,
+        /// Most results to print.
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Record the attributes imported from every file under a directory.
+    Snapshot {
+        corpus: PathBuf,
+        /// Write to this file instead of stdout.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Show which files' attributes differ between two snapshots.
+    Diff { old: PathBuf, new: PathBuf },
+    /// Check a built .mdimporter, or the importers in an .app, against its
+    /// Info.plist: Mach-O file type, exported factory and UUIDs.
+    Verify { bundle: PathBuf },
+    /// Load a built .mdimporter the way Spotlight does and print what its
+    /// build-info interface reports: through CFPlugIn on macOS, elsewhere
+    /// through minimal-importer-host for bundles built against the portable
+    /// ABI.
+    BuildInfo {
+        bundle: PathBuf,
+        #[arg(long, value_enum, default_value_t = Format::Table)]
+        format: Format,
+    },
+    /// Re-import files whenever they change and print how their attributes
+    /// differ from the previous run.
+    Watch {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Seconds between checks for changes.
+        #[arg(long, default_value_t = 0.5)]
+        interval: f64,
+        /// Rebuild the util and reload it when the importer's source changes.
+        #[arg(long)]
+        rebuild: bool,
+    },
+}
+
+/// The app side of `bundle --app`.
+#[derive(Debug)]
+struct AppArgs {
+    manifest: PathBuf,
+    binary: Option<PathBuf>,
+    name: Option<String>,
+}
+
+fn open_options(no_follow_symlinks: bool) -> minimal_importer::open::Options {
+    minimal_importer::open::Options {
+        follow_symlinks: !no_follow_symlinks,
+    }
+}
+
+fn import(
+    file: PathBuf,
+    uti: Option<String>,
+    format: Format,
+    names: &[String],
+    open: minimal_importer::open::Options,
+) -> Result<(), String> {
+    let db = uti::database();
+    let handler = match &uti {
+        Some(uti) => registry::handler_for_type(db, uti)
+            .ok_or_else(|| format!("no importer handles {uti} or a type it conforms to"))?,
+        None => registry::handler_for_path(db, &file)
+            .ok_or_else(|| format!("can't guess a UTI for {}, pass --uti", file.display()))?,
+    };
+    let mut attrs = Attributes::new();
+    minimal_importer::import_file_with(&mut attrs, handler.uti, &file, open).map_err(|e| {
+        format!(
+            "importing {} as {} failed: {e}",
+            file.display(),
+            handler.uti
+        )
+    })?;
+    let mut stdout = std::io::stdout().lock();
+    if names.is_empty() {
+        return output::print(&attrs, format, &mut stdout).map_err(|e| e.to_string());
+    }
+    if format == Format::Mdls {
+        return mdls::print(&attrs, Some(names), &mut stdout).map_err(|e| e.to_string());
+    }
+    let mut selected = Attributes::new();
+    for name in names {
+        if let Some(value) = attrs.get(name) {
+            selected.insert(name.clone(), value.clone());
+        }
+    }
+    output::print(&selected, format, &mut stdout).map_err(|e| e.to_string())
+}
+
+fn resolve_types(
+    name: &str,
+    mime: bool,
+    conforms_to: Option<String>,
+    plists: &[PathBuf],
+) -> Result<(), String> {
+    let mut db = uti::database().clone();
+    for plist in plists {
+        uti::declare_from_file(&mut db, plist).map_err(|e| format!("{}: {e}", plist.display()))?;
+    }
+    let types: Vec<&str> = if mime {
+        db.types_for_mime_type(name)
+    } else if let Some(decl) = db.get(name) {
+        vec![decl.identifier.as_str()]
+    } else {
+        // A path, a file name or a bare extension.
+        let ext = Path::new(name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or(name);
+        db.types_for_extension(ext)
+    };
+    let Some(&preferred) = types.first() else {
+        return Err(format!("no type is declared for {name}"));
+    };
+    if let Some(parent) = conforms_to {
+        return if db.conforms_to(preferred, &parent) {
+            println!("{preferred} conforms to {parent}");
+            Ok(())
+        } else {
+            Err(format!("{preferred} doesn't conform to {parent}"))
+        };
+    }
+    for uti in types {
+        println!("{uti}");
+        println!("  conforms to: {}", db.supertypes(uti).join(", "));
+        let importer = registry::handler_for_type(&db, uti).map_or("none", |h| h.uti);
+        println!("  importer: {importer}");
+    }
+    Ok(())
+}
+
+fn generate_info_plist(manifest: &Path, output: Option<PathBuf>) -> Result<(), String> {
+    let info = info_plist::read_manifest(manifest).map_err(|e| e.to_string())?;
+    let xml = info_plist::info_plist(&info)
+        .and_then(|plist| info_plist::to_xml(&plist))
+        .map_err(|e| e.to_string())?;
+    match output {
+        Some(path) => fs::write(&path, xml).map_err(|e| format!("{}: {e}", path.display())),
+        None => {
+            print!("{xml}");
+            Ok(())
+        }
+    }
+}
+
+fn assemble_bundle(
+    manifest: &Path,
+    binary: Option<PathBuf>,
+    out_dir: &Path,
+    name: Option<String>,
+    app: Option<AppArgs>,
+) -> Result<(), String> {
+    let info = info_plist::read_manifest(manifest).map_err(|e| e.to_string())?;
+    let binary = match binary {
+        Some(binary) => binary,
+        None => build_importer(manifest, &info)?,
+    };
+    verify::check_linked_as_bundle(&binary).map_err(|e| e.to_string())?;
+    let name = name.unwrap_or_else(|| info.package_name.clone());
+    fs::create_dir_all(out_dir).map_err(|e| format!("{}: {e}", out_dir.display()))?;
+    let path = match app {
+        None => bundle::assemble_importer(&info, &binary, out_dir, &name),
+        Some(app) => {
+            let app_info = info_plist::read_manifest(&app.manifest).map_err(|e| e.to_string())?;
+            let app_binary = app
+                .binary
+                .unwrap_or_else(|| bundle::default_binary(&app_info));
+            let app_name = app.name.unwrap_or_else(|| app_info.package_name.clone());
+            bundle::assemble_app(
+                &app_info,
+                &app_binary,
+                &info,
+                &binary,
+                out_dir,
+                &app_name,
+                &name,
+            )
+        }
+    }
+    .map_err(|e| e.to_string())?;
+    println!("{}", path.display());
+    Ok(())
+}
+
+/// Builds the importer crate at `manifest` for release, linking it through
+/// `minimal-importer-ld` so it comes out an `MH_BUNDLE`, and returns the
+/// cdylib. The driver is built first, with the system linker; nothing but
+/// the importer's own link goes through it.
+fn build_importer(manifest: &Path, info: &info_plist::BundleInfo) -> Result<PathBuf, String> {
+    let cargo = |args: &[&std::ffi::OsStr]| -> Result<(), String> {
+        let status =
+            std::process::Command::new(std::env::var_os("CARGO").unwrap_or("cargo".into()))
+                .args(args)
+                .status()
+                .map_err(|e| format!("running cargo: {e}"))?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(format!("cargo {} failed: {status}", args[0].display()))
+        }
+    };
+    cargo(&["build", "--release", "-p", "minimal-importer-ld"].map(AsRef::as_ref))?;
+
+    let driver = std::env::current_dir()
+        .map_err(|e| e.to_string())?
+        .join("target/release/minimal-importer-ld");
+    let mut linker = std::ffi::OsString::from("linker=");
+    linker.push(&driver);
+    let manifest = if manifest.is_dir() {
+        manifest.join("Cargo.toml")
+    } else {
+        manifest.to_owned()
+    };
+    cargo(&[
+        "rustc".as_ref(),
+        "--release".as_ref(),
+        "--manifest-path".as_ref(),
+        manifest.as_os_str(),
+        "--lib".as_ref(),
+        "--".as_ref(),
+        "-C".as_ref(),
+        linker.as_os_str(),
+    ])?;
+    Ok(bundle::default_binary(info))
+}
+
+fn run_batch(
+    dir: &Path,
+    jobs: Option<usize>,
+    timeout: f64,
+    report_path: Option<PathBuf>,
+    index_path: Option<PathBuf>,
+    hash: bool,
+    open: minimal_importer::open::Options,
+) -> Result<(), String> {
+    let mut options = batch::Options {
+        open,
+        ..batch::Options::default()
+    };
+    if let Some(jobs) = jobs {
+        options.workers = jobs;
+    }
+    options.timeout = (timeout > 0.0)
+        .then(|| Duration::try_from_secs_f64(timeout))
+        .transpose()
+        .map_err(|e| format!("--timeout: {e}"))?;
+    let mut index = index_path
+        .as_deref()
+        .map(index::Index::load_or_default)
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    let (report, changes) = match &mut index {
+        Some(index) => {
+            let options = index::RefreshOptions {
+                batch: options,
+                hash,
+            };
+            let (report, changes) = index
+                .refresh(dir, &options)
+                .map_err(|e| format!("{}: {e}", dir.display()))?;
+            (report, Some(changes))
+        }
+        None => (
+            batch::run(dir, &options).map_err(|e| format!("{}: {e}", dir.display()))?,
+            None,
+        ),
+    };
+
+    let to_stdout = report_path.as_deref() == Some(Path::new("-"));
+    // Keep stdout clean for the JSON report.
+    let mut out: Box<dyn Write> = if to_stdout {
+        Box::new(std::io::stderr())
+    } else {
+        Box::new(std::io::stdout())
+    };
+    let write_err = |e: std::io::Error| e.to_string();
+    for file in &report.files {
+        let detail = match &file.outcome {
+            batch::Outcome::Imported(attrs) => format!("{} attributes", attrs.len()),
+            batch::Outcome::Refused(rejection) => rejection.to_string(),
+            batch::Outcome::Failed(e) | batch::Outcome::Panicked(e) => e.clone(),
+            batch::Outcome::TimedOut => String::new(),
+        };
+        writeln!(
+            out,
+            "{:<9} {:>9.1}ms  {}  {detail}",
+            file.outcome.status(),
+            batch::millis(file.duration),
+            file.path.display()
+        )
+        .map_err(write_err)?;
+    }
+    writeln!(
+        out,
+        "{} files: {} imported, {} refused, {} failed, {} panicked, {} timed out, {} skipped in {:.1}ms",
+        report.files.len(),
+        report.count("imported"),
+        report.count("refused"),
+        report.count("failed"),
+        report.count("panicked"),
+        report.count("timed_out"),
+        report.skipped.len(),
+        batch::millis(report.elapsed)
+    )
+    .map_err(write_err)?;
+    if let Some(changes) = &changes {
+        writeln!(
+            out,
+            "index: {} added, {} updated, {} removed, {} skipped as unchanged",
+            changes.added.len(),
+            changes.updated.len(),
+            changes.removed.len(),
+            changes.skipped.len()
+        )
+        .map_err(write_err)?;
+    }
+
+    if let Some(path) = report_path {
+        let mut json = report.to_json();
+        if let Some(changes) = &changes {
+            json["index"] = changes.to_json();
+        }
+        let json = serde_json::to_string_pretty(&json).map_err(|e| e.to_string())?;
+        if to_stdout {
+            println!("{json}");
+        } else {
+            fs::write(&path, json + "\n").map_err(|e| format!("{}: {e}", path.display()))?;
+        }
+    }
+    if let (Some(index), Some(path)) = (index, index_path) {
+        index
+            .save(&path)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+    }
+    if report.is_success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} files didn't import",
+            report.files.len() - report.count("imported")
+        ))
+    }
+}
+
+fn run_query(query: &str, index: &Path) -> Result<(), String> {
+    let query = query::Query::parse(query).map_err(|e| e.to_string())?;
+    let index = index::Index::load(index).map_err(|e| e.to_string())?;
+    for path in index.query(&query) {
+        println!("{path}");
+    }
+    Ok(())
+}
+
+fn run_search(query: &str, index: &Path, limit: usize) -> Result<(), String> {
+    let index = index::Index::load(index).map_err(|e| e.to_string())?;
+    for hit in search::TextIndex::build(&index).search(query, limit) {
+        println!("{:8.3}  {}", hit.score, hit.path);
+    }
+    Ok(())
+}
+
+fn take_snapshot(corpus: &Path, output: Option<PathBuf>) -> Result<(), String> {
+    let snapshot = snapshot::Snapshot::take(corpus, &batch::Options::default())
+        .map_err(|e| format!("{}: {e}", corpus.display()))?;
+    match output {
+        Some(path) => snapshot
+            .save(&path)
+            .map_err(|e| format!("{}: {e}", path.display())),
+        None => {
+            print!("{}", snapshot.to_json());
+            Ok(())
+        }
+    }
+}
+
+fn diff_snapshots(old: &Path, new: &Path) -> Result<(), String> {
+    let old = snapshot::Snapshot::load(old).map_err(|e| e.to_string())?;
+    let new = snapshot::Snapshot::load(new).map_err(|e| e.to_string())?;
+    let diff = snapshot::diff(&old, &new);
+    print!("{diff}");
+    if diff.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{} files differ", diff.files.len()))
+    }
+}
+
+fn verify_bundle(bundle: &Path) -> Result<(), String> {
+    for verified in verify::verify(bundle).map_err(|e| e.to_string())? {
+        println!("{}: ok", verified.bundle.display());
+        for image in &verified.images {
+            let uuid = image.uuid.map(|u| uuid_string(&u)).unwrap_or_default();
+            println!(
+                "  {} {} {uuid}",
+                image.arch_name(),
+                macho::file_type_name(image.file_type)
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "macos"))]
+fn print_build_info(bundle: &Path, format: Format) -> Result<(), String> {
+    let plugin = minimal_importer_host::PlugIn::create(bundle).map_err(|e| e.to_string())?;
+    let info = plugin
+        .build_info()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("{} has no build-info interface", bundle.display()))?;
+    output::print(&info, format, &mut std::io::stdout().lock()).map_err(|e| e.to_string())
+}
+
+#[cfg(target_os = "macos")]
+fn print_build_info(bundle: &Path, format: Format) -> Result<(), String> {
+    let info = minimal_importer_util::cfplugin::build_info(bundle)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("{} has no build-info interface", bundle.display()))?;
+    output::print(&info, format, &mut std::io::stdout().lock()).map_err(|e| e.to_string())
+}
+
+/// Where a reloading `watch` leaves its snapshot for the rebuilt util.
+const WATCH_STATE_VAR: &str = "MINIMAL_IMPORTER_WATCH_STATE";
+
+/// The importer sources `watch --rebuild` reloads on.
+const IMPORTER_SOURCES: &[&str] = &[
+    concat!(env!("CARGO_MANIFEST_DIR"), "/../minimal-importer/src"),
+    concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../minimal-importer/Cargo.toml"
+    ),
+];
+
+/// Rebuilds the util with cargo and replaces this process with the new
+/// binary, handing it `snapshot` so the next diff is against this run.
+fn reload(snapshot: &snapshot::Snapshot) -> Result<(), String> {
+    let mut cargo = std::process::Command::new(std::env::var_os("CARGO").unwrap_or("cargo".into()));
+    cargo
+        .args(["build", "-p", "minimal-importer-util"])
+        .current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/.."));
+    if !cfg!(debug_assertions) {
+        cargo.arg("--release");
+    }
+    let status = cargo.status().map_err(|e| format!("running cargo: {e}"))?;
+    if !status.success() {
+        return Err(format!("cargo build failed: {status}"));
+    }
+    let state = std::env::temp_dir().join(format!(
+        "minimal-importer-watch-{}.json",
+        std::process::id()
+    ));
+    snapshot
+        .save(&state)
+        .map_err(|e| format!("{}: {e}", state.display()))?;
+    let exe = std::env::current_exe().map_err(|e| e.to_string())?;
+    let mut util = std::process::Command::new(exe);
+    util.args(std::env::args_os().skip(1))
+        .env(WATCH_STATE_VAR, &state);
+    eprintln!("reloading");
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        Err(format!("reloading: {}", util.exec()))
+    }
+    #[cfg(not(unix))]
+    {
+        let status = util.status().map_err(|e| format!("reloading: {e}"))?;
+        std::process::exit(status.code().unwrap_or(1));
+    }
+}
+
+fn watch_paths(paths: Vec<PathBuf>, interval: f64, rebuild: bool) -> Result<(), String> {
+    let interval = Duration::try_from_secs_f64(interval).map_err(|e| format!("--interval: {e}"))?;
+    let previous = match std::env::var_os(WATCH_STATE_VAR) {
+        Some(state) => {
+            let state = PathBuf::from(state);
+            let snapshot = snapshot::Snapshot::load(&state).map_err(|e| e.to_string())?;
+            let _ = fs::remove_file(&state);
+            snapshot
+        }
+        None => snapshot::Snapshot::default(),
+    };
+    let sources: Vec<PathBuf> = IMPORTER_SOURCES.iter().map(PathBuf::from).collect();
+    let mut source_stamps = watch::scan(&sources);
+    let mut watch = watch::Watch::new(paths, batch::Options::default(), previous);
+    loop {
+        let diff = watch.poll();
+        if !diff.is_empty() {
+            print!("{diff}");
+            std::io::stdout().flush().map_err(|e| e.to_string())?;
+        }
+        std::thread::sleep(interval);
+        if rebuild {
+            let stamps = watch::scan(&sources);
+            if !watch::changed(&source_stamps, &stamps).is_empty() {
+                source_stamps = stamps;
+                // A failed build leaves the old importer running.
+                if let Err(e) = reload(watch.snapshot()) {
+                    eprintln!("error: {e}");
+                }
+            }
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Import {
+            file,
+            uti,
+            format,
+            name,
+            no_follow_symlinks,
+        } => import(file, uti, format, &name, open_options(no_follow_symlinks)),
+        Command::Uti {
+            name,
+            mime,
+            conforms_to,
+            plist,
+        } => resolve_types(&name, mime, conforms_to, &plist),
+        Command::InfoPlist { manifest, output } => generate_info_plist(&manifest, output),
+        Command::Bundle {
+            manifest,
+            binary,
+            out_dir,
+            name,
+            app,
+            app_binary,
+            app_name,
+        } => {
+            let app = app.map(|manifest| AppArgs {
+                manifest,
+                binary: app_binary,
+                name: app_name,
+            });
+            assemble_bundle(&manifest, binary, &out_dir, name, app)
+        }
+        Command::Batch {
+            dir,
+            jobs,
+            timeout,
+            report,
+            index,
+            hash,
+            no_follow_symlinks,
+        } => run_batch(
+            &dir,
+            jobs,
+            timeout,
+            report,
+            index,
+            hash,
+            open_options(no_follow_symlinks),
+        ),
+        Command::Query { query, index } => run_query(&query, &index),
+        Command::Search {
+            query,
+            index,
+            limit,
+        } => run_search(&query, &index, limit),
+        Command::Snapshot { corpus, output } => take_snapshot(&corpus, output),
+        Command::Diff { old, new } => diff_snapshots(&old, &new),
+        Command::Verify { bundle } => verify_bundle(&bundle),
+        Command::BuildInfo { bundle, format } => print_build_info(&bundle, format),
+        Command::Watch {
+            paths,
+            interval,
+            rebuild,
+        } => watch_paths(paths, interval, rebuild),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,358 @@
+//! Printing and reading attributes in the format `mdls` uses.
+//!
+//! Keys are padded to the longest one and followed by ` = `. Top-level
+//! strings are quoted, dates print as `2000-02-29 00:00:00 +0000`, requested
+//! attributes that aren't set print as `(null)`, and arrays use the
+//! `NSArray` description format: one item per line, indented four spaces,
+//! with strings quoted and non-ASCII escaped unless they are plain words.
+
+use std::io::{self, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use minimal_importer::{AttrValue, Attributes};
+
+use crate::output::utc_fields;
+use crate::{Error, Result};
+
+const NULL: &str = "(null)";
+const INDENT: &str = "    ";
+
+/// Prints every attribute in `attrs`, or only `names` in that order if
+/// given, the way `mdls [-name name ...]` does.
+pub fn print(attrs: &Attributes, names: Option<&[String]>, out: &mut impl Write) -> io::Result<()> {
+    let keys: Vec<&str> = match names {
+        Some(names) => names.iter().map(String::as_str).collect(),
+        None => attrs.iter().map(|(k, _)| k).collect(),
+    };
+    let width = keys.iter().map(|k| k.len()).max().unwrap_or(0);
+    for key in keys {
+        let value = attrs.get(key).map_or_else(|| NULL.to_owned(), top_level);
+        writeln!(out, "{key:<width$} = {value}")?;
+    }
+    Ok(())
+}
+
+pub fn to_string(attrs: &Attributes, names: Option<&[String]>) -> String {
+    let mut out = Vec::new();
+    print(attrs, names, &mut out).expect("writing to a Vec can't fail");
+    String::from_utf8(out).expect("mdls output is UTF-8")
+}
+
+fn top_level(value: &AttrValue) -> String {
+    match value {
+        AttrValue::String(s) => format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")),
+        AttrValue::Array(values) => {
+            let items: Vec<String> = values.iter().map(array_item).collect();
+            if items.is_empty() {
+                "(\n)".to_owned()
+            } else {
+                format!("(\n{INDENT}{}\n)", items.join(&format!(",\n{INDENT}")))
+            }
+        }
+        other => scalar(other),
+    }
+}
+
+fn scalar(value: &AttrValue) -> String {
+    match value {
+        AttrValue::Integer(i) => i.to_string(),
+        AttrValue::Real(f) => f.to_string(),
+        AttrValue::Bool(b) => u8::from(*b).to_string(),
+        AttrValue::Date(t) => date(*t),
+        AttrValue::String(s) => s.clone(),
+        AttrValue::Array(_) => unreachable!("arrays are handled by the caller"),
+    }
+}
+
+/// Strings `NSArray` prints without quotes.
+fn is_plain_word(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+fn array_item(value: &AttrValue) -> String {
+    match value {
+        AttrValue::String(s) if is_plain_word(s) => s.clone(),
+        AttrValue::String(s) => {
+            let mut quoted = String::from("\"");
+            for c in s.chars() {
+                match c {
+                    '"' => quoted.push_str("\\\""),
+                    '\\' => quoted.push_str("\\\\"),
+                    '\n' => quoted.push_str("\\n"),
+                    '\t' => quoted.push_str("\\t"),
+                    c if c.is_ascii() && !c.is_ascii_control() => quoted.push(c),
+                    c => {
+                        let mut units = [0; 2];
+                        for unit in c.encode_utf16(&mut units) {
+                            quoted.push_str(&format!("\\U{unit:04x}"));
+                        }
+                    }
+                }
+            }
+            quoted.push('"');
+            quoted
+        }
+        // NSArray flattens nested arrays onto one line; Spotlight doesn't
+        // store them, so keep it simple.
+        AttrValue::Array(values) => {
+            let items: Vec<String> = values.iter().map(array_item).collect();
+            format!("({})", items.join(", "))
+        }
+        other => scalar(other),
+    }
+}
+
+fn date(t: SystemTime) -> String {
+    let (y, mo, d, h, mi, s) = utc_fields(t);
+    format!("{y:04}-{mo:02}-{d:02} {h:02}:{mi:02}:{s:02} +0000")
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date; Howard Hinnant's
+/// days_from_civil.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Parses `YYYY-MM-DD HH:MM:SS ±HHMM`.
+fn parse_date(s: &str) -> Option<SystemTime> {
+    let (date, rest) = s.split_once(' ')?;
+    let (time, zone) = rest.split_once(' ')?;
+    let num = |s: &str| -> Option<i64> {
+        s.bytes()
+            .all(|b| b.is_ascii_digit())
+            .then(|| s.parse().ok())?
+    };
+    let mut date = date.splitn(3, '-').map(num);
+    let (y, mo, d) = (date.next()??, date.next()??, date.next()??);
+    let mut time = time.splitn(3, ':').map(num);
+    let (h, mi, sec) = (time.next()??, time.next()??, time.next()??);
+    let (sign, zone) = match zone.split_at_checked(1)? {
+        ("+", z) => (1, z),
+        ("-", z) => (-1, z),
+        _ => return None,
+    };
+    if zone.len() != 4 || !(1..=12).contains(&mo) || !(1..=31).contains(&d) {
+        return None;
+    }
+    let offset = sign * (num(&zone[..2])? * 3600 + num(&zone[2..])? * 60);
+    let secs = days_from_civil(y, mo, d) * 86400 + h * 3600 + mi * 60 + sec - offset;
+    Some(if secs >= 0 {
+        UNIX_EPOCH + Duration::from_secs(secs as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())
+    })
+}
+
+fn unquote(s: &str) -> Option<String> {
+    let inner = s.strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars().peekable();
+    let mut pending_high: Option<u16> = None;
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => out.push('\n'),
+            't' => out.push('\t'),
+            'U' => {
+                let hex: String = chars.by_ref().take(4).collect();
+                let unit = u16::from_str_radix(&hex, 16).ok()?;
+                match pending_high.take() {
+                    Some(high) => out.push(char::decode_utf16([high, unit]).next()?.ok()?),
+                    None if (0xd800..0xdc00).contains(&unit) => pending_high = Some(unit),
+                    None => out.push(char::from_u32(u32::from(unit))?),
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
+
+/// Reads one value the way [`print`] writes it. Numbers stay numbers, so
+/// booleans, which `mdls` prints as `0` and `1`, come back as integers.
+fn parse_scalar(s: &str) -> AttrValue {
+    if let Some(unquoted) = unquote(s) {
+        return AttrValue::String(unquoted);
+    }
+    if let Some(t) = parse_date(s) {
+        return AttrValue::Date(t);
+    }
+    if let Ok(i) = s.parse::<i64>() {
+        return AttrValue::Integer(i);
+    }
+    if s.contains(['.', 'e', 'E'])
+        && let Ok(f) = s.parse::<f64>()
+    {
+        return AttrValue::Real(f);
+    }
+    AttrValue::String(s.to_owned())
+}
+
+/// True if `s` is a whole quoted string: its last quote isn't escaped.
+fn is_closed(s: &str) -> bool {
+    let Some(body) = s.strip_suffix('"').filter(|_| s.len() >= 2) else {
+        return false;
+    };
+    body.bytes().rev().take_while(|&b| b == b'\\').count() % 2 == 0
+}
+
+/// Reads `mdls` output back into attributes, plus every key in the order
+/// printed so `(null)` entries can be reproduced.
+pub fn parse(transcript: &str) -> Result<(Attributes, Vec<String>)> {
+    let mut attrs = Attributes::new();
+    let mut names = Vec::new();
+    let mut lines = transcript.lines().enumerate();
+    while let Some((n, line)) = lines.next() {
+        let invalid = |what: &str| Error::Invalid(format!("line {}: {what}", n + 1));
+        let (key, value) = line
+            .split_once(" = ")
+            .ok_or_else(|| invalid("expected `key = value`"))?;
+        let key = key.trim_end().to_owned();
+        names.push(key.clone());
+        match value {
+            NULL => {}
+            "(" => {
+                let mut items = Vec::new();
+                loop {
+                    let (_, item) = lines.next().ok_or_else(|| invalid("unterminated array"))?;
+                    if item == ")" {
+                        break;
+                    }
+                    let item = item.trim_start();
+                    items.push(parse_scalar(item.strip_suffix(',').unwrap_or(item)));
+                }
+                attrs.insert(key, AttrValue::Array(items));
+            }
+            value if value.starts_with('"') => {
+                // Top-level strings are printed raw, newlines and all.
+                let mut value = value.to_owned();
+                while !is_closed(&value) {
+                    let (_, more) = lines.next().ok_or_else(|| invalid("unterminated string"))?;
+                    value.push('\n');
+                    value.push_str(more);
+                }
+                attrs.insert(key, parse_scalar(&value));
+            }
+            value => attrs.insert(key, parse_scalar(value)),
+        }
+    }
+    Ok((attrs, names))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn transcript(name: &str) -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/mdls")
+            .join(name);
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    /// The `-name`s `testdata/mdls/test.great.txt` was made with.
+    const TEST_GREAT_NAMES: [&str; 4] = [
+        "kMDItemDescription",
+        "kMDItemKeywords",
+        "kMDItemLanguages",
+        "kMDItemAuthors",
+    ];
+
+    #[test]
+    fn prints_test_great_like_mdls() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../test.great");
+        let mut attrs = Attributes::new();
+        minimal_importer::import_file(&mut attrs, "vin.je.great", &path).unwrap();
+        let names = TEST_GREAT_NAMES.map(str::to_owned);
+        assert_eq!(
+            to_string(&attrs, Some(&names)),
+            transcript("test.great.txt")
+        );
+    }
+
+    #[test]
+    fn parses_what_it_prints() {
+        let transcript = transcript("test.great.txt");
+        let (attrs, names) = parse(&transcript).unwrap();
+        assert_eq!(names, TEST_GREAT_NAMES);
+        assert_eq!(attrs.get("kMDItemAuthors"), None);
+        assert_eq!(to_string(&attrs, Some(&names)), transcript);
+
+        let text = "kMDItemTextContent = \"two\nlines \\\"quoted\\\"\"\n";
+        let (attrs, _) = parse(text).unwrap();
+        assert_eq!(
+            attrs.get("kMDItemTextContent"),
+            Some(&AttrValue::from("two\nlines \"quoted\""))
+        );
+        assert_eq!(to_string(&attrs, None), text);
+    }
+
+    #[test]
+    fn formats_values() {
+        let mut attrs = Attributes::new();
+        attrs.insert("kMDItemDescription", "say \"hi\"");
+        attrs.insert("kMDItemKeywords", vec!["hello", "küche", "two words"]);
+        attrs.insert("kMDItemLanguages", Vec::<String>::new());
+        attrs.insert("kMDItemFSSize", 42_i64);
+        attrs.insert("kMDItemFSInvisible", false);
+        attrs.insert(
+            "kMDItemContentCreationDate",
+            UNIX_EPOCH + Duration::from_secs(951_782_400),
+        );
+        let names: Vec<String> = [
+            "kMDItemDescription",
+            "kMDItemKeywords",
+            "kMDItemLanguages",
+            "kMDItemFSSize",
+            "kMDItemFSInvisible",
+            "kMDItemContentCreationDate",
+            "kMDItemAuthors",
+        ]
+        .map(str::to_owned)
+        .to_vec();
+        assert_eq!(
+            to_string(&attrs, Some(&names)),
+            "kMDItemDescription         = \"say \\\"hi\\\"\"\n\
+             kMDItemKeywords            = (\n    \
+             hello,\n    \
+             \"k\\U00fcche\",\n    \
+             \"two words\"\n\
+             )\n\
+             kMDItemLanguages           = (\n\
+             )\n\
+             kMDItemFSSize              = 42\n\
+             kMDItemFSInvisible         = 0\n\
+             kMDItemContentCreationDate = 2000-02-29 00:00:00 +0000\n\
+             kMDItemAuthors             = (null)\n"
+        );
+    }
+
+    #[test]
+    fn parses_dates_and_escapes() {
+        assert_eq!(
+            parse_date("2000-02-29 01:00:00 +0100"),
+            Some(UNIX_EPOCH + Duration::from_secs(951_782_400))
+        );
+        assert_eq!(
+            parse_date("1969-12-31 23:59:59 +0000"),
+            Some(UNIX_EPOCH - Duration::from_secs(1))
+        );
+        assert_eq!(parse_date("2000-13-01 00:00:00 +0000"), None);
+        assert_eq!(
+            unquote(r#""\Ud83d\Ude00 \"x\"""#).as_deref(),
+            Some("😀 \"x\"")
+        );
+    }
+}
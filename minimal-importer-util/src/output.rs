@@ -0,0 +1,181 @@
+//! Printing imported attributes in the formats the util supports.
+
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::ValueEnum;
+use minimal_importer::{AttrValue, Attributes};
+
+/// Longest string value shown in full by the table format.
+const TABLE_MAX_CHARS: usize = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Aligned key/value columns for reading in a terminal.
+    Table,
+    Json,
+    /// An XML property list, as Spotlight would store it.
+    Plist,
+    /// What `mdls` prints, for comparing against a Mac.
+    Mdls,
+}
+
+pub fn print(attrs: &Attributes, format: Format, out: &mut impl Write) -> io::Result<()> {
+    match format {
+        Format::Table => print_table(attrs, out),
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *out, &to_json(attrs))?;
+            writeln!(out)
+        }
+        Format::Plist => {
+            to_plist(attrs)
+                .to_writer_xml(&mut *out)
+                .map_err(io::Error::other)?;
+            writeln!(out)
+        }
+        Format::Mdls => crate::mdls::print(attrs, None, out),
+    }
+}
+
+fn print_table(attrs: &Attributes, out: &mut impl Write) -> io::Result<()> {
+    let width = attrs.iter().map(|(k, _)| k.len()).max().unwrap_or(0);
+    for (key, value) in attrs.iter() {
+        writeln!(out, "{key:<width$}  {}", table_value(value))?;
+    }
+    Ok(())
+}
+
+fn table_value(value: &AttrValue) -> String {
+    match value {
+        AttrValue::String(s) => {
+            let escaped = s.escape_debug().to_string();
+            if escaped.chars().count() > TABLE_MAX_CHARS {
+                let cut: String = escaped.chars().take(TABLE_MAX_CHARS).collect();
+                format!("{cut}…")
+            } else {
+                escaped
+            }
+        }
+        AttrValue::Integer(i) => i.to_string(),
+        AttrValue::Real(f) => f.to_string(),
+        AttrValue::Bool(b) => b.to_string(),
+        AttrValue::Date(t) => iso8601(*t),
+        AttrValue::Array(values) => values
+            .iter()
+            .map(table_value)
+            .collect::<Vec<_>>()
+            .join(", "),
+    }
+}
+
+pub fn to_json(attrs: &Attributes) -> serde_json::Value {
+    attrs
+        .iter()
+        .map(|(k, v)| (k.to_owned(), json_value(v)))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+fn json_value(value: &AttrValue) -> serde_json::Value {
+    match value {
+        AttrValue::String(s) => s.as_str().into(),
+        AttrValue::Integer(i) => (*i).into(),
+        AttrValue::Real(f) => (*f).into(),
+        AttrValue::Bool(b) => (*b).into(),
+        AttrValue::Date(t) => iso8601(*t).into(),
+        AttrValue::Array(values) => values.iter().map(json_value).collect(),
+    }
+}
+
+pub fn to_plist(attrs: &Attributes) -> plist::Value {
+    attrs
+        .iter()
+        .map(|(k, v)| (k.to_owned(), plist_value(v)))
+        .collect::<plist::Dictionary>()
+        .into()
+}
+
+fn plist_value(value: &AttrValue) -> plist::Value {
+    match value {
+        AttrValue::String(s) => s.as_str().into(),
+        AttrValue::Integer(i) => (*i).into(),
+        AttrValue::Real(f) => (*f).into(),
+        AttrValue::Bool(b) => (*b).into(),
+        AttrValue::Date(t) => plist::Date::from(*t).into(),
+        AttrValue::Array(values) => values.iter().map(plist_value).collect::<Vec<_>>().into(),
+    }
+}
+
+/// Splits `t` into UTC `(year, month, day, hour, minute, second)`.
+pub fn utc_fields(t: SystemTime) -> (i64, u32, u32, u32, u32, u32) {
+    let secs = match t.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs_f64().ceil() as i64),
+    };
+    let (days, rem) = (secs.div_euclid(86400), secs.rem_euclid(86400) as u32);
+    // Howard Hinnant's civil_from_days.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day, rem / 3600, rem / 60 % 60, rem % 60)
+}
+
+fn iso8601(t: SystemTime) -> String {
+    let (y, mo, d, h, mi, s) = utc_fields(t);
+    format!("{y:04}-{mo:02}-{d:02}T{h:02}:{mi:02}:{s:02}Z")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn sample() -> Attributes {
+        let mut attrs = Attributes::new();
+        attrs.insert("kMDItemDescription", "hello\tthere");
+        attrs.insert("kMDItemKeywords", vec!["world", "blank"]);
+        attrs.insert(
+            "kMDItemContentCreationDate",
+            UNIX_EPOCH + Duration::from_secs(951_782_400),
+        );
+        attrs
+    }
+
+    #[test]
+    fn table() {
+        let mut out = Vec::new();
+        print(&sample(), Format::Table, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "kMDItemContentCreationDate  2000-02-29T00:00:00Z\n\
+             kMDItemDescription          hello\\tthere\n\
+             kMDItemKeywords             world, blank\n"
+        );
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(
+            to_json(&sample()),
+            serde_json::json!({
+                "kMDItemContentCreationDate": "2000-02-29T00:00:00Z",
+                "kMDItemDescription": "hello\tthere",
+                "kMDItemKeywords": ["world", "blank"],
+            })
+        );
+    }
+
+    #[test]
+    fn plist_round_trips() {
+        let mut out = Vec::new();
+        print(&sample(), Format::Plist, &mut out).unwrap();
+        let parsed = plist::Value::from_reader_xml(&out[..]).unwrap();
+        assert_eq!(parsed, to_plist(&sample()));
+    }
+}
@@ -0,0 +1,599 @@
+//! The subset of Spotlight's query language our search expectations use.
+//!
+//! Supported are comparisons with `==`, `!=`, `<`, `>`, `<=` and `>=`,
+//! `InRange(attribute, low, high)`, grouping with parentheses, `&&` and
+//! `||`. String values may contain `*` and `?` wildcards and be followed by
+//! the `c` (case-insensitive), `d` (diacritic-insensitive) and `w`
+//! (word-based) modifiers, as in `kMDItemDescription == "*great*"cd`; a bare
+//! `*` matches any value. `$time` functions and the rest of the syntax
+//! aren't supported.
+//!
+//! Queries run against attributes in the JSON form [`output::to_json`]
+//! produces, so dates compare as ISO 8601 strings.
+//!
+//! [`output::to_json`]: crate::output::to_json
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+
+use crate::{Error, Result};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    /// `c`: ignore case.
+    pub case: bool,
+    /// `d`: ignore diacritics.
+    pub diacritics: bool,
+    /// `w`: match against each word rather than the whole string.
+    pub word: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Literal {
+    String(String, Modifiers),
+    Number(f64),
+    /// A bare `*`: any value at all.
+    Any,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Query {
+    Compare {
+        attribute: String,
+        op: Op,
+        value: Literal,
+    },
+    /// Inclusive at both ends.
+    InRange {
+        attribute: String,
+        low: Literal,
+        high: Literal,
+    },
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Literal(Literal),
+    Op(Op),
+    And,
+    Or,
+    Open,
+    Close,
+    Comma,
+}
+
+fn error(at: usize, what: impl std::fmt::Display) -> Error {
+    Error::Invalid(format!("query: {what} at offset {at}"))
+}
+
+fn lex(source: &str) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(at, c)) = chars.peek() {
+        let two = source.get(at..at + 2).unwrap_or_default();
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '(' | ')' | ',' | '*' => {
+                chars.next();
+                match c {
+                    '(' => Token::Open,
+                    ')' => Token::Close,
+                    ',' => Token::Comma,
+                    _ => Token::Literal(Literal::Any),
+                }
+            }
+            '=' | '!' | '<' | '>' | '&' | '|' => {
+                let (token, len) = match two {
+                    "==" => (Token::Op(Op::Eq), 2),
+                    "!=" => (Token::Op(Op::Ne), 2),
+                    "<=" => (Token::Op(Op::Le), 2),
+                    ">=" => (Token::Op(Op::Ge), 2),
+                    "&&" => (Token::And, 2),
+                    "||" => (Token::Or, 2),
+                    _ if c == '<' => (Token::Op(Op::Lt), 1),
+                    _ if c == '>' => (Token::Op(Op::Gt), 1),
+                    _ => return Err(error(at, format!("unexpected `{c}`"))),
+                };
+                chars.nth(len - 1);
+                token
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        None => return Err(error(at, "unterminated string")),
+                        Some((_, q)) if q == c => break,
+                        // Keep escapes so `\*` stays a literal star for the
+                        // wildcard matcher; quotes lose theirs.
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, q)) if q == c => s.push(q),
+                            Some((_, e)) => {
+                                s.push('\\');
+                                s.push(e);
+                            }
+                            None => return Err(error(at, "unterminated string")),
+                        },
+                        Some((_, ch)) => s.push(ch),
+                    }
+                }
+                let mut modifiers = Modifiers::default();
+                while let Some(&(m_at, m)) = chars.peek() {
+                    match m {
+                        'c' => modifiers.case = true,
+                        'd' => modifiers.diacritics = true,
+                        'w' => modifiers.word = true,
+                        m if m.is_alphanumeric() => {
+                            return Err(error(m_at, format!("unknown modifier `{m}`")));
+                        }
+                        _ => break,
+                    }
+                    chars.next();
+                }
+                Token::Literal(Literal::String(s, modifiers))
+            }
+            c if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' => {
+                let mut end = at;
+                while let Some(&(i, d)) = chars.peek() {
+                    if !(d.is_ascii_alphanumeric() || matches!(d, '-' | '+' | '.')) {
+                        break;
+                    }
+                    end = i + d.len_utf8();
+                    chars.next();
+                }
+                let text = &source[at..end];
+                let n = text
+                    .parse()
+                    .map_err(|_| error(at, format!("bad number `{text}`")))?;
+                Token::Literal(Literal::Number(n))
+            }
+            c if c.is_alphabetic() || c == '_' || c == '$' => {
+                let mut end = at;
+                while let Some(&(i, d)) = chars.peek() {
+                    if !(d.is_alphanumeric() || matches!(d, '_' | ':' | '.' | '$')) {
+                        break;
+                    }
+                    end = i + d.len_utf8();
+                    chars.next();
+                }
+                Token::Ident(source[at..end].to_owned())
+            }
+            c => return Err(error(at, format!("unexpected `{c}`"))),
+        };
+        tokens.push((at, token));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    len: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.len, |&(at, _)| at)
+    }
+
+    fn next(&mut self, expected: &str) -> Result<Token> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .map(|(_, t)| t.clone())
+            .ok_or_else(|| error(self.len, format!("expected {expected}, found the end")))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, token: Token, expected: &str) -> Result<()> {
+        let at = self.offset();
+        if self.next(expected)? == token {
+            Ok(())
+        } else {
+            Err(error(at, format!("expected {expected}")))
+        }
+    }
+
+    fn literal(&mut self) -> Result<Literal> {
+        let at = self.offset();
+        match self.next("a value")? {
+            Token::Literal(literal) => Ok(literal),
+            Token::Ident(name) if name.starts_with('$') => {
+                Err(error(at, format!("`{name}` isn't supported")))
+            }
+            _ => Err(error(at, "expected a value")),
+        }
+    }
+
+    fn or(&mut self) -> Result<Query> {
+        let mut query = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            query = Query::Or(Box::new(query), Box::new(self.and()?));
+        }
+        Ok(query)
+    }
+
+    fn and(&mut self) -> Result<Query> {
+        let mut query = self.primary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            query = Query::And(Box::new(query), Box::new(self.primary()?));
+        }
+        Ok(query)
+    }
+
+    fn primary(&mut self) -> Result<Query> {
+        let at = self.offset();
+        match self.next("a comparison")? {
+            Token::Open => {
+                let query = self.or()?;
+                self.expect(Token::Close, "`)`")?;
+                Ok(query)
+            }
+            Token::Ident(name) if name == "InRange" => {
+                self.expect(Token::Open, "`(`")?;
+                let at = self.offset();
+                let Token::Ident(attribute) = self.next("an attribute")? else {
+                    return Err(error(at, "expected an attribute"));
+                };
+                self.expect(Token::Comma, "`,`")?;
+                let low = self.literal()?;
+                self.expect(Token::Comma, "`,`")?;
+                let high = self.literal()?;
+                self.expect(Token::Close, "`)`")?;
+                Ok(Query::InRange {
+                    attribute,
+                    low,
+                    high,
+                })
+            }
+            Token::Ident(attribute) => {
+                let at = self.offset();
+                let Token::Op(op) = self.next("an operator")? else {
+                    return Err(error(at, "expected an operator"));
+                };
+                let value = self.literal()?;
+                Ok(Query::Compare {
+                    attribute,
+                    op,
+                    value,
+                })
+            }
+            _ => Err(error(at, "expected a comparison")),
+        }
+    }
+}
+
+/// `s` folded as `modifiers` ask. Diacritics go by decomposing to NFD and
+/// dropping the combining marks, so `é` becomes `e` in either form.
+fn normalize(s: &str, modifiers: Modifiers) -> String {
+    let stripped: String;
+    let s = if modifiers.diacritics {
+        stripped = s.nfd().filter(|&c| !is_combining_mark(c)).collect();
+        &stripped
+    } else {
+        s
+    };
+    s.chars()
+        .flat_map(|c| {
+            let lower = modifiers.case.then(|| c.to_lowercase());
+            lower
+                .into_iter()
+                .flatten()
+                .chain((!modifiers.case).then_some(c))
+        })
+        .collect()
+}
+
+/// Matches `text` against `pattern`, where `*` is any run of characters,
+/// `?` any one character and `\` escapes the next.
+fn wildcard_match(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+                continue;
+            }
+            Some('?') => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            Some('\\') if pattern.get(p + 1) == Some(&text[t]) => {
+                p += 2;
+                t += 1;
+                continue;
+            }
+            Some(&c) if c != '\\' && c == text[t] => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            _ => {}
+        }
+        let Some((star, from)) = backtrack else {
+            return false;
+        };
+        backtrack = Some((star, from + 1));
+        p = star + 1;
+        t = from + 1;
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// The words in `s`, split at non-alphanumerics and lower-to-upper case
+/// changes, as the `w` modifier sees them.
+fn words(s: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut prev_lower = false;
+    for c in s.chars() {
+        if (!c.is_alphanumeric() || (prev_lower && c.is_uppercase())) && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        if c.is_alphanumeric() {
+            word.push(c);
+        }
+        prev_lower = c.is_lowercase();
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+fn string_matches(value: &str, pattern: &str, modifiers: Modifiers) -> bool {
+    let pattern: Vec<char> = normalize(pattern, modifiers).chars().collect();
+    let matches = |s: &str| {
+        let s: Vec<char> = normalize(s, modifiers).chars().collect();
+        wildcard_match(&pattern, &s)
+    };
+    if modifiers.word {
+        words(value).iter().any(|w| matches(w))
+    } else {
+        matches(value)
+    }
+}
+
+/// How a scalar attribute value orders against a literal, if they're
+/// comparable at all.
+fn order(value: &Value, literal: &Literal) -> Option<Ordering> {
+    match (value, literal) {
+        (Value::String(s), Literal::String(l, modifiers)) => {
+            Some(normalize(s, *modifiers).cmp(&normalize(l, *modifiers)))
+        }
+        (Value::Number(n), Literal::Number(l)) => n.as_f64()?.partial_cmp(l),
+        (Value::Number(n), Literal::String(l, _)) => n.as_f64()?.partial_cmp(&l.parse().ok()?),
+        (Value::Bool(b), Literal::Number(l)) => f64::from(u8::from(*b)).partial_cmp(l),
+        _ => None,
+    }
+}
+
+fn equals(value: &Value, literal: &Literal) -> bool {
+    match (value, literal) {
+        (_, Literal::Any) => true,
+        (Value::String(s), Literal::String(pattern, modifiers)) => {
+            string_matches(s, pattern, *modifiers)
+        }
+        _ => order(value, literal) == Some(Ordering::Equal),
+    }
+}
+
+fn compare(value: &Value, op: Op, literal: &Literal) -> bool {
+    match op {
+        Op::Eq => equals(value, literal),
+        Op::Ne => !equals(value, literal),
+        Op::Lt => order(value, literal) == Some(Ordering::Less),
+        Op::Gt => order(value, literal) == Some(Ordering::Greater),
+        Op::Le => matches!(
+            order(value, literal),
+            Some(Ordering::Less | Ordering::Equal)
+        ),
+        Op::Ge => matches!(
+            order(value, literal),
+            Some(Ordering::Greater | Ordering::Equal)
+        ),
+    }
+}
+
+/// The attribute's value, or each element of an array value.
+fn scalars(value: &Value) -> &[Value] {
+    match value {
+        Value::Array(values) => values,
+        value => std::slice::from_ref(value),
+    }
+}
+
+impl Query {
+    pub fn parse(source: &str) -> Result<Query> {
+        let mut parser = Parser {
+            tokens: lex(source)?,
+            pos: 0,
+            len: source.len(),
+        };
+        let query = parser.or()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(error(parser.offset(), "expected `&&`, `||` or the end"));
+        }
+        Ok(query)
+    }
+
+    /// Whether a file with `attributes` matches. Comparisons against an
+    /// attribute the file doesn't have are false, even with `!=`; array
+    /// attributes match `==` if any element does and `!=` if none does.
+    pub fn matches(&self, attributes: &BTreeMap<String, Value>) -> bool {
+        match self {
+            Query::Compare {
+                attribute,
+                op,
+                value,
+            } => attributes.get(attribute).is_some_and(|v| match op {
+                Op::Ne => scalars(v).iter().all(|s| compare(s, Op::Ne, value)),
+                op => scalars(v).iter().any(|s| compare(s, *op, value)),
+            }),
+            Query::InRange {
+                attribute,
+                low,
+                high,
+            } => attributes.get(attribute).is_some_and(|v| {
+                scalars(v)
+                    .iter()
+                    .any(|s| compare(s, Op::Ge, low) && compare(s, Op::Le, high))
+            }),
+            Query::And(a, b) => a.matches(attributes) && b.matches(attributes),
+            Query::Or(a, b) => a.matches(attributes) || b.matches(attributes),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn attrs() -> BTreeMap<String, Value> {
+        serde_json::from_value(json!({
+            "kMDItemDescription": "This is GREAT news",
+            "kMDItemDisplayName": "Crème brûlée",
+            "kMDItemKeywords": ["great", "news", "FooBar"],
+            "kMDItemFSSize": 1200,
+            "kMDItemContentCreationDate": "2024-03-01T18:22:05Z",
+        }))
+        .unwrap()
+    }
+
+    fn check(query: &str) -> bool {
+        Query::parse(query)
+            .unwrap_or_else(|e| panic!("{query}: {e}"))
+            .matches(&attrs())
+    }
+
+    #[test]
+    fn parses() {
+        assert_eq!(
+            Query::parse(r#"kMDItemDescription == "*GREAT*"cd || InRange(kMDItemFSSize, 1, 2)"#)
+                .unwrap(),
+            Query::Or(
+                Box::new(Query::Compare {
+                    attribute: "kMDItemDescription".to_owned(),
+                    op: Op::Eq,
+                    value: Literal::String(
+                        "*GREAT*".to_owned(),
+                        Modifiers {
+                            case: true,
+                            diacritics: true,
+                            word: false
+                        }
+                    ),
+                }),
+                Box::new(Query::InRange {
+                    attribute: "kMDItemFSSize".to_owned(),
+                    low: Literal::Number(1.0),
+                    high: Literal::Number(2.0),
+                }),
+            )
+        );
+        for bad in [
+            "",
+            "kMDItemFSSize ==",
+            r#"kMDItemDescription == "great"x"#,
+            r#"kMDItemDescription == "great"#,
+            "kMDItemFSSize = 1",
+            "(kMDItemFSSize == 1",
+            "kMDItemFSSize == 1 kMDItemFSSize == 2",
+            "kMDItemContentCreationDate > $time.today",
+        ] {
+            assert!(Query::parse(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn string_comparisons() {
+        assert!(check(r#"kMDItemDescription == "*GREAT*""#));
+        assert!(!check(r#"kMDItemDescription == "*great*""#));
+        assert!(check(r#"kMDItemDescription == "*great*"c"#));
+        assert!(check(r#"kMDItemDescription == "this is great news"c"#));
+        assert!(!check(r#"kMDItemDescription == "great"c"#));
+        assert!(check(r#"kMDItemDescription == "great"cw"#));
+        assert!(check(r#"kMDItemDescription == "n?ws"w"#));
+        assert!(check(r#"kMDItemDisplayName == "creme brulee"cd"#));
+        assert!(!check(r#"kMDItemDisplayName == "creme brulee"c"#));
+        // Decomposed, and beyond Latin-1.
+        assert!(check("kMDItemDisplayName == \"cre\u{300}me brulee\"cd"));
+        let d = Modifiers {
+            diacritics: true,
+            ..Modifiers::default()
+        };
+        assert_eq!(normalize("Dvořák, Łódź, Ærø", d), "Dvorak, Łodz, Ærø");
+        assert!(check(r#"kMDItemDescription != "*bad*""#));
+        assert!(check(r#"kMDItemDescription < "U""#));
+        assert!(check("kMDItemDescription == *"));
+        assert!(!check("kMDItemAuthors == *"));
+        assert!(!check(r#"kMDItemAuthors != "x""#));
+    }
+
+    #[test]
+    fn arrays_numbers_and_logic() {
+        assert!(check(r#"kMDItemKeywords == "news""#));
+        assert!(check(r#"kMDItemKeywords == "bar"cw"#));
+        assert!(!check(r#"kMDItemKeywords != "news""#));
+        assert!(check("kMDItemFSSize > 1000 && kMDItemFSSize <= 1200"));
+        assert!(!check("kMDItemFSSize < 1000"));
+        assert!(check("InRange(kMDItemFSSize, 1000, 2000)"));
+        assert!(check(
+            r#"InRange(kMDItemContentCreationDate, "2024-01-01", "2025-01-01")"#
+        ));
+        assert!(check(
+            r#"kMDItemFSSize == 1 || (kMDItemKeywords == "great" && kMDItemFSSize >= 1.2e3)"#
+        ));
+        assert!(!check(r#"kMDItemFSSize == 1 || kMDItemKeywords == "none""#));
+    }
+
+    #[test]
+    fn wildcards() {
+        let m = |p: &str, t: &str| {
+            wildcard_match(
+                &p.chars().collect::<Vec<_>>(),
+                &t.chars().collect::<Vec<_>>(),
+            )
+        };
+        assert!(m("*", ""));
+        assert!(m("a*b*c", "aXbYbc"));
+        assert!(!m("a*b", "ac"));
+        assert!(m(r"\*", "*"));
+        assert!(!m(r"\*", "x"));
+        assert_eq!(
+            words("helloWorld foo-bar"),
+            ["hello", "World", "foo", "bar"]
+        );
+    }
+}
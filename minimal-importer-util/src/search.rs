@@ -0,0 +1,306 @@
+//! Ranked full-text search over the `kMDItemTextContent` in an [`Index`].
+//!
+//! Text goes through [`text::tokenize`], the same tokenizer keyword
+//! extraction uses, into an inverted index of term positions. A search is
+//! a list of clauses: plain words, `prefix*` words and `"quoted phrases"`.
+//! Files matching any clause are ranked by BM25, with a phrase scored as
+//! one term whose frequency is the number of times the phrase occurs and a
+//! prefix as every term it expands to.
+//!
+//! [`text::tokenize`]: minimal_importer::text::tokenize
+
+use std::collections::{BTreeMap, HashMap};
+
+use minimal_importer::keys::kMDItemTextContent;
+use minimal_importer::text;
+use serde_json::Value;
+
+use crate::index::Index;
+
+/// BM25 term frequency saturation.
+const K1: f64 = 1.2;
+/// BM25 document length normalization.
+const B: f64 = 0.75;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Clause {
+    Term(String),
+    /// Every term starting with this.
+    Prefix(String),
+    /// Terms that must appear consecutively.
+    Phrase(Vec<String>),
+}
+
+/// Splits a search into clauses. Quoted text is a phrase; elsewhere each
+/// token is a term, and a `*` right after a word makes it a prefix.
+pub fn parse(query: &str) -> Vec<Clause> {
+    let mut clauses = Vec::new();
+    for (i, part) in query.split('"').enumerate() {
+        if i % 2 == 1 {
+            let mut terms: Vec<String> = text::tokenize(part).collect();
+            match terms.len() {
+                0 => {}
+                1 => clauses.push(Clause::Term(terms.remove(0))),
+                _ => clauses.push(Clause::Phrase(terms)),
+            }
+            continue;
+        }
+        for word in part.split_whitespace() {
+            let prefix = word.ends_with('*');
+            let mut terms: Vec<String> = text::tokenize(word).collect();
+            let last = terms.pop();
+            clauses.extend(terms.into_iter().map(Clause::Term));
+            if let Some(last) = last {
+                clauses.push(if prefix {
+                    Clause::Prefix(last)
+                } else {
+                    Clause::Term(last)
+                });
+            }
+        }
+    }
+    clauses
+}
+
+#[derive(Clone, Debug)]
+struct Posting {
+    doc: usize,
+    positions: Vec<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hit {
+    pub path: String,
+    pub score: f64,
+}
+
+/// An inverted index of the words in each file's text content.
+#[derive(Clone, Debug, Default)]
+pub struct TextIndex {
+    paths: Vec<String>,
+    /// Tokens per document.
+    lengths: Vec<usize>,
+    /// Sorted so prefixes are a range scan.
+    postings: BTreeMap<String, Vec<Posting>>,
+}
+
+impl TextIndex {
+    /// Indexes the text content of every file in `index`.
+    pub fn build(index: &Index) -> TextIndex {
+        let mut text_index = TextIndex::default();
+        for (path, record) in &index.files {
+            if let Some(Value::String(text)) = record.attributes.get(kMDItemTextContent) {
+                text_index.add(path, text);
+            }
+        }
+        text_index
+    }
+
+    pub fn add(&mut self, path: &str, content: &str) {
+        let doc = self.paths.len();
+        let mut positions: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut length = 0;
+        for (i, token) in text::tokenize(content).enumerate() {
+            positions.entry(token).or_default().push(i);
+            length = i + 1;
+        }
+        for (term, positions) in positions {
+            self.postings
+                .entry(term)
+                .or_default()
+                .push(Posting { doc, positions });
+        }
+        self.paths.push(path.to_owned());
+        self.lengths.push(length);
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    /// How often the phrase occurs in each document containing it.
+    fn phrase_frequencies(&self, terms: &[String]) -> Vec<(usize, usize)> {
+        let lists: Option<Vec<&Vec<Posting>>> =
+            terms.iter().map(|t| self.postings.get(t)).collect();
+        let Some(lists) = lists else {
+            return Vec::new();
+        };
+        let mut found = Vec::new();
+        for first in lists[0] {
+            let later: Option<Vec<&Posting>> = lists[1..]
+                .iter()
+                .map(|list| list.iter().find(|p| p.doc == first.doc))
+                .collect();
+            let Some(later) = later else {
+                continue;
+            };
+            let count = first
+                .positions
+                .iter()
+                .filter(|&&start| {
+                    later
+                        .iter()
+                        .enumerate()
+                        .all(|(i, p)| p.positions.binary_search(&(start + i + 1)).is_ok())
+                })
+                .count();
+            if count > 0 {
+                found.push((first.doc, count));
+            }
+        }
+        found
+    }
+
+    /// Adds the BM25 contribution of one term, given its frequency in each
+    /// document containing it, to `scores`.
+    fn score_term(&self, frequencies: &[(usize, usize)], scores: &mut HashMap<usize, f64>) {
+        if frequencies.is_empty() {
+            return;
+        }
+        let docs = self.paths.len() as f64;
+        let average = self.lengths.iter().sum::<usize>() as f64 / docs;
+        let n = frequencies.len() as f64;
+        let idf = ((docs - n + 0.5) / (n + 0.5) + 1.0).ln();
+        for &(doc, tf) in frequencies {
+            let tf = tf as f64;
+            let norm = 1.0 - B + B * self.lengths[doc] as f64 / average.max(1.0);
+            *scores.entry(doc).or_default() += idf * tf * (K1 + 1.0) / (tf + K1 * norm);
+        }
+    }
+
+    fn frequencies(postings: &[Posting]) -> Vec<(usize, usize)> {
+        postings
+            .iter()
+            .map(|p| (p.doc, p.positions.len()))
+            .collect()
+    }
+
+    /// The files matching any clause of `query`, best first, at most
+    /// `limit` of them. Ties go to the earlier path.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<Hit> {
+        let mut scores = HashMap::new();
+        for clause in parse(query) {
+            match clause {
+                Clause::Term(term) => {
+                    if let Some(postings) = self.postings.get(&term) {
+                        self.score_term(&Self::frequencies(postings), &mut scores);
+                    }
+                }
+                Clause::Prefix(prefix) => {
+                    let expansions = self
+                        .postings
+                        .range(prefix.clone()..)
+                        .take_while(|(term, _)| term.starts_with(&prefix));
+                    for (_, postings) in expansions {
+                        self.score_term(&Self::frequencies(postings), &mut scores);
+                    }
+                }
+                Clause::Phrase(terms) => {
+                    self.score_term(&self.phrase_frequencies(&terms), &mut scores);
+                }
+            }
+        }
+        let mut hits: Vec<Hit> = scores
+            .into_iter()
+            .map(|(doc, score)| Hit {
+                path: self.paths[doc].clone(),
+                score,
+            })
+            .collect();
+        hits.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        hits.truncate(limit);
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corpus() -> TextIndex {
+        let mut index = TextIndex::default();
+        index.add("hello.great", "Hello world. Hello again, world!");
+        index.add("greeting.great", "hello there, the world says hello");
+        index.add("worldly.great", "A worldly person travels the world.");
+        index.add(
+            "other.great",
+            "Nothing relevant here at all, just filler text.",
+        );
+        index
+    }
+
+    fn paths(hits: Vec<Hit>) -> Vec<String> {
+        hits.into_iter().map(|h| h.path).collect()
+    }
+
+    #[test]
+    fn parses_clauses() {
+        assert_eq!(
+            parse(r#"Hello wor* "the World says" l'été"#),
+            [
+                Clause::Term("hello".to_owned()),
+                Clause::Prefix("wor".to_owned()),
+                Clause::Phrase(vec![
+                    "the".to_owned(),
+                    "world".to_owned(),
+                    "says".to_owned()
+                ]),
+                Clause::Term("l".to_owned()),
+                Clause::Term("été".to_owned()),
+            ]
+        );
+        assert!(parse(r#"  "" * "#).is_empty());
+    }
+
+    #[test]
+    fn ranks_with_bm25() {
+        let index = corpus();
+        let hits = index.search("hello world", 10);
+        assert_eq!(
+            paths(hits.clone()),
+            ["hello.great", "greeting.great", "worldly.great"]
+        );
+        assert!(hits.windows(2).all(|w| w[0].score >= w[1].score));
+        assert!(index.search("absent", 10).is_empty());
+        assert_eq!(index.search("hello world", 1).len(), 1);
+    }
+
+    #[test]
+    fn phrases_and_prefixes() {
+        let index = corpus();
+        assert_eq!(paths(index.search(r#""hello again""#, 10)), ["hello.great"]);
+        assert_eq!(paths(index.search(r#""world hello""#, 10)), ["hello.great"]);
+        assert!(index.search(r#""again hello""#, 10).is_empty());
+        assert_eq!(paths(index.search("worldl*", 10)), ["worldly.great"]);
+        assert_eq!(index.search("wor*", 10).len(), 3);
+    }
+
+    #[test]
+    fn searches_the_great_corpus() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            tmp.path().join("test.great"),
+            include_str!("../../test.great"),
+        )
+        .unwrap();
+        std::fs::write(tmp.path().join("other.great"), "hello\ngoodbye\n").unwrap();
+        let mut index = Index::default();
+        index
+            .refresh(tmp.path(), &crate::index::RefreshOptions::default())
+            .unwrap();
+        let text = TextIndex::build(&index);
+        assert_eq!(text.len(), 2);
+        let hits = text.search("world", 10);
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].path.ends_with("test.great"));
+        assert_eq!(text.search("\"hello blank\"", 10).len(), 1);
+    }
+}
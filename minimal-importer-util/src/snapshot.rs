@@ -0,0 +1,315 @@
+//! Recording the attributes imported from a corpus and diffing two records.
+//!
+//! A snapshot maps each file's path, relative to the corpus, to the
+//! attributes imported from it in the JSON form [`output::to_json`] prints.
+//! Diffing the snapshots taken before and after a change shows exactly which
+//! files it affected; [`assert_snapshot`] does that from a test.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::batch::{self, Outcome};
+use crate::{Error, Result, output};
+
+/// Bumped when the snapshot format changes incompatibly.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Set to rewrite snapshots instead of comparing against them.
+pub const UPDATE_VAR: &str = "UPDATE_SNAPSHOTS";
+
+/// What importing one file produced.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "status")]
+pub enum Entry {
+    Imported {
+        attributes: BTreeMap<String, Value>,
+    },
+    /// The file failed, panicked or timed out; only the status is kept.
+    Failed {
+        reason: String,
+    },
+}
+
+impl From<Outcome> for Entry {
+    fn from(outcome: Outcome) -> Entry {
+        match outcome {
+            Outcome::Imported(attrs) => Entry::Imported {
+                attributes: match output::to_json(&attrs) {
+                    Value::Object(map) => map.into_iter().collect(),
+                    _ => unreachable!("attributes serialize as an object"),
+                },
+            },
+            other => Entry::Failed {
+                reason: other.status().to_owned(),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    /// Keyed by `/`-separated path relative to the corpus.
+    pub files: BTreeMap<String, Entry>,
+}
+
+impl Snapshot {
+    /// Imports every file under `corpus` and records the result.
+    pub fn take(corpus: &Path, options: &batch::Options) -> Result<Snapshot> {
+        let report = batch::run(corpus, options)?;
+        let mut files = BTreeMap::new();
+        for file in report.files {
+            let relative = file.path.strip_prefix(corpus).unwrap_or(&file.path);
+            let key = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.insert(key, Entry::from(file.outcome));
+        }
+        Ok(Snapshot {
+            version: FORMAT_VERSION,
+            files,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Snapshot> {
+        let snapshot: Snapshot = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| Error::Invalid(format!("{}: {e}", path.display())))?;
+        if snapshot.version != FORMAT_VERSION {
+            return Err(Error::Invalid(format!(
+                "{}: snapshot format {} isn't the supported {FORMAT_VERSION}",
+                path.display(),
+                snapshot.version
+            )));
+        }
+        Ok(snapshot)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("snapshots always serialize") + "\n"
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        Ok(fs::write(path, self.to_json())?)
+    }
+}
+
+/// How one file differs between two snapshots.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FileDiff {
+    Added,
+    Removed,
+    Changed {
+        added: BTreeMap<String, Value>,
+        removed: BTreeMap<String, Value>,
+        /// Old and new values.
+        changed: BTreeMap<String, (Value, Value)>,
+    },
+    /// Imported in one snapshot and failed in the other, or failed
+    /// differently.
+    Status {
+        old: String,
+        new: String,
+    },
+}
+
+/// The files that differ between two snapshots, by path.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Diff {
+    pub files: BTreeMap<String, FileDiff>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+fn status(entry: &Entry) -> &str {
+    match entry {
+        Entry::Imported { .. } => "imported",
+        Entry::Failed { reason } => reason,
+    }
+}
+
+fn diff_attributes(
+    old: &BTreeMap<String, Value>,
+    new: &BTreeMap<String, Value>,
+) -> Option<FileDiff> {
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    let (mut added, mut removed, mut changed) = (BTreeMap::new(), BTreeMap::new(), BTreeMap::new());
+    for key in keys {
+        match (old.get(key), new.get(key)) {
+            (None, Some(v)) => {
+                added.insert(key.clone(), v.clone());
+            }
+            (Some(v), None) => {
+                removed.insert(key.clone(), v.clone());
+            }
+            (Some(a), Some(b)) if a != b => {
+                changed.insert(key.clone(), (a.clone(), b.clone()));
+            }
+            _ => {}
+        }
+    }
+    (!added.is_empty() || !removed.is_empty() || !changed.is_empty()).then_some(FileDiff::Changed {
+        added,
+        removed,
+        changed,
+    })
+}
+
+/// Compares two snapshots file by file.
+pub fn diff(old: &Snapshot, new: &Snapshot) -> Diff {
+    let paths: BTreeSet<&String> = old.files.keys().chain(new.files.keys()).collect();
+    let mut files = BTreeMap::new();
+    for path in paths {
+        let file = match (old.files.get(path), new.files.get(path)) {
+            (None, Some(_)) => Some(FileDiff::Added),
+            (Some(_), None) => Some(FileDiff::Removed),
+            (Some(Entry::Imported { attributes: a }), Some(Entry::Imported { attributes: b })) => {
+                diff_attributes(a, b)
+            }
+            (Some(a), Some(b)) if a != b => Some(FileDiff::Status {
+                old: status(a).to_owned(),
+                new: status(b).to_owned(),
+            }),
+            _ => None,
+        };
+        if let Some(file) = file {
+            files.insert(path.clone(), file);
+        }
+    }
+    Diff { files }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (path, file) in &self.files {
+            match file {
+                FileDiff::Added => writeln!(f, "+ {path}")?,
+                FileDiff::Removed => writeln!(f, "- {path}")?,
+                FileDiff::Status { old, new } => writeln!(f, "~ {path}: {old} -> {new}")?,
+                FileDiff::Changed {
+                    added,
+                    removed,
+                    changed,
+                } => {
+                    writeln!(f, "~ {path}")?;
+                    for (key, value) in added {
+                        writeln!(f, "    + {key}: {value}")?;
+                    }
+                    for (key, value) in removed {
+                        writeln!(f, "    - {key}: {value}")?;
+                    }
+                    for (key, (old, new)) in changed {
+                        writeln!(f, "    ~ {key}: {old} -> {new}")?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Snapshots `corpus` and panics with the diff if it doesn't match the one
+/// saved at `path`. With [`UPDATE_VAR`] set, saves the new snapshot instead.
+#[track_caller]
+pub fn assert_snapshot(corpus: &Path, path: &Path) {
+    let new = Snapshot::take(corpus, &batch::Options::default())
+        .unwrap_or_else(|e| panic!("snapshotting {}: {e}", corpus.display()));
+    if std::env::var_os(UPDATE_VAR).is_some() {
+        new.save(path)
+            .unwrap_or_else(|e| panic!("saving {}: {e}", path.display()));
+        return;
+    }
+    let old = Snapshot::load(path).unwrap_or_else(|e| panic!("{e}"));
+    let diff = diff(&old, &new);
+    assert!(
+        diff.is_empty(),
+        "attributes imported from {} no longer match {}; rerun with {UPDATE_VAR}=1 \
+         if the change is intended:\n{diff}",
+        corpus.display(),
+        path.display()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn snapshot(files: Value) -> Snapshot {
+        serde_json::from_value(json!({"version": FORMAT_VERSION, "files": files})).unwrap()
+    }
+
+    #[test]
+    fn language_corpus() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        assert_snapshot(
+            &root.join("../minimal-importer/testdata/lang"),
+            &root.join("testdata/snapshot/lang.json"),
+        );
+    }
+
+    #[test]
+    fn diffs_files_and_keys() {
+        let old = snapshot(json!({
+            "same.great": {"status": "imported", "attributes": {"kMDItemDescription": "a"}},
+            "edited.great": {"status": "imported", "attributes": {
+                "kMDItemDescription": "old",
+                "kMDItemLanguages": ["en"],
+            }},
+            "broken.great": {"status": "imported", "attributes": {}},
+            "gone.great": {"status": "imported", "attributes": {}},
+        }));
+        let new = snapshot(json!({
+            "same.great": {"status": "imported", "attributes": {"kMDItemDescription": "a"}},
+            "edited.great": {"status": "imported", "attributes": {
+                "kMDItemDescription": "new",
+                "kMDItemKeywords": ["great"],
+            }},
+            "broken.great": {"status": "failed", "reason": "panicked"},
+            "new.great": {"status": "imported", "attributes": {}},
+        }));
+        let diff = diff(&old, &new);
+        assert_eq!(
+            diff.to_string(),
+            "~ broken.great: imported -> panicked\n\
+             ~ edited.great\n    \
+             + kMDItemKeywords: [\"great\"]\n    \
+             - kMDItemLanguages: [\"en\"]\n    \
+             ~ kMDItemDescription: \"old\" -> \"new\"\n\
+             - gone.great\n\
+             + new.great\n"
+        );
+        assert!(super::diff(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(
+            tmp.path().join("test.great"),
+            include_str!("../../test.great"),
+        )
+        .unwrap();
+        let taken = Snapshot::take(tmp.path(), &batch::Options::default()).unwrap();
+        let path = tmp.path().join("snap.json");
+        taken.save(&path).unwrap();
+        assert_eq!(Snapshot::load(&path).unwrap(), taken);
+
+        fs::write(
+            &path,
+            taken.to_json().replace("\"version\": 1", "\"version\": 0"),
+        )
+        .unwrap();
+        assert!(Snapshot::load(&path).is_err());
+    }
+}
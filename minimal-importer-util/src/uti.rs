@@ -0,0 +1,131 @@
+//! The UTI database the util resolves files with, seeded from our plists.
+//!
+//! Launch Services learns about `vin.je.great` from the app's
+//! `UTExportedTypeDeclarations`; [`database`] does the same with the
+//! checked-in `Info.plist`s, on top of the system types.
+
+use std::sync::LazyLock;
+
+use minimal_importer::uti::{Database, TypeDeclaration};
+use plist::Value;
+
+use crate::Result;
+
+/// The `Info.plist`s whose type declarations are built into the util.
+static BUNDLED_PLISTS: &[&str] = &[
+    include_str!("../../minimal-importer-app/resources/Info.plist.xml"),
+    include_str!("../../minimal-importer-bundle/resources/Info.plist.xml"),
+];
+
+static DATABASE: LazyLock<Database> = LazyLock::new(|| {
+    let mut db = Database::with_system_types();
+    for xml in BUNDLED_PLISTS {
+        let plist = Value::from_reader_xml(xml.as_bytes()).expect("bundled plists parse");
+        declare_from_plist(&mut db, &plist);
+    }
+    db
+});
+
+/// System types plus everything our bundles declare.
+pub fn database() -> &'static Database {
+    &DATABASE
+}
+
+fn strings(value: Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::String(s)) => vec![s.clone()],
+        Some(Value::Array(values)) => values
+            .iter()
+            .filter_map(Value::as_string)
+            .map(str::to_owned)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// The type declarations in `plist`, imported ones first so exported ones
+/// take precedence, as in Launch Services.
+pub fn declarations(plist: &Value) -> Vec<TypeDeclaration> {
+    let dict = plist.as_dictionary();
+    ["UTImportedTypeDeclarations", "UTExportedTypeDeclarations"]
+        .into_iter()
+        .filter_map(|key| dict?.get(key)?.as_array())
+        .flatten()
+        .filter_map(Value::as_dictionary)
+        .filter_map(|decl| {
+            let tags = decl
+                .get("UTTypeTagSpecification")
+                .and_then(Value::as_dictionary);
+            Some(TypeDeclaration {
+                identifier: decl.get("UTTypeIdentifier")?.as_string()?.to_owned(),
+                conforms_to: strings(decl.get("UTTypeConformsTo")),
+                extensions: strings(tags.and_then(|t| t.get("public.filename-extension"))),
+                mime_types: strings(tags.and_then(|t| t.get("public.mime-type"))),
+            })
+        })
+        .collect()
+}
+
+pub fn declare_from_plist(db: &mut Database, plist: &Value) {
+    for decl in declarations(plist) {
+        db.declare(decl);
+    }
+}
+
+/// Adds the declarations in the plist at `path` to `db`.
+pub fn declare_from_file(db: &mut Database, path: &std::path::Path) -> Result<()> {
+    declare_from_plist(db, &Value::from_file(path)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn bundled_declarations() {
+        let db = database();
+        let great = db.get("vin.je.great").unwrap();
+        assert_eq!(great.conforms_to, ["public.text"]);
+        assert_eq!(
+            db.type_for_path(Path::new("test.great")),
+            Some("vin.je.great")
+        );
+        assert!(db.conforms_to("vin.je.great", "public.data"));
+    }
+
+    #[test]
+    fn reads_tag_specifications() {
+        let plist =
+            Value::from_reader_xml(&include_bytes!("../testdata/info-plist/app.plist.xml")[..])
+                .unwrap();
+        assert_eq!(
+            declarations(&plist),
+            [
+                TypeDeclaration {
+                    identifier: "vin.je.great".to_owned(),
+                    conforms_to: vec!["public.text".to_owned()],
+                    extensions: vec!["great".to_owned()],
+                    mime_types: vec![],
+                },
+                TypeDeclaration {
+                    identifier: "com.example.notes".to_owned(),
+                    conforms_to: vec!["public.text".to_owned(), "public.content".to_owned()],
+                    extensions: vec!["notes".to_owned(), "note".to_owned()],
+                    mime_types: vec!["text/x-example-notes".to_owned()],
+                },
+            ]
+        );
+        let mut db = Database::with_system_types();
+        declare_from_plist(&mut db, &plist);
+        assert_eq!(
+            db.type_for_path(Path::new("a.NOTE")),
+            Some("com.example.notes")
+        );
+        assert_eq!(
+            db.types_for_mime_type("text/x-example-notes")[0],
+            "com.example.notes"
+        );
+    }
+}
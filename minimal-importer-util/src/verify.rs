@@ -0,0 +1,306 @@
+//! Checking a built bundle's binary against its `Info.plist`.
+//!
+//! rustc links a cdylib with `-dynamiclib`, and CFPlugIn won't load the
+//! result as an importer; `minimal-importer-ld` swaps in `-bundle`. Nothing else
+//! notices when the driver didn't run, so this reads the Mach-O and makes
+//! sure it is an `MH_BUNDLE` exporting the factory the plist names, and
+//! nothing else: stray exports clash when several Rust importers share one
+//! `mdworker`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use minimal_importer::plugin::{self, uuid_string};
+use plist::Value;
+
+use crate::bundle::{self, APP_EXTENSION, plist_string, report};
+use crate::macho::{self, MH_BUNDLE, MachO};
+use crate::{Error, Result};
+
+/// Symbols the linker defines in every image, which are fine to export.
+const LINKER_SYMBOLS: &[&str] = &["__mh_bundle_header"];
+
+/// An importer that passed verification and the images in its executable.
+#[derive(Debug)]
+pub struct Verified {
+    pub bundle: PathBuf,
+    pub images: Vec<MachO>,
+}
+
+/// Verifies an `.mdimporter`, or every importer nested in an `.app`.
+pub fn verify(bundle: &Path) -> Result<Vec<Verified>> {
+    if bundle.extension().and_then(|e| e.to_str()) == Some(APP_EXTENSION) {
+        bundle::validate_app(bundle)?;
+        bundle::nested_importers(bundle)?
+            .iter()
+            .map(|importer| verify_importer(importer))
+            .collect()
+    } else {
+        Ok(vec![verify_importer(bundle)?])
+    }
+}
+
+/// Verifies one `.mdimporter`, reporting every problem found.
+pub fn verify_importer(bundle: &Path) -> Result<Verified> {
+    bundle::validate_importer(bundle)?;
+    let contents = bundle.join("Contents");
+    let plist = Value::from_file(contents.join("Info.plist"))?;
+    // validate_importer checked that the executable is there.
+    let exe = plist_string(&plist, "CFBundleExecutable").unwrap_or_default();
+    let images = macho::parse(&fs::read(contents.join("MacOS").join(exe))?)
+        .map_err(|e| Error::Invalid(format!("{}: MacOS/{exe}: {e}", bundle.display())))?;
+
+    let mut problems = Vec::new();
+    check_images(&images, exe, &mut problems);
+    check_factories(&plist, &images, &mut problems);
+    report(bundle, problems)?;
+    Ok(Verified {
+        bundle: bundle.to_owned(),
+        images,
+    })
+}
+
+/// Fails unless every image in `binary` is an `MH_BUNDLE`, so a dylib is
+/// caught before it's bundled rather than when the bundle is verified.
+pub fn check_linked_as_bundle(binary: &Path) -> Result<()> {
+    let images = macho::parse(&fs::read(binary)?)
+        .map_err(|e| Error::Invalid(format!("{}: {e}", binary.display())))?;
+    let mut problems = Vec::new();
+    for image in &images {
+        check_file_type(image, &mut problems);
+    }
+    report(binary, problems)
+}
+
+fn check_file_type(image: &MachO, problems: &mut Vec<String>) {
+    if image.file_type != MH_BUNDLE {
+        problems.push(format!(
+            "{} image is {}, not MH_BUNDLE; was it linked without minimal-importer-ld?",
+            image.arch_name(),
+            macho::file_type_name(image.file_type)
+        ));
+    }
+}
+
+fn check_images(images: &[MachO], exe: &str, problems: &mut Vec<String>) {
+    let mut uuids: HashMap<[u8; 16], String> = HashMap::new();
+    for image in images {
+        let arch = image.arch_name();
+        check_file_type(image, problems);
+        if let Some(name) = &image.install_name
+            && Path::new(name).file_name().and_then(|n| n.to_str()) != Some(exe)
+        {
+            problems.push(format!(
+                "{arch} install name {name} doesn't match CFBundleExecutable {exe}"
+            ));
+        }
+        match image.uuid {
+            None => problems.push(format!("{arch} image has no LC_UUID")),
+            Some(uuid) => {
+                if let Some(other) = uuids.insert(uuid, arch.clone()) {
+                    problems.push(format!(
+                        "{other} and {arch} images share the UUID {}",
+                        uuid_string(&uuid)
+                    ));
+                }
+            }
+        }
+    }
+}
+
+fn check_factories(plist: &Value, images: &[MachO], problems: &mut Vec<String>) {
+    let dict = plist.as_dictionary();
+    let factories = dict
+        .and_then(|d| d.get("CFPlugInFactories"))
+        .and_then(Value::as_dictionary);
+    let expected = uuid_string(&plugin::MetadataImporterPluginFactoryUUID);
+    let mut allowed: Vec<String> = LINKER_SYMBOLS.iter().map(|&s| s.to_owned()).collect();
+    for (uuid, name) in factories.into_iter().flatten() {
+        if !uuid.eq_ignore_ascii_case(&expected) {
+            problems.push(format!(
+                "factory {uuid} isn't the one the importer registers, {expected}"
+            ));
+        }
+        let Some(name) = name.as_string() else {
+            problems.push(format!("factory {uuid} has no function name"));
+            continue;
+        };
+        let symbol = format!("_{name}");
+        for image in images.iter().filter(|i| !i.is_exported(&symbol)) {
+            problems.push(format!(
+                "{} image doesn't export {symbol}",
+                image.arch_name()
+            ));
+        }
+        allowed.push(symbol);
+    }
+    for image in images {
+        let mut unexpected: Vec<&str> = image
+            .exported_symbols()
+            .filter(|s| !allowed.iter().any(|a| a == s))
+            .collect();
+        if !unexpected.is_empty() {
+            unexpected.sort();
+            problems.push(format!(
+                "{} image exports {}, which no factory names",
+                image.arch_name(),
+                unexpected.join(", ")
+            ));
+        }
+    }
+
+    let importer_type = uuid_string(&plugin::kMDImporterTypeID);
+    let registered = dict
+        .and_then(|d| d.get("CFPlugInTypes"))
+        .and_then(Value::as_dictionary)
+        .and_then(|types| types.get(&importer_type))
+        .and_then(Value::as_array);
+    for uuid in registered
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_string)
+    {
+        if !factories.is_some_and(|f| f.contains_key(uuid)) {
+            problems.push(format!(
+                "CFPlugInTypes lists factory {uuid}, which isn't in CFPlugInFactories"
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::info_plist::read_manifest;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/macho")
+            .join(name)
+    }
+
+    fn assemble(tmp: &Path, binary: &str) -> PathBuf {
+        let info = read_manifest(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("../minimal-importer-bundle"),
+        )
+        .unwrap();
+        bundle::assemble_importer(&info, &fixture(binary), tmp, "Minimal").unwrap()
+    }
+
+    fn problems(bundle: &Path) -> String {
+        match verify(bundle) {
+            Err(Error::Invalid(msg)) => msg,
+            other => panic!("{other:?}"),
+        }
+    }
+
+    fn edit_plist(bundle: &Path, edit: impl FnOnce(&mut plist::Dictionary)) {
+        let path = bundle.join("Contents/Info.plist");
+        let mut plist = Value::from_file(&path).unwrap();
+        edit(plist.as_dictionary_mut().unwrap());
+        plist.to_file_xml(&path).unwrap();
+    }
+
+    #[test]
+    fn accepts_thin_and_fat_bundles() {
+        let tmp = tempfile::tempdir().unwrap();
+        for (binary, arches) in [
+            ("bundle-arm64", &["arm64"][..]),
+            ("bundle-fat", &["x86_64", "arm64"]),
+        ] {
+            let verified = verify(&assemble(tmp.path(), binary)).unwrap();
+            let found: Vec<_> = verified[0].images.iter().map(MachO::arch_name).collect();
+            assert_eq!(found, arches);
+        }
+    }
+
+    #[test]
+    fn flags_dylibs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let msg = problems(&assemble(tmp.path(), "dylib-arm64"));
+        assert!(
+            msg.contains("arm64 image is MH_DYLIB, not MH_BUNDLE"),
+            "{msg}"
+        );
+        assert!(
+            msg.contains("install name @rpath/libfactory.dylib"),
+            "{msg}"
+        );
+
+        check_linked_as_bundle(&fixture("bundle-fat")).unwrap();
+        let Err(Error::Invalid(msg)) = check_linked_as_bundle(&fixture("dylib-arm64")) else {
+            panic!("a dylib passed");
+        };
+        assert!(
+            msg.ends_with(
+                "arm64 image is MH_DYLIB, not MH_BUNDLE; was it linked without minimal-importer-ld?"
+            ),
+            "{msg}"
+        );
+    }
+
+    #[test]
+    fn flags_unexported_factory() {
+        let tmp = tempfile::tempdir().unwrap();
+        let msg = problems(&assemble(tmp.path(), "hidden-factory-arm64"));
+        assert!(
+            msg.contains("arm64 image doesn't export _MetadataImporterPluginFactory"),
+            "{msg}"
+        );
+    }
+
+    #[test]
+    fn flags_stray_exports() {
+        let tmp = tempfile::tempdir().unwrap();
+        let msg = problems(&assemble(tmp.path(), "stray-export-arm64"));
+        assert!(msg.contains("arm64 image exports "), "{msg}");
+        assert!(msg.contains("_helper"), "{msg}");
+        assert!(!msg.contains("_MetadataImporterPluginFactory"), "{msg}");
+    }
+
+    #[test]
+    fn cross_checks_the_plist() {
+        let tmp = tempfile::tempdir().unwrap();
+        let bundle = assemble(tmp.path(), "bundle-arm64");
+        let factory = uuid_string(&plugin::MetadataImporterPluginFactoryUUID);
+        edit_plist(&bundle, |d| {
+            let factories = d["CFPlugInFactories"].as_dictionary_mut().unwrap();
+            factories.insert(factory.clone(), "SomeOtherFactory".into());
+        });
+        let msg = problems(&bundle);
+        assert!(msg.contains("doesn't export _SomeOtherFactory"), "{msg}");
+
+        let wrong = "00000000-0000-0000-0000-000000000000";
+        let importer_type = uuid_string(&plugin::kMDImporterTypeID);
+        edit_plist(&bundle, |d| {
+            let factories = d["CFPlugInFactories"].as_dictionary_mut().unwrap();
+            factories.clear();
+            factories.insert(wrong.to_owned(), "MetadataImporterPluginFactory".into());
+            let types = d["CFPlugInTypes"].as_dictionary_mut().unwrap();
+            types.insert(
+                importer_type.clone(),
+                Value::Array(vec![wrong.into(), factory.as_str().into()]),
+            );
+        });
+        let msg = problems(&bundle);
+        assert!(
+            msg.contains(&format!("factory {wrong} isn't the one")),
+            "{msg}"
+        );
+        assert!(
+            msg.contains(&format!("lists factory {factory}, which isn't")),
+            "{msg}"
+        );
+    }
+
+    #[test]
+    fn flags_missing_executable() {
+        let tmp = tempfile::tempdir().unwrap();
+        let bundle = assemble(tmp.path(), "bundle-arm64");
+        edit_plist(&bundle, |d| {
+            d.insert("CFBundleExecutable".to_owned(), "renamed".into());
+        });
+        assert!(problems(&bundle).contains("MacOS/renamed is missing"));
+    }
+}
@@ -0,0 +1,184 @@
+//! Re-importing files as they change, for the edit–check loop on importer
+//! logic.
+//!
+//! [`Watch`] polls modification times and sizes rather than subscribing to
+//! inotify: it behaves the same on every platform, and the handful of files
+//! someone is editing by hand are cheap to stat. Each [`Watch::poll`]
+//! re-imports only what changed and returns how the attributes differ from
+//! the previous run.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use minimal_importer::registry;
+
+use crate::snapshot::{self, Diff, Entry, Snapshot};
+use crate::{batch, uti};
+
+/// A file's modification time and size.
+pub type Stamp = (SystemTime, u64);
+
+/// The stamp of every file under `paths`, which may be files or
+/// directories. Paths that don't exist, or vanish mid-scan, are left out.
+pub fn scan(paths: &[PathBuf]) -> BTreeMap<PathBuf, Stamp> {
+    let mut stamps = BTreeMap::new();
+    for path in paths {
+        let files = if path.is_dir() {
+            batch::walk(path).map(|walk| walk.files).unwrap_or_default()
+        } else {
+            vec![path.clone()]
+        };
+        for file in files {
+            if let Ok(meta) = fs::metadata(&file)
+                && let Ok(modified) = meta.modified()
+            {
+                stamps.insert(file, (modified, meta.len()));
+            }
+        }
+    }
+    stamps
+}
+
+/// Files added, removed or touched between two scans.
+pub fn changed(old: &BTreeMap<PathBuf, Stamp>, new: &BTreeMap<PathBuf, Stamp>) -> Vec<PathBuf> {
+    let removed = old.keys().filter(|path| !new.contains_key(*path));
+    let touched = new
+        .iter()
+        .filter(|(path, stamp)| old.get(*path) != Some(stamp))
+        .map(|(path, _)| path);
+    let mut paths: Vec<PathBuf> = removed.chain(touched).cloned().collect();
+    paths.sort();
+    paths
+}
+
+fn key(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+/// Watched paths and the attributes last imported from them.
+#[derive(Debug)]
+pub struct Watch {
+    paths: Vec<PathBuf>,
+    options: batch::Options,
+    stamps: BTreeMap<PathBuf, Stamp>,
+    snapshot: Snapshot,
+}
+
+impl Watch {
+    /// Watches `paths`. The first [`poll`](Watch::poll) imports everything
+    /// and diffs it against `previous`, which is empty unless a reloaded
+    /// util is picking up where the old one left off.
+    pub fn new(paths: Vec<PathBuf>, options: batch::Options, previous: Snapshot) -> Watch {
+        Watch {
+            paths,
+            options,
+            stamps: BTreeMap::new(),
+            snapshot: Snapshot {
+                version: snapshot::FORMAT_VERSION,
+                ..previous
+            },
+        }
+    }
+
+    /// Re-imports whatever changed since the last poll, keyed by path as
+    /// given, and returns the difference.
+    pub fn poll(&mut self) -> Diff {
+        let stamps = scan(&self.paths);
+        let changed = changed(&self.stamps, &stamps);
+        self.stamps = stamps;
+        if changed.is_empty() {
+            return Diff::default();
+        }
+        let mut next = self.snapshot.clone();
+        let mut imports = Vec::new();
+        for path in changed {
+            next.files.remove(&key(&path));
+            if !self.stamps.contains_key(&path) {
+                continue;
+            }
+            if let Some(handler) = registry::handler_for_path(uti::database(), &path) {
+                imports.push((path, handler.uti));
+            }
+        }
+        for file in batch::import_files(imports, &self.options, minimal_importer::import_file_with)
+        {
+            next.files
+                .insert(key(&file.path), Entry::from(file.outcome));
+        }
+        let diff = snapshot::diff(&self.snapshot, &next);
+        self.snapshot = next;
+        diff
+    }
+
+    /// The attributes from the latest poll.
+    pub fn snapshot(&self) -> &Snapshot {
+        &self.snapshot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::FileDiff;
+
+    #[test]
+    fn reports_edits_additions_and_removals() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("corpus");
+        fs::create_dir(&dir).unwrap();
+        let great = dir.join("test.great");
+        fs::write(&great, "hello\nworld\n").unwrap();
+        fs::write(dir.join("image.png"), [0x89, b'P', b'N', b'G']).unwrap();
+
+        let mut watch = Watch::new(
+            vec![dir.clone()],
+            batch::Options::default(),
+            Snapshot::default(),
+        );
+        let first = watch.poll();
+        assert_eq!(first.files.keys().collect::<Vec<_>>(), [&key(&great)]);
+        assert_eq!(first.files[&key(&great)], FileDiff::Added);
+        assert!(watch.poll().is_empty());
+
+        // A different size makes the edit visible even with coarse mtimes.
+        fs::write(&great, "goodbye\nworld\nagain\n").unwrap();
+        let notes = dir.join("notes.txt");
+        fs::write(&notes, "Some plain notes.\n").unwrap();
+        let diff = watch.poll();
+        assert_eq!(diff.files[&key(&notes)], FileDiff::Added);
+        let FileDiff::Changed { changed, .. } = &diff.files[&key(&great)] else {
+            panic!("{diff}");
+        };
+        assert_eq!(
+            changed["kMDItemDescription"],
+            ("hello".into(), "goodbye".into())
+        );
+
+        fs::remove_file(&notes).unwrap();
+        let diff = watch.poll();
+        assert_eq!(diff.files.len(), 1);
+        assert_eq!(diff.files[&key(&notes)], FileDiff::Removed);
+    }
+
+    #[test]
+    fn resumes_from_a_previous_snapshot() {
+        let tmp = tempfile::tempdir().unwrap();
+        let great = tmp.path().join("test.great");
+        fs::write(&great, "hello\n").unwrap();
+        let mut watch = Watch::new(
+            vec![great.clone()],
+            batch::Options::default(),
+            Snapshot::default(),
+        );
+        watch.poll();
+
+        let mut resumed = Watch::new(
+            vec![great.clone()],
+            batch::Options::default(),
+            watch.snapshot().clone(),
+        );
+        assert!(resumed.poll().is_empty());
+    }
+}
@@ -0,0 +1,46 @@
+#!/bin/sh
+# Rebuilds the Mach-O fixtures used by the verifier tests. Needs the
+# aarch64-apple-darwin and x86_64-apple-darwin targets; rust-lld does the
+# linking, so this runs on Linux too.
+set -eu
+cd "$(dirname "$0")"
+tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT
+lld=$(find "$(rustc --print sysroot)" -name rust-lld -type f | head -n 1)
+
+for target in aarch64 x86_64; do
+	rustc --edition 2024 --crate-type lib --emit obj -C opt-level=2 -C panic=abort \
+		--target "$target-apple-darwin" factory.rs -o "$tmp/factory-$target.o"
+done
+link() {
+	"$lld" -flavor darwin -platform_version macos 11.0 11.0 "$@"
+}
+
+# What minimal-importer-ld should produce.
+link -arch arm64 -bundle -exported_symbol _MetadataImporterPluginFactory \
+	-o bundle-arm64 "$tmp/factory-aarch64.o"
+link -arch x86_64 -bundle -exported_symbol _MetadataImporterPluginFactory \
+	-o "$tmp/bundle-x86_64" "$tmp/factory-x86_64.o"
+# A bundle linked without an exported symbols list.
+link -arch arm64 -bundle -o stray-export-arm64 "$tmp/factory-aarch64.o"
+# What plain `cargo build` of a cdylib produces.
+link -arch arm64 -dylib -install_name @rpath/libfactory.dylib -o dylib-arm64 "$tmp/factory-aarch64.o"
+# A bundle whose factory isn't exported.
+link -arch arm64 -bundle -exported_symbol _helper -o hidden-factory-arm64 "$tmp/factory-aarch64.o"
+
+# There's no lipo off macOS, so write the fat header by hand.
+python3 - "$tmp/bundle-x86_64" bundle-arm64 bundle-fat <<'PY'
+import struct, sys
+CPU_TYPE_X86_64, CPU_TYPE_ARM64 = 0x01000007, 0x0100000C
+slices = [(CPU_TYPE_X86_64, 3, 12, open(sys.argv[1], "rb").read()),
+          (CPU_TYPE_ARM64, 0, 14, open(sys.argv[2], "rb").read())]
+header = struct.pack(">II", 0xCAFEBABE, len(slices))
+offset, body, entries = 1 << 14, b"", b""
+for cputype, subtype, align, data in slices:
+    offset = -(-offset // (1 << align)) * (1 << align)
+    entries += struct.pack(">iiIII", cputype, subtype, offset, len(data), align)
+    body = body.ljust(offset - (1 << 14), b"\0") + data
+    offset += len(data)
+with open(sys.argv[3], "wb") as f:
+    f.write((header + entries).ljust(1 << 14, b"\0") + body)
+PY
@@ -0,0 +1,16 @@
+//! Stand-in for the importer's cdylib, only as big as the verifier needs.
+
+#![no_std]
+
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn MetadataImporterPluginFactory() -> *const u8 {
+    core::ptr::null()
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn helper() {}
@@ -0,0 +1,343 @@
+//! The C ABI an importer and a CFPlugIn host share where CoreFoundation
+//! doesn't exist.
+//!
+//! The COM side is the same everywhere: an instance starts with a pointer to
+//! an [`MDImporterInterface`] table, and interfaces are named by
+//! [`CFUUIDBytes`] passed by value. What changes off macOS is what the CF
+//! object pointers point at. The host passes plain `#[repr(C)]` stand-ins
+//! instead: a [`PortableHost`] where the factory's `CFAllocatorRef` goes,
+//! [`CFUUIDBytes`] for the `CFUUIDRef` type ID, [`PortableString`]s for
+//! `CFStringRef`s and a [`PortableDictionary`] for the attribute dictionary.
+//! None of them need code from the other side of the `dlopen` to use.
+
+use std::ffi::c_void;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::AttrValue;
+
+pub type HRESULT = i32;
+pub const S_OK: HRESULT = 0;
+/// What an importer answers `QueryInterface` with for interfaces it lacks.
+pub const S_FALSE: HRESULT = 1;
+
+/// `CFUUIDBytes`, which `REFIID` is.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CFUUIDBytes {
+    pub bytes: [u8; 16],
+}
+
+/// `IUnknownVTbl` followed by `ImporterImportData`: Spotlight's
+/// `MDImporterInterfaceStruct`. Every `this` is the instance pointer.
+#[repr(C)]
+#[derive(Debug)]
+pub struct MDImporterInterface {
+    pub _reserved: *mut c_void,
+    pub query_interface: Option<
+        unsafe extern "C-unwind" fn(
+            this: *mut c_void,
+            iid: CFUUIDBytes,
+            out: *mut *mut c_void,
+        ) -> HRESULT,
+    >,
+    pub add_ref: Option<unsafe extern "C-unwind" fn(this: *mut c_void) -> u32>,
+    pub release: Option<unsafe extern "C-unwind" fn(this: *mut c_void) -> u32>,
+    /// `attributes` is a `CFMutableDictionaryRef` and `content_type_uti` and
+    /// `path` are `CFStringRef`s, or their portable stand-ins.
+    pub importer_import_data: Option<
+        unsafe extern "C-unwind" fn(
+            this: *mut c_void,
+            attributes: *mut c_void,
+            content_type_uti: *const c_void,
+            path: *const c_void,
+        ) -> bool,
+    >,
+}
+
+// Vtables are immutable statics shared by every instance.
+unsafe impl Send for MDImporterInterface {}
+unsafe impl Sync for MDImporterInterface {}
+
+/// The vtable behind [`kMinimalImporterBuildInfoInterfaceID`]: `IUnknownVTbl`
+/// and one method, which fills `attributes` with the
+/// [`plugin::build_info`] keys the way `ImporterImportData` fills it with
+/// file attributes.
+///
+/// [`kMinimalImporterBuildInfoInterfaceID`]: crate::plugin::kMinimalImporterBuildInfoInterfaceID
+/// [`plugin::build_info`]: crate::plugin::build_info
+#[repr(C)]
+#[derive(Debug)]
+pub struct BuildInfoInterface {
+    pub _reserved: *mut c_void,
+    pub query_interface: Option<
+        unsafe extern "C-unwind" fn(
+            this: *mut c_void,
+            iid: CFUUIDBytes,
+            out: *mut *mut c_void,
+        ) -> HRESULT,
+    >,
+    pub add_ref: Option<unsafe extern "C-unwind" fn(this: *mut c_void) -> u32>,
+    pub release: Option<unsafe extern "C-unwind" fn(this: *mut c_void) -> u32>,
+    pub copy_build_info:
+        Option<unsafe extern "C-unwind" fn(this: *mut c_void, attributes: *mut c_void) -> bool>,
+}
+
+unsafe impl Send for BuildInfoInterface {}
+unsafe impl Sync for BuildInfoInterface {}
+
+/// The fields every importer instance starts with, as the Apple sample
+/// code lays them out. Hosts only rely on `interface`; the rest is there so
+/// a host can show the reference count the way `bndltest` does.
+#[repr(C)]
+#[derive(Debug)]
+pub struct PluginInstanceHeader {
+    pub interface: *const MDImporterInterface,
+    pub factory_id: *mut c_void,
+    pub ref_count: u32,
+}
+
+/// The exported factory function's signature. On macOS `allocator` is a
+/// `CFAllocatorRef` and `type_id` a `CFUUIDRef`; elsewhere they're a
+/// [`PortableHost`] and [`CFUUIDBytes`].
+pub type FactoryFn =
+    unsafe extern "C-unwind" fn(allocator: *mut c_void, type_id: *mut c_void) -> *mut c_void;
+
+/// UTF-8 text standing in for a `CFStringRef`; not NUL-terminated. A path
+/// is the file system's bytes instead, which on Unix needn't be UTF-8.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct PortableString {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl PortableString {
+    /// Borrows `s`; the result must not outlive it.
+    pub fn new(s: &str) -> PortableString {
+        PortableString {
+            ptr: s.as_ptr(),
+            len: s.len(),
+        }
+    }
+
+    /// Borrows `bytes`, which needn't be UTF-8, like [`new`](Self::new).
+    pub fn from_bytes(bytes: &[u8]) -> PortableString {
+        PortableString {
+            ptr: bytes.as_ptr(),
+            len: bytes.len(),
+        }
+    }
+
+    /// The text, or `None` if it isn't UTF-8.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to `len` readable bytes that live for `'a`.
+    pub unsafe fn as_str<'a>(&self) -> Option<&'a str> {
+        std::str::from_utf8(unsafe { self.as_bytes() }).ok()
+    }
+
+    /// The bytes, whatever they are.
+    ///
+    /// # Safety
+    ///
+    /// As for [`as_str`](Self::as_str).
+    pub unsafe fn as_bytes<'a>(&self) -> &'a [u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+pub const KIND_STRING: u32 = 0;
+pub const KIND_INTEGER: u32 = 1;
+pub const KIND_REAL: u32 = 2;
+pub const KIND_BOOL: u32 = 3;
+/// `real` holds a `CFAbsoluteTime`: seconds since 2001-01-01 UTC.
+pub const KIND_DATE: u32 = 4;
+pub const KIND_ARRAY: u32 = 5;
+
+/// Seconds from the Unix epoch to CoreFoundation's.
+const CF_EPOCH_OFFSET: f64 = 978_307_200.0;
+
+/// One attribute value; `kind` says which of the other fields is set.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct PortableValue {
+    pub kind: u32,
+    pub integer: i64,
+    pub real: f64,
+    pub string: PortableString,
+    pub items: *const PortableValue,
+    pub count: usize,
+}
+
+fn absolute_time(t: SystemTime) -> f64 {
+    let unix = match t.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs_f64(),
+        Err(e) => -e.duration().as_secs_f64(),
+    };
+    unix - CF_EPOCH_OFFSET
+}
+
+fn from_absolute_time(seconds: f64) -> Option<SystemTime> {
+    let unix = seconds + CF_EPOCH_OFFSET;
+    let offset = Duration::try_from_secs_f64(unix.abs()).ok()?;
+    if unix >= 0.0 {
+        UNIX_EPOCH.checked_add(offset)
+    } else {
+        UNIX_EPOCH.checked_sub(offset)
+    }
+}
+
+impl PortableValue {
+    fn empty(kind: u32) -> PortableValue {
+        PortableValue {
+            kind,
+            integer: 0,
+            real: 0.0,
+            string: PortableString::new(""),
+            items: std::ptr::null(),
+            count: 0,
+        }
+    }
+
+    fn lay_out(value: &AttrValue, arrays: &mut Vec<Vec<PortableValue>>) -> PortableValue {
+        match value {
+            AttrValue::String(s) => PortableValue {
+                string: PortableString::new(s),
+                ..PortableValue::empty(KIND_STRING)
+            },
+            AttrValue::Integer(i) => PortableValue {
+                integer: *i,
+                ..PortableValue::empty(KIND_INTEGER)
+            },
+            AttrValue::Real(f) => PortableValue {
+                real: *f,
+                ..PortableValue::empty(KIND_REAL)
+            },
+            AttrValue::Bool(b) => PortableValue {
+                integer: i64::from(*b),
+                ..PortableValue::empty(KIND_BOOL)
+            },
+            AttrValue::Date(t) => PortableValue {
+                real: absolute_time(*t),
+                ..PortableValue::empty(KIND_DATE)
+            },
+            AttrValue::Array(values) => {
+                let items: Vec<PortableValue> =
+                    values.iter().map(|v| Self::lay_out(v, arrays)).collect();
+                // The heap buffer doesn't move when the Vec does.
+                let value = PortableValue {
+                    items: items.as_ptr(),
+                    count: items.len(),
+                    ..PortableValue::empty(KIND_ARRAY)
+                };
+                arrays.push(items);
+                value
+            }
+        }
+    }
+
+    /// Calls `f` with `value` laid out for the ABI. The layout borrows from
+    /// `value` and only lives for the call.
+    pub fn with<R>(value: &AttrValue, f: impl FnOnce(&PortableValue) -> R) -> R {
+        let mut arrays = Vec::new();
+        let portable = Self::lay_out(value, &mut arrays);
+        f(&portable)
+    }
+
+    /// Reads the value back, or `None` if it's malformed.
+    ///
+    /// # Safety
+    ///
+    /// Strings and arrays must point to valid memory, recursively.
+    pub unsafe fn to_attr_value(&self) -> Option<AttrValue> {
+        Some(match self.kind {
+            KIND_STRING => AttrValue::String(unsafe { self.string.as_str() }?.to_owned()),
+            KIND_INTEGER => AttrValue::Integer(self.integer),
+            KIND_REAL => AttrValue::Real(self.real),
+            KIND_BOOL => AttrValue::Bool(self.integer != 0),
+            KIND_DATE => AttrValue::Date(from_absolute_time(self.real)?),
+            KIND_ARRAY => {
+                let items = if self.count == 0 {
+                    &[]
+                } else {
+                    unsafe { std::slice::from_raw_parts(self.items, self.count) }
+                };
+                let items: Option<Vec<AttrValue>> =
+                    items.iter().map(|v| unsafe { v.to_attr_value() }).collect();
+                AttrValue::Array(items?)
+            }
+            _ => return None,
+        })
+    }
+}
+
+/// A `CFMutableDictionaryRef` stand-in the host owns; importers add to it
+/// through `set`.
+#[repr(C)]
+#[derive(Debug)]
+pub struct PortableDictionary {
+    pub context: *mut c_void,
+    pub set: unsafe extern "C-unwind" fn(
+        context: *mut c_void,
+        key: *const PortableString,
+        value: *const PortableValue,
+    ),
+}
+
+pub const LOG_ERROR: u32 = 1;
+pub const LOG_WARN: u32 = 2;
+pub const LOG_INFO: u32 = 3;
+pub const LOG_DEBUG: u32 = 4;
+
+/// What the host provides in place of CoreFoundation's process-wide
+/// services: `CFPlugInAddInstanceForFactory`,
+/// `CFPlugInRemoveInstanceForFactory` and the unified log.
+#[repr(C)]
+#[derive(Debug)]
+pub struct PortableHost {
+    pub context: *mut c_void,
+    pub add_instance_for_factory:
+        unsafe extern "C-unwind" fn(context: *mut c_void, factory: *const CFUUIDBytes),
+    pub remove_instance_for_factory:
+        unsafe extern "C-unwind" fn(context: *mut c_void, factory: *const CFUUIDBytes),
+    pub log: unsafe extern "C-unwind" fn(
+        context: *mut c_void,
+        level: u32,
+        message: *const PortableString,
+    ),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_round_trip() {
+        let date = UNIX_EPOCH + Duration::from_secs(951_782_400);
+        for value in [
+            AttrValue::from("grüße"),
+            AttrValue::from(-7_i64),
+            AttrValue::from(2.5),
+            AttrValue::from(true),
+            AttrValue::from(date),
+            AttrValue::from(UNIX_EPOCH - Duration::from_secs(60)),
+            AttrValue::Array(vec![
+                AttrValue::from("a"),
+                AttrValue::Array(vec![AttrValue::from(1_i64)]),
+                AttrValue::Array(vec![]),
+            ]),
+        ] {
+            let back = PortableValue::with(&value, |p| unsafe { p.to_attr_value() });
+            assert_eq!(back, Some(value));
+        }
+        PortableValue::with(&AttrValue::from(date), |p| {
+            assert_eq!(p.real, 951_782_400.0 - CF_EPOCH_OFFSET);
+        });
+        let bad = PortableValue::empty(99);
+        assert_eq!(unsafe { bad.to_attr_value() }, None);
+    }
+}
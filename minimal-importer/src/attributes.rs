@@ -0,0 +1,93 @@
+//! A plain-Rust attribute dictionary that mirrors what the bundle hands back to
+//! Spotlight in its `CFMutableDictionary`.
+
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttrValue {
+    String(String),
+    Integer(i64),
+    Real(f64),
+    Bool(bool),
+    Date(SystemTime),
+    Array(Vec<AttrValue>),
+}
+
+impl From<&str> for AttrValue {
+    fn from(s: &str) -> Self {
+        AttrValue::String(s.to_owned())
+    }
+}
+
+impl From<String> for AttrValue {
+    fn from(s: String) -> Self {
+        AttrValue::String(s)
+    }
+}
+
+impl From<i64> for AttrValue {
+    fn from(i: i64) -> Self {
+        AttrValue::Integer(i)
+    }
+}
+
+impl From<f64> for AttrValue {
+    fn from(f: f64) -> Self {
+        AttrValue::Real(f)
+    }
+}
+
+impl From<bool> for AttrValue {
+    fn from(b: bool) -> Self {
+        AttrValue::Bool(b)
+    }
+}
+
+impl From<SystemTime> for AttrValue {
+    fn from(t: SystemTime) -> Self {
+        AttrValue::Date(t)
+    }
+}
+
+impl<T: Into<AttrValue>> From<Vec<T>> for AttrValue {
+    fn from(v: Vec<T>) -> Self {
+        AttrValue::Array(v.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Attributes keyed by their `kMDItem*` name, kept sorted so output is stable.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Attributes {
+    map: BTreeMap<String, AttrValue>,
+}
+
+impl Attributes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<AttrValue>) {
+        self.map.insert(key.into(), value.into());
+    }
+
+    pub fn get(&self, key: &str) -> Option<&AttrValue> {
+        self.map.get(key)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<AttrValue> {
+        self.map.remove(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &AttrValue)> {
+        self.map.iter().map(|(k, v)| (k.as_str(), v))
+    }
+}
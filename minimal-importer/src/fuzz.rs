@@ -0,0 +1,110 @@
+//! What the fuzz targets in `fuzz/` run, kept here so the seed corpus and
+//! any crash the fuzzer finds replay as ordinary unit tests.
+//!
+//! Each target takes arbitrary bytes and panics if the code under test does,
+//! or if it breaks an invariant the importer relies on.
+
+use crate::keys::{kMDItemKeywords, kMDItemTextContent};
+use crate::{AttrValue, Attributes, keywords, language, registry, text};
+
+/// A fuzz target's body.
+pub type Target = fn(&[u8]);
+
+/// Every target, by the name of its `fuzz/fuzz_targets/*.rs` file.
+pub static TARGETS: &[(&str, Target)] = &[
+    ("decode", decode),
+    ("great", great),
+    ("import", import),
+    ("language", language),
+];
+
+/// Encoding detection: never fails, and leaves BOM-less UTF-8 alone.
+pub fn decode(data: &[u8]) {
+    let decoded = text::decode(data);
+    if let Ok(s) = std::str::from_utf8(data)
+        && !s.starts_with('\u{feff}')
+    {
+        assert_eq!(decoded, s);
+    }
+}
+
+/// A `.great` file through the whole import.
+pub fn great(data: &[u8]) {
+    let mut attrs = Attributes::new();
+    crate::import_bytes(&mut attrs, "vin.je.great", data).unwrap();
+    assert_eq!(
+        attrs.get(kMDItemTextContent),
+        Some(&AttrValue::String(text::decode(data)))
+    );
+    if let Some(AttrValue::Array(words)) = attrs.get(kMDItemKeywords) {
+        assert!(words.len() <= keywords::DEFAULT_MAX_KEYWORDS);
+    }
+}
+
+/// Any registered content type, picked by the first byte, with keywords
+/// already in the dictionary the way Spotlight can hand them over.
+pub fn import(data: &[u8]) {
+    let Some((&pick, contents)) = data.split_first() else {
+        return;
+    };
+    let handler = &registry::HANDLERS[usize::from(pick) % registry::HANDLERS.len()];
+    let mut attrs = Attributes::new();
+    attrs.insert(kMDItemKeywords, vec!["explicit"]);
+    crate::import_bytes(&mut attrs, handler.uti, contents).unwrap();
+    let Some(AttrValue::Array(words)) = attrs.get(kMDItemKeywords) else {
+        panic!("explicit keywords were dropped");
+    };
+    assert_eq!(words.first(), Some(&AttrValue::from("explicit")));
+}
+
+/// Language detection on whatever the text decodes to.
+pub fn language(data: &[u8]) {
+    let text = text::decode(data);
+    for code in language::detect_languages(&text, 0.0) {
+        assert!(language::supported_languages().any(|l| l == code));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// Runs every seed and saved crash through its target.
+    #[test]
+    fn replays_seeds_and_regressions() {
+        let fuzz = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz");
+        let mut replayed = 0;
+        for (name, target) in TARGETS {
+            for dir in ["seeds", "regressions"] {
+                let Ok(entries) = std::fs::read_dir(fuzz.join(dir).join(name)) else {
+                    continue;
+                };
+                for entry in entries {
+                    let path = entry.unwrap().path();
+                    let data = std::fs::read(&path).unwrap();
+                    eprintln!("replaying {}", path.display());
+                    target(&data);
+                    replayed += 1;
+                }
+            }
+        }
+        assert!(replayed >= TARGETS.len());
+    }
+
+    #[test]
+    fn handles_edge_cases() {
+        for (_, target) in TARGETS {
+            for data in [
+                &b""[..],
+                b"\xFF\xFE\x00",
+                b"\xFE\xFF\xD8\x00",
+                b"\xEF\xBB\xBF\xFF",
+                b"\x00\x00\x00\x00",
+                "\u{feff}é".as_bytes(),
+            ] {
+                target(data);
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! A ledger of hand-refcounted objects, for finding leaks and double
+//! releases.
+//!
+//! COM-style code that wants checking calls [`created`] when it boxes an
+//! object, [`check_live`] before touching one through a pointer it was
+//! handed, and [`freed`] when the count reaches zero. Each object is filed
+//! with the backtrace of its creation, and of its freeing once that
+//! happens, so a leak or a use after free says where the object came from.
+//!
+//! Capturing backtraces is slow, so crates only call in here behind their
+//! `track-instances` feature.
+
+use std::backtrace::Backtrace;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+/// An object [`created`] and not yet [`freed`].
+#[derive(Debug)]
+pub struct Instance {
+    pub kind: &'static str,
+    pub address: usize,
+    /// The thread that created it, numbered by [`thread`]. Tests run in
+    /// parallel, so each only answers for its own.
+    pub thread: u64,
+    pub created: Backtrace,
+}
+
+impl fmt::Display for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} at {:#x}, created on thread {} at:",
+            self.kind, self.address, self.thread
+        )?;
+        write!(f, "{}", self.created)
+    }
+}
+
+/// Which instances [`assert_none_live`] looks at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scope {
+    /// Those the calling thread created: what a test should check.
+    Thread,
+    /// All of them: what to check as the process exits.
+    Process,
+}
+
+impl Scope {
+    fn contains(self, instance: &Instance) -> bool {
+        self == Scope::Process || instance.thread == thread()
+    }
+}
+
+/// A number for the calling thread, unique within the process.
+///
+/// Not `std::thread::current`: that registers a thread-local destructor,
+/// which crashes the thread on exit if a host has since unloaded the
+/// importer.
+pub fn thread() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    thread_local! {
+        static THREAD: Cell<u64> = const { Cell::new(0) };
+    }
+    THREAD.with(|thread| {
+        if thread.get() == 0 {
+            thread.set(NEXT.fetch_add(1, Ordering::Relaxed));
+        }
+        thread.get()
+    })
+}
+
+#[derive(Default)]
+struct Ledger {
+    live: HashMap<usize, Instance>,
+    /// What was last freed at each address, with where it was freed.
+    freed: HashMap<usize, (Instance, Backtrace)>,
+}
+
+static LEDGER: Mutex<Option<Ledger>> = Mutex::new(None);
+
+fn ledger() -> MutexGuard<'static, Option<Ledger>> {
+    // A failed check panics with the lock released, so poisoning only means
+    // some other test failed.
+    LEDGER.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn with_ledger<T>(f: impl FnOnce(&mut Ledger) -> T) -> T {
+    f(ledger().get_or_insert_with(Ledger::default))
+}
+
+/// Files `ptr` as a new live `kind`.
+pub fn created(ptr: *const c_void, kind: &'static str) {
+    let address = ptr as usize;
+    let instance = Instance {
+        kind,
+        address,
+        thread: thread(),
+        created: Backtrace::force_capture(),
+    };
+    with_ledger(|ledger| {
+        ledger.freed.remove(&address);
+        ledger.live.insert(address, instance)
+    });
+}
+
+/// Panics unless `ptr` is a live instance, saying where it was freed if it
+/// was.
+pub fn check_live(ptr: *const c_void) {
+    let message = with_ledger(|ledger| {
+        let address = ptr as usize;
+        if ledger.live.contains_key(&address) {
+            return None;
+        }
+        Some(match ledger.freed.get(&address) {
+            Some((instance, freed)) => {
+                format!("use after release of {instance}\nwhich was freed at:\n{freed}")
+            }
+            None => format!("{address:#x} was never a live instance"),
+        })
+    });
+    if let Some(message) = message {
+        panic!("{message}");
+    }
+}
+
+/// Files the live instance at `ptr` as freed. Panics like [`check_live`] if
+/// it isn't live.
+pub fn freed(ptr: *const c_void) {
+    check_live(ptr);
+    with_ledger(|ledger| {
+        let address = ptr as usize;
+        let instance = ledger.live.remove(&address).unwrap();
+        ledger
+            .freed
+            .insert(address, (instance, Backtrace::force_capture()));
+    });
+}
+
+/// How many instances in `scope` are live.
+pub fn live(scope: Scope) -> usize {
+    with_ledger(|ledger| ledger.live.values().filter(|i| scope.contains(i)).count())
+}
+
+/// Writes every live instance in `scope`, with its creation backtrace.
+pub fn dump(scope: Scope, out: &mut dyn Write) -> io::Result<()> {
+    let report = with_ledger(|ledger| {
+        let mut live: Vec<_> = ledger.live.values().filter(|i| scope.contains(i)).collect();
+        live.sort_by_key(|i| i.address);
+        live.iter().map(|i| format!("{i}\n")).collect::<String>()
+    });
+    out.write_all(report.as_bytes())
+}
+
+/// Panics, listing the leaks, if any instance in `scope` is still live.
+pub fn assert_none_live(scope: Scope) {
+    if live(scope) == 0 {
+        return;
+    }
+    let mut report = Vec::new();
+    dump(scope, &mut report).unwrap();
+    panic!("leaked instances:\n{}", String::from_utf8_lossy(&report));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_instances_per_thread() {
+        let object = Box::into_raw(Box::new(0_u64)).cast::<c_void>();
+        created(object, "u64");
+        check_live(object);
+        assert_eq!(live(Scope::Thread), 1);
+        assert!(live(Scope::Process) >= 1);
+        std::thread::spawn(|| assert_none_live(Scope::Thread))
+            .join()
+            .unwrap();
+
+        let mut report = Vec::new();
+        dump(Scope::Thread, &mut report).unwrap();
+        let report = String::from_utf8(report).unwrap();
+        assert!(report.starts_with(&format!("u64 at {:#x}, created on", object as usize)));
+        assert!(report.contains("tracks_instances_per_thread"), "{report}");
+
+        freed(object);
+        assert_none_live(Scope::Thread);
+        let leaked = std::panic::catch_unwind(|| check_live(object)).unwrap_err();
+        let leaked = leaked.downcast_ref::<String>().unwrap();
+        assert!(leaked.starts_with("use after release of u64"), "{leaked}");
+        assert!(leaked.contains("which was freed at:"), "{leaked}");
+        drop(unsafe { Box::from_raw(object.cast::<u64>()) });
+    }
+
+    #[test]
+    #[should_panic(expected = "leaked instances:\nu8 at")]
+    fn reports_leaks() {
+        created(Box::into_raw(Box::new(0_u8)).cast(), "u8");
+        assert_none_live(Scope::Thread);
+    }
+
+    #[test]
+    #[should_panic(expected = "was never a live instance")]
+    fn refuses_unknown_pointers() {
+        freed(std::ptr::dangling());
+    }
+}
@@ -0,0 +1,25 @@
+//! Spotlight attribute keys set by the importer.
+#![allow(non_upper_case_globals)]
+
+/// This is the key Spotlight expects for the human-readable description.
+pub const kMDItemDescription: &str = "kMDItemDescription";
+
+/// The full extracted text, used by Spotlight for content search.
+pub const kMDItemTextContent: &str = "kMDItemTextContent";
+
+/// ISO 639-1 codes of the languages the content is written in.
+pub const kMDItemLanguages: &str = "kMDItemLanguages";
+
+/// Keywords describing the content, explicit or extracted.
+pub const kMDItemKeywords: &str = "kMDItemKeywords";
+
+/// Every key the importer may set, for the bundle's `schema.xml`.
+pub const ALL: &[&str] = &[
+    kMDItemDescription,
+    kMDItemKeywords,
+    kMDItemLanguages,
+    kMDItemTextContent,
+];
+
+/// Keys worth showing in Finder's Get Info panel.
+pub const DISPLAYED: &[&str] = &[kMDItemDescription, kMDItemKeywords, kMDItemLanguages];
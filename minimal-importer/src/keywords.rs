@@ -0,0 +1,116 @@
+//! Automatic keyword extraction for `kMDItemKeywords`.
+//!
+//! Words are ranked by how often they occur once stop words for the detected
+//! languages are removed. Ties are broken alphabetically so the output for a
+//! given text never changes between runs.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+
+use crate::text;
+
+/// Number of keywords extracted when the caller has no preference.
+pub const DEFAULT_MAX_KEYWORDS: usize = 10;
+
+/// Words shorter than this, in characters, are never keywords.
+const MIN_KEYWORD_CHARS: usize = 3;
+
+/// Stop words used when no language could be detected.
+const FALLBACK_LANGUAGE: &str = "en";
+
+/// ISO 639-1 code and whitespace-separated stop words for each language.
+static STOP_WORD_LISTS: &[(&str, &str)] = &[
+    ("de", include_str!("keywords/de.txt")),
+    ("en", include_str!("keywords/en.txt")),
+    ("es", include_str!("keywords/es.txt")),
+    ("fr", include_str!("keywords/fr.txt")),
+    ("it", include_str!("keywords/it.txt")),
+    ("nl", include_str!("keywords/nl.txt")),
+    ("pt", include_str!("keywords/pt.txt")),
+    ("ru", include_str!("keywords/ru.txt")),
+];
+
+static STOP_WORDS: LazyLock<HashMap<&'static str, HashSet<&'static str>>> = LazyLock::new(|| {
+    STOP_WORD_LISTS
+        .iter()
+        .map(|&(code, list)| (code, list.split_whitespace().collect()))
+        .collect()
+});
+
+/// Returns true if `word` (already lowercased) is a stop word in any of
+/// `languages`, or in English if `languages` is empty.
+pub fn is_stop_word(word: &str, languages: &[&str]) -> bool {
+    let fallback = [FALLBACK_LANGUAGE];
+    let languages = if languages.is_empty() {
+        &fallback[..]
+    } else {
+        languages
+    };
+    languages
+        .iter()
+        .filter_map(|code| STOP_WORDS.get(code))
+        .any(|set| set.contains(word))
+}
+
+/// Extracts up to `max` keywords from `text`, most frequent first.
+///
+/// `languages` selects the stop word lists, normally the output of
+/// [`crate::language::detect_languages`].
+pub fn extract(text: &str, languages: &[&str], max: usize) -> Vec<String> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for token in text::tokenize(text) {
+        if token.chars().count() < MIN_KEYWORD_CHARS
+            || token.chars().all(|c| c.is_numeric())
+            || is_stop_word(&token, languages)
+        {
+            continue;
+        }
+        *counts.entry(token).or_insert(0) += 1;
+    }
+    let mut ranked: Vec<(String, usize)> = counts.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked.truncate(max);
+    ranked.into_iter().map(|(word, _)| word).collect()
+}
+
+/// Combines explicitly provided keywords with extracted ones.
+///
+/// Explicit keywords come first and keep their spelling; extracted keywords
+/// that repeat one of them, ignoring case, are dropped.
+pub fn merge(explicit: &[String], extracted: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    explicit
+        .iter()
+        .cloned()
+        .chain(extracted)
+        .filter(|k| seen.insert(k.to_lowercase()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_great_snapshot() {
+        let text = include_str!("../../test.great");
+        assert_eq!(
+            extract(text, &[], DEFAULT_MAX_KEYWORDS),
+            ["world", "blank", "hello"]
+        );
+    }
+
+    #[test]
+    fn stop_words_follow_language() {
+        let text = "Die Katze und die Maus. Die Katze schläft, die Maus nicht.";
+        assert_eq!(extract(text, &["de"], 3), ["katze", "maus", "schläft"]);
+        assert_eq!(extract(text, &["en"], 1), ["die"]);
+    }
+
+    #[test]
+    fn merge_keeps_explicit_first() {
+        let explicit = vec!["GREAT".to_owned(), "docs".to_owned()];
+        let extracted = vec!["world".to_owned(), "great".to_owned()];
+        assert_eq!(merge(&explicit, extracted), ["GREAT", "docs", "world"]);
+    }
+}
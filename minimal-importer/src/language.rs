@@ -0,0 +1,224 @@
+//! Offline language identification for extracted text.
+//!
+//! Every supported language has a character trigram profile built from a small
+//! training text compiled into the crate (see `src/language/*.txt`). Input text
+//! is scored against each profile as a naive Bayes classifier and the best match
+//! is only trusted when it beats the runner-up by a clear margin.
+
+use std::collections::HashMap;
+use std::iter;
+use std::sync::LazyLock;
+
+/// Length of the character n-grams used for profiles.
+const NGRAM: usize = 3;
+
+/// Texts with fewer letters than this are too short to classify.
+const MIN_LETTERS: usize = 20;
+
+/// Smallest share of the classified text a language needs to be reported.
+const MIN_SHARE: f32 = 0.15;
+
+/// Confidence below which a guess is not trusted.
+pub const DEFAULT_MIN_CONFIDENCE: f32 = 0.05;
+
+/// ISO 639-1 code and training text for every supported language.
+static CORPORA: &[(&str, &str)] = &[
+    ("de", include_str!("language/de.txt")),
+    ("en", include_str!("language/en.txt")),
+    ("es", include_str!("language/es.txt")),
+    ("fr", include_str!("language/fr.txt")),
+    ("it", include_str!("language/it.txt")),
+    ("nl", include_str!("language/nl.txt")),
+    ("pt", include_str!("language/pt.txt")),
+    ("ru", include_str!("language/ru.txt")),
+];
+
+struct Profile {
+    code: &'static str,
+    /// Natural log of each n-gram's smoothed relative frequency.
+    log_probs: HashMap<String, f32>,
+    /// Log probability given to n-grams never seen in the training text.
+    unseen: f32,
+}
+
+static PROFILES: LazyLock<Vec<Profile>> = LazyLock::new(|| {
+    CORPORA
+        .iter()
+        .map(|&(code, corpus)| {
+            let counts = ngrams(corpus);
+            // Add-one smoothing, with one extra slot for everything unseen.
+            let total = (counts.values().sum::<u32>() as usize + counts.len() + 1) as f32;
+            let log_probs = counts
+                .into_iter()
+                .map(|(g, n)| (g, ((n + 1) as f32 / total).ln()))
+                .collect();
+            Profile {
+                code,
+                log_probs,
+                unseen: (1.0 / total).ln(),
+            }
+        })
+        .collect()
+});
+
+/// The most likely language of a piece of text.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LanguageGuess {
+    /// ISO 639-1 language code.
+    pub code: &'static str,
+    /// How clearly the best language beat the runner-up, in `0.0..=1.0`.
+    pub confidence: f32,
+}
+
+/// The ISO 639-1 codes this module can recognise.
+pub fn supported_languages() -> impl Iterator<Item = &'static str> {
+    CORPORA.iter().map(|&(code, _)| code)
+}
+
+/// Counts the `NGRAM`-grams of every word in `text`, lowercased and padded
+/// with a space on either side so word boundaries are captured.
+fn ngrams(text: &str) -> HashMap<String, u32> {
+    let mut counts = HashMap::new();
+    let words = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty());
+    for word in words {
+        let padded: Vec<char> = iter::once(' ')
+            .chain(word.chars().flat_map(char::to_lowercase))
+            .chain(iter::once(' '))
+            .collect();
+        for gram in padded.windows(NGRAM) {
+            *counts.entry(gram.iter().collect()).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+fn letter_count(text: &str) -> usize {
+    text.chars().filter(|c| c.is_alphabetic()).count()
+}
+
+/// Guesses the language of `text`, or `None` if it is too short to tell.
+pub fn detect(text: &str) -> Option<LanguageGuess> {
+    if letter_count(text) < MIN_LETTERS {
+        return None;
+    }
+    let input = ngrams(text);
+    let grams: u32 = input.values().sum();
+    let mut scores: Vec<(&'static str, f32)> = PROFILES
+        .iter()
+        .map(|p| {
+            let log_likelihood: f32 = input
+                .iter()
+                .map(|(g, &n)| n as f32 * p.log_probs.get(g).copied().unwrap_or(p.unseen))
+                .sum();
+            (p.code, log_likelihood / grams as f32)
+        })
+        .collect();
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    let (code, best) = scores[0];
+    let runner_up = scores.get(1).map_or(f32::NEG_INFINITY, |s| s.1);
+    Some(LanguageGuess {
+        code,
+        confidence: 1.0 - (runner_up - best).exp(),
+    })
+}
+
+/// `text` split at blank lines, whatever its line endings.
+fn paragraphs(text: &str) -> Vec<String> {
+    let mut paragraphs = Vec::new();
+    let mut current = String::new();
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                paragraphs.push(std::mem::take(&mut current));
+            }
+        } else {
+            if !current.is_empty() {
+                current.push('\n');
+            }
+            current.push_str(line);
+        }
+    }
+    if !current.is_empty() {
+        paragraphs.push(current);
+    }
+    paragraphs
+}
+
+/// Returns the languages `text` is written in, most prominent first, for use
+/// as `kMDItemLanguages`.
+///
+/// Each paragraph is classified separately so multilingual documents report
+/// every language that makes up a meaningful share of the text. Paragraphs
+/// whose guess is below `min_confidence` are ignored.
+pub fn detect_languages(text: &str, min_confidence: f32) -> Vec<&'static str> {
+    let mut weights: HashMap<&'static str, usize> = HashMap::new();
+    for paragraph in paragraphs(text) {
+        if let Some(guess) = detect(&paragraph).filter(|g| g.confidence >= min_confidence) {
+            *weights.entry(guess.code).or_insert(0) += letter_count(&paragraph);
+        }
+    }
+    if weights.is_empty() {
+        if let Some(guess) = detect(text).filter(|g| g.confidence >= min_confidence) {
+            return vec![guess.code];
+        }
+        return Vec::new();
+    }
+    let total: usize = weights.values().sum();
+    let mut ranked: Vec<(&'static str, usize)> = weights
+        .into_iter()
+        .filter(|&(_, w)| w as f32 / total as f32 >= MIN_SHARE)
+        .collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    ranked.into_iter().map(|(code, _)| code).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(code: &str) -> &'static str {
+        match code {
+            "de" => include_str!("../testdata/lang/de.txt"),
+            "en" => include_str!("../testdata/lang/en.txt"),
+            "es" => include_str!("../testdata/lang/es.txt"),
+            "fr" => include_str!("../testdata/lang/fr.txt"),
+            "it" => include_str!("../testdata/lang/it.txt"),
+            "nl" => include_str!("../testdata/lang/nl.txt"),
+            "pt" => include_str!("../testdata/lang/pt.txt"),
+            "ru" => include_str!("../testdata/lang/ru.txt"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn detects_each_fixture() {
+        for code in supported_languages() {
+            let guess = detect(fixture(code)).unwrap();
+            assert_eq!(guess.code, code, "{guess:?}");
+            assert!(guess.confidence >= DEFAULT_MIN_CONFIDENCE, "{guess:?}");
+        }
+    }
+
+    #[test]
+    fn multilingual_document() {
+        let text = format!("{}\n\n{}", fixture("fr"), fixture("de"));
+        let mut langs = detect_languages(&text, DEFAULT_MIN_CONFIDENCE);
+        langs.sort();
+        assert_eq!(langs, ["de", "fr"]);
+
+        let crlf = text.replace('\n', "\r\n");
+        assert_eq!(paragraphs(&crlf), paragraphs(&text));
+        let mut langs = detect_languages(&crlf, DEFAULT_MIN_CONFIDENCE);
+        langs.sort();
+        assert_eq!(langs, ["de", "fr"]);
+    }
+
+    #[test]
+    fn short_or_empty_text() {
+        assert_eq!(detect("hello"), None);
+        assert_eq!(detect("1234 5678 !!!! ???? ---- ...."), None);
+        assert!(detect_languages("", DEFAULT_MIN_CONFIDENCE).is_empty());
+    }
+}
@@ -1,7 +1,105 @@
+use std::io;
+use std::path::Path;
+
+pub mod abi;
+pub mod attributes;
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod fuzz;
+#[cfg(any(test, feature = "track-instances"))]
+pub mod instances;
+pub mod keys;
+pub mod keywords;
+pub mod language;
+pub mod open;
+pub mod path;
+pub mod plugin;
+pub mod registry;
+pub mod text;
+pub mod uti;
+
+pub use attributes::{AttrValue, Attributes};
+use keys::{kMDItemDescription, kMDItemKeywords, kMDItemLanguages, kMDItemTextContent};
+
+/// The importer's version. Attributes imported by another version may
+/// differ, so indexes re-import when it changes.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
 }
 
+/// Imports the file at `path`, of content type `uti`, into `attrs`.
+///
+/// This is the logic behind the bundle's `ImporterImportData`, kept free of
+/// CoreFoundation so it can be run and tested anywhere. UTIs without a
+/// [`registry`] handler fail with [`io::ErrorKind::Unsupported`], and files
+/// that aren't regular with an [`open::Rejection`].
+pub fn import_file(attrs: &mut Attributes, uti: &str, path: &Path) -> io::Result<()> {
+    import_file_with(attrs, uti, path, open::Options::default())
+}
+
+/// [`import_file`], opening the file with `options`.
+pub fn import_file_with(
+    attrs: &mut Attributes,
+    uti: &str,
+    path: &Path,
+    options: open::Options,
+) -> io::Result<()> {
+    let handler = handler(uti)?;
+    (handler.import)(attrs, &open::read(path, options)?)
+}
+
+/// Imports a file's contents, already read into memory, like
+/// [`import_file`].
+pub fn import_bytes(attrs: &mut Attributes, uti: &str, bytes: &[u8]) -> io::Result<()> {
+    (handler(uti)?.import)(attrs, bytes)
+}
+
+fn handler(uti: &str) -> io::Result<&'static registry::Handler> {
+    registry::handler_for_uti(uti).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("no importer registered for {uti}"),
+        )
+    })
+}
+
+/// Imports a text file. Keywords already in `attrs` are treated as explicitly
+/// provided and kept ahead of extracted ones.
+fn import_text(attrs: &mut Attributes, bytes: &[u8]) -> io::Result<()> {
+    let text = text::decode(bytes);
+    if let Some(first_line) = text.lines().map(str::trim).find(|l| !l.is_empty()) {
+        attrs.insert(kMDItemDescription, first_line);
+    }
+    let languages = language::detect_languages(&text, language::DEFAULT_MIN_CONFIDENCE);
+    let keywords = keywords::merge(
+        &explicit_keywords(attrs),
+        keywords::extract(&text, &languages, keywords::DEFAULT_MAX_KEYWORDS),
+    );
+    if !keywords.is_empty() {
+        attrs.insert(kMDItemKeywords, keywords);
+    }
+    if !languages.is_empty() {
+        attrs.insert(kMDItemLanguages, languages);
+    }
+    attrs.insert(kMDItemTextContent, text);
+    Ok(())
+}
+
+fn explicit_keywords(attrs: &Attributes) -> Vec<String> {
+    match attrs.get(kMDItemKeywords) {
+        Some(AttrValue::String(s)) => vec![s.clone()],
+        Some(AttrValue::Array(values)) => values
+            .iter()
+            .filter_map(|v| match v {
+                AttrValue::String(s) => Some(s.clone()),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -11,4 +109,38 @@ mod tests {
         let result = add(2, 2);
         assert_eq!(result, 4);
     }
+
+    #[test]
+    fn import_sets_languages() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/lang/fr.txt");
+        let mut attrs = Attributes::new();
+        import_file(&mut attrs, "public.plain-text", &path).unwrap();
+        assert_eq!(
+            attrs.get(kMDItemLanguages),
+            Some(&AttrValue::from(vec!["fr"]))
+        );
+        assert!(matches!(
+            attrs.get(kMDItemDescription),
+            Some(AttrValue::String(s)) if s.starts_with("Le renard")
+        ));
+    }
+
+    #[test]
+    fn import_merges_keywords() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../test.great");
+        let mut attrs = Attributes::new();
+        attrs.insert(kMDItemKeywords, vec!["GREAT", "World"]);
+        import_file(&mut attrs, "vin.je.great", &path).unwrap();
+        assert_eq!(
+            attrs.get(kMDItemKeywords),
+            Some(&AttrValue::from(vec!["GREAT", "World", "blank", "hello"]))
+        );
+    }
+
+    #[test]
+    fn import_rejects_unknown_uti() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../test.great");
+        let err = import_file(&mut Attributes::new(), "vin.je.rich", &path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
 }
@@ -0,0 +1,231 @@
+//! Opening the files Spotlight hands the importer, whatever they turn out
+//! to be.
+//!
+//! Only regular files are read. A FIFO or a terminal would block the import
+//! forever and a device could be endless, so anything else is refused with
+//! a [`Rejection`] saying why. The file type is checked before opening, the
+//! open itself doesn't block or (optionally) follow a symlink, and the type
+//! is checked again on the open file in case it was swapped in between.
+
+use std::fmt;
+use std::fs::{self, File, FileType};
+use std::io::{self, Read};
+use std::path::Path;
+
+/// How [`open`] treats the path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Options {
+    /// Open what a symlink points at, rather than refusing it.
+    pub follow_symlinks: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            follow_symlinks: true,
+        }
+    }
+}
+
+/// Why [`open`] refused a file. It's the inner error of the [`io::Error`],
+/// which [`rejection`] gets back out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    Directory,
+    Fifo,
+    Socket,
+    BlockDevice,
+    CharDevice,
+    /// A symlink, when [`Options::follow_symlinks`] is off.
+    Symlink,
+    /// Anything else that isn't a regular file.
+    Other,
+}
+
+impl Rejection {
+    /// A stable name for logs and reports.
+    pub fn code(self) -> &'static str {
+        match self {
+            Rejection::Directory => "directory",
+            Rejection::Fifo => "fifo",
+            Rejection::Socket => "socket",
+            Rejection::BlockDevice => "block_device",
+            Rejection::CharDevice => "char_device",
+            Rejection::Symlink => "symlink",
+            Rejection::Other => "not_regular",
+        }
+    }
+
+    /// What refusing a file of this type means, or `None` for a regular
+    /// file.
+    fn of(file_type: FileType) -> Option<Rejection> {
+        #[cfg(unix)]
+        use std::os::unix::fs::FileTypeExt;
+        if file_type.is_file() {
+            return None;
+        }
+        Some(match file_type {
+            t if t.is_dir() => Rejection::Directory,
+            t if t.is_symlink() => Rejection::Symlink,
+            #[cfg(unix)]
+            t if t.is_fifo() => Rejection::Fifo,
+            #[cfg(unix)]
+            t if t.is_socket() => Rejection::Socket,
+            #[cfg(unix)]
+            t if t.is_block_device() => Rejection::BlockDevice,
+            #[cfg(unix)]
+            t if t.is_char_device() => Rejection::CharDevice,
+            _ => Rejection::Other,
+        })
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self {
+            Rejection::Directory => "a directory",
+            Rejection::Fifo => "a FIFO",
+            Rejection::Socket => "a socket",
+            Rejection::BlockDevice => "a block device",
+            Rejection::CharDevice => "a character device",
+            Rejection::Symlink => "a symlink",
+            Rejection::Other => "not a regular file",
+        };
+        write!(f, "refused {what} ({})", self.code())
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+impl From<Rejection> for io::Error {
+    fn from(rejection: Rejection) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, rejection)
+    }
+}
+
+/// The [`Rejection`] an error from [`open`] carries, if it's a refusal.
+pub fn rejection(err: &io::Error) -> Option<Rejection> {
+    err.get_ref()?.downcast_ref().copied()
+}
+
+/// Opens `path` for reading if it's a regular file.
+pub fn open(path: &Path, options: Options) -> io::Result<File> {
+    let metadata = if options.follow_symlinks {
+        fs::metadata(path)?
+    } else {
+        fs::symlink_metadata(path)?
+    };
+    if let Some(rejection) = Rejection::of(metadata.file_type()) {
+        return Err(rejection.into());
+    }
+
+    let mut open = fs::OpenOptions::new();
+    open.read(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        let mut flags = libc::O_NONBLOCK | libc::O_NOCTTY;
+        if !options.follow_symlinks {
+            flags |= libc::O_NOFOLLOW;
+        }
+        open.custom_flags(flags);
+    }
+    let file = match open.open(path) {
+        #[cfg(unix)]
+        Err(err) if !options.follow_symlinks && err.raw_os_error() == Some(libc::ELOOP) => {
+            return Err(Rejection::Symlink.into());
+        }
+        result => result?,
+    };
+    if let Some(rejection) = Rejection::of(file.metadata()?.file_type()) {
+        return Err(rejection.into());
+    }
+    Ok(file)
+}
+
+/// Reads all of `path` if it's a regular file, like [`fs::read`] otherwise.
+pub fn read(path: &Path, options: Options) -> io::Result<Vec<u8>> {
+    let mut file = open(path, options)?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOFOLLOW: Options = Options {
+        follow_symlinks: false,
+    };
+
+    fn refused(path: &Path, options: Options) -> Option<Rejection> {
+        rejection(&read(path, options).unwrap_err())
+    }
+
+    #[test]
+    fn reads_regular_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("a.great");
+        fs::write(&file, "hello").unwrap();
+        assert_eq!(read(&file, Options::default()).unwrap(), b"hello");
+        assert_eq!(read(&file, NOFOLLOW).unwrap(), b"hello");
+
+        let err = read(&tmp.path().join("missing"), Options::default()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(rejection(&err), None);
+        assert_eq!(
+            refused(tmp.path(), Options::default()),
+            Some(Rejection::Directory)
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn refuses_what_could_block() {
+        use std::os::unix::net::UnixListener;
+
+        let tmp = tempfile::tempdir().unwrap();
+        let fifo = tmp.path().join("fifo.great");
+        let c_path = std::ffi::CString::new(fifo.as_os_str().as_encoded_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+        assert_eq!(refused(&fifo, Options::default()), Some(Rejection::Fifo));
+
+        let socket = tmp.path().join("socket.great");
+        let _listener = UnixListener::bind(&socket).unwrap();
+        assert_eq!(
+            refused(&socket, Options::default()),
+            Some(Rejection::Socket)
+        );
+
+        let null = Path::new("/dev/null");
+        assert_eq!(
+            refused(null, Options::default()),
+            Some(Rejection::CharDevice)
+        );
+
+        let err = read(&fifo, Options::default()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(err.to_string(), "refused a FIFO (fifo)");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn follows_symlinks_only_if_asked() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join("a.great");
+        fs::write(&file, "hello").unwrap();
+        let link = tmp.path().join("link.great");
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+
+        assert_eq!(read(&link, Options::default()).unwrap(), b"hello");
+        assert_eq!(refused(&link, NOFOLLOW), Some(Rejection::Symlink));
+
+        let to_null = tmp.path().join("null.great");
+        std::os::unix::fs::symlink("/dev/null", &to_null).unwrap();
+        assert_eq!(
+            refused(&to_null, Options::default()),
+            Some(Rejection::CharDevice)
+        );
+    }
+}
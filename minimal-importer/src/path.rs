@@ -0,0 +1,311 @@
+//! Turning the path Spotlight hands an importer into a [`PathBuf`] that
+//! names the same file.
+//!
+//! Spotlight passes a `CFString`, which is UTF-16, where file systems want
+//! bytes, and the obvious conversion loses on two counts. An unpaired
+//! surrogate, which HFS+ allows in names, comes out as U+FFFD and names some
+//! other file. And the string needn't be in the normalization form the name
+//! is stored in: HFS+ decomposes names (NFD), APFS keeps whichever form they
+//! were created with, and a path can cross both.
+//!
+//! So a path becomes a list of candidates, most faithful first, and the
+//! first one that exists wins. If none does the most faithful is returned,
+//! and opening it fails the usual way.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use unicode_normalization::UnicodeNormalization;
+
+/// Which candidate a [`Resolved`] path is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// The path as given, or as the platform's file system representation.
+    Exact,
+    /// Decomposed to NFD, as HFS+ stores names.
+    Decomposed,
+    /// Composed to NFC.
+    Composed,
+    /// Each component matched against its directory's entries, whatever
+    /// form either is in. For paths that mix forms.
+    DirectoryScan,
+    /// Unpaired surrogates kept, encoded as WTF-8. Only on Unix, where a
+    /// name can be any bytes.
+    Wtf8,
+    /// Unpaired surrogates replaced with U+FFFD. Unlikely to exist.
+    Lossy,
+}
+
+/// A path to open, and how it was found.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Resolved {
+    pub path: PathBuf,
+    pub strategy: Strategy,
+}
+
+/// Resolves a path given as UTF-16, the way a `CFString` holds it.
+pub fn from_utf16(units: &[u16]) -> Resolved {
+    if let Ok(text) = String::from_utf16(units) {
+        return from_text(&text);
+    }
+    let candidates = vec![
+        #[cfg(unix)]
+        (wtf8(units), Strategy::Wtf8),
+        (
+            PathBuf::from(String::from_utf16_lossy(units)),
+            Strategy::Lossy,
+        ),
+    ];
+    resolve(candidates, None)
+}
+
+/// Resolves a path given as bytes: a file system representation, or the
+/// portable ABI's path string. Bytes that aren't UTF-8 are taken as they
+/// are on Unix, and lossily elsewhere.
+pub fn from_bytes(bytes: &[u8]) -> Resolved {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => from_text(text),
+        #[cfg(unix)]
+        Err(_) => {
+            use std::os::unix::ffi::OsStrExt;
+            Resolved {
+                path: std::ffi::OsStr::from_bytes(bytes).into(),
+                strategy: Strategy::Exact,
+            }
+        }
+        #[cfg(not(unix))]
+        Err(_) => resolve(
+            vec![(
+                PathBuf::from(String::from_utf8_lossy(bytes).into_owned()),
+                Strategy::Lossy,
+            )],
+            None,
+        ),
+    }
+}
+
+/// Resolves a path given as text.
+pub fn from_text(text: &str) -> Resolved {
+    let mut candidates = vec![(PathBuf::from(text), Strategy::Exact)];
+    for (form, strategy) in [
+        (text.nfd().collect::<String>(), Strategy::Decomposed),
+        (text.nfc().collect::<String>(), Strategy::Composed),
+    ] {
+        if form != text {
+            candidates.push((PathBuf::from(form), strategy));
+        }
+    }
+    resolve(candidates, Some(Path::new(text)))
+}
+
+/// Resolves a local `file:` URL, or returns `None` for anything else.
+///
+/// Percent escapes are decoded to bytes, so they needn't spell UTF-8.
+pub fn from_file_url(url: &str) -> Option<Resolved> {
+    let rest = url
+        .get(..7)
+        .filter(|scheme| scheme.eq_ignore_ascii_case("file://"))
+        .map(|_| &url[7..])?;
+    let path = rest.strip_prefix("localhost").unwrap_or(rest);
+    if !path.starts_with('/') {
+        // Another host's file.
+        return None;
+    }
+    let path = &path[..path.find(['?', '#']).unwrap_or(path.len())];
+
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    Some(from_bytes(&bytes))
+}
+
+/// The first candidate that exists, else a directory scan for `scan`, else
+/// the first candidate.
+fn resolve(candidates: Vec<(PathBuf, Strategy)>, scan: Option<&Path>) -> Resolved {
+    let found = candidates
+        .iter()
+        .find(|(path, _)| exists(path))
+        .map(|(path, strategy)| (path.clone(), *strategy))
+        .or_else(|| Some((directory_scan(scan?)?, Strategy::DirectoryScan)));
+    let (path, strategy) = found.unwrap_or_else(|| candidates.into_iter().next().unwrap());
+    Resolved { path, strategy }
+}
+
+/// Whether there's anything at `path`, a dangling symlink included.
+fn exists(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok()
+}
+
+/// Walks `path` a component at a time, taking the directory entry whose
+/// name is canonically equivalent when the component itself isn't there.
+fn directory_scan(path: &Path) -> Option<PathBuf> {
+    let mut found = PathBuf::new();
+    for component in path.components() {
+        let Component::Normal(name) = component else {
+            found.push(component);
+            continue;
+        };
+        if exists(&found.join(name)) {
+            found.push(name);
+            continue;
+        }
+        let wanted: String = name.to_str()?.nfd().collect();
+        let dir = if found.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            &found
+        };
+        let entry = fs::read_dir(dir).ok()?.flatten().find(|entry| {
+            let name = entry.file_name();
+            name.to_str()
+                .is_some_and(|name| name.nfd().eq(wanted.chars()))
+        })?;
+        found.push(entry.file_name());
+    }
+    Some(found)
+}
+
+/// UTF-8, except that unpaired surrogates are encoded like any other code
+/// point.
+#[cfg(unix)]
+fn wtf8(units: &[u16]) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    let mut bytes = Vec::with_capacity(units.len() * 3);
+    for decoded in char::decode_utf16(units.iter().copied()) {
+        match decoded {
+            Ok(c) => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            Err(err) => {
+                let unit = err.unpaired_surrogate();
+                bytes.extend_from_slice(&[
+                    0xE0 | (unit >> 12) as u8,
+                    0x80 | ((unit >> 6) & 0x3F) as u8,
+                    0x80 | (unit & 0x3F) as u8,
+                ]);
+            }
+        }
+    }
+    std::ffi::OsString::from_vec(bytes).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NFC: &str = "Caf\u{e9} No\u{eb}l.great";
+    const NFD: &str = "Cafe\u{301} Noe\u{308}l.great";
+
+    fn utf16(s: &str) -> Vec<u16> {
+        s.encode_utf16().collect()
+    }
+
+    #[test]
+    fn finds_either_normalization_form() {
+        let tmp = tempfile::tempdir().unwrap();
+        for (on_disk, asked, strategy) in [
+            (NFC, NFD, Strategy::Composed),
+            (NFD, NFC, Strategy::Decomposed),
+        ] {
+            let file = tmp.path().join(on_disk);
+            fs::write(&file, "").unwrap();
+            let asked = tmp.path().join(asked);
+            let asked = asked.to_str().unwrap();
+            let resolved = from_utf16(&utf16(asked));
+            assert_eq!(
+                resolved,
+                Resolved {
+                    path: file.clone(),
+                    strategy
+                }
+            );
+            assert_eq!(from_bytes(asked.as_bytes()), resolved);
+            fs::remove_file(file).unwrap();
+        }
+    }
+
+    #[test]
+    fn scans_paths_that_mix_forms() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("r\u{e9}sum\u{e9}s");
+        fs::create_dir(&dir).unwrap();
+        let file = dir.join(NFD);
+        fs::write(&file, "").unwrap();
+
+        let asked = tmp.path().join("re\u{301}sume\u{301}s").join(NFC);
+        let resolved = from_text(asked.to_str().unwrap());
+        assert_eq!(resolved.strategy, Strategy::DirectoryScan);
+        assert_eq!(resolved.path, file);
+    }
+
+    #[test]
+    fn keeps_what_cannot_be_found() {
+        let resolved = from_text("/nonexistent/Caf\u{e9}");
+        assert_eq!(resolved.strategy, Strategy::Exact);
+        assert_eq!(resolved.path, Path::new("/nonexistent/Caf\u{e9}"));
+
+        let resolved = from_utf16(&[u16::from(b'/'), 0xD800]);
+        assert_ne!(resolved.strategy, Strategy::Exact);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn keeps_unpaired_surrogates_and_raw_bytes() {
+        use std::os::unix::ffi::OsStrExt;
+        let tmp = tempfile::tempdir().unwrap();
+
+        let name = std::ffi::OsStr::from_bytes(b"lone \xED\xA0\x80.great");
+        let file = tmp.path().join(name);
+        fs::write(&file, "").unwrap();
+        let mut asked = utf16(&format!("{}/lone ", tmp.path().display()));
+        asked.push(0xD800);
+        asked.extend(utf16(".great"));
+        assert_eq!(
+            from_utf16(&asked),
+            Resolved {
+                path: file,
+                strategy: Strategy::Wtf8
+            }
+        );
+
+        let latin1 = tmp
+            .path()
+            .join(std::ffi::OsStr::from_bytes(b"caf\xE9.great"));
+        fs::write(&latin1, "").unwrap();
+        let resolved = from_bytes(latin1.as_os_str().as_bytes());
+        assert_eq!(resolved.path, latin1);
+        assert_eq!(resolved.strategy, Strategy::Exact);
+    }
+
+    #[test]
+    fn decodes_file_urls() {
+        let tmp = tempfile::tempdir().unwrap();
+        let file = tmp.path().join(NFD);
+        fs::write(&file, "").unwrap();
+        let dir = tmp.path().to_str().unwrap();
+
+        let url = format!("file://{dir}/Caf%C3%A9%20No%C3%ABl.great");
+        assert_eq!(
+            from_file_url(&url),
+            Some(Resolved {
+                path: file.clone(),
+                strategy: Strategy::Decomposed
+            })
+        );
+        let url = format!("FILE://localhost{dir}/Cafe%CC%81%20Noe%CC%88l.great?x#y");
+        assert_eq!(from_file_url(&url).unwrap().path, file);
+
+        assert_eq!(from_file_url("file://server/share/a"), None);
+        assert_eq!(from_file_url("http://localhost/a"), None);
+        assert_eq!(from_file_url("file:///a%2"), None);
+        assert_eq!(from_file_url("file:///a%zz"), None);
+        assert_eq!(from_file_url("fil"), None);
+    }
+}
@@ -0,0 +1,125 @@
+//! CFPlugIn identifiers shared by the bundle and the tools that describe it.
+#![allow(non_upper_case_globals)]
+
+/// The plug-in type Spotlight loads importers as.
+#[rustfmt::skip]
+pub const kMDImporterTypeID: [u8; 16] = [
+    0x8B, 0x08, 0xC4, 0xBF, 0x41, 0x5B, 0x11, 0xD8,
+    0xB3, 0xF9, 0x00, 0x03, 0x93, 0x67, 0x26, 0xFC,
+];
+
+/// The interface every importer instance has to answer `QueryInterface` for.
+#[rustfmt::skip]
+pub const kMDImporterInterfaceID: [u8; 16] = [
+    0x6E, 0xBC, 0x27, 0xC4, 0x89, 0x9C, 0x11, 0xD8,
+    0x84, 0xAE, 0x00, 0x03, 0x93, 0x67, 0x26, 0xFC,
+];
+
+#[rustfmt::skip]
+pub const IUnknownUUID: [u8; 16] = [
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46,
+];
+
+/// This importer's own diagnostic interface, which reports what build it is.
+/// Nothing outside this repository asks for it.
+#[rustfmt::skip]
+pub const kMinimalImporterBuildInfoInterfaceID: [u8; 16] = [
+    0x1A, 0x45, 0xBE, 0x1D, 0x99, 0x8E, 0x49, 0x24,
+    0xA8, 0xF7, 0xD3, 0x37, 0x50, 0xCA, 0xA9, 0x10,
+];
+
+/// Keys of the dictionary the build-info interface fills in.
+pub mod build_info {
+    /// The crate version, a string.
+    pub const Version: &str = "Version";
+    /// `git describe --always --dirty` when the importer was built, or
+    /// `unknown`.
+    pub const GitHash: &str = "GitHash";
+    /// Cargo's profile: `debug` or `release`.
+    pub const Profile: &str = "Profile";
+    /// The cargo features the importer was built with, an array.
+    pub const Features: &str = "Features";
+    /// The content types it imports, an array.
+    pub const ContentTypes: &str = "ContentTypes";
+}
+
+/// The factory this importer registers in `CFPlugInFactories`.
+#[rustfmt::skip]
+pub const MetadataImporterPluginFactoryUUID: [u8; 16] = [
+    0xd8, 0x78, 0x57, 0xf7, 0xb0, 0xc0, 0x4c, 0x70,
+    0x9b, 0x8f, 0x2e, 0x3d, 0x8e, 0x55, 0x19, 0x8c,
+];
+
+/// The exported function behind `MetadataImporterPluginFactoryUUID`.
+pub const MetadataImporterPluginFactoryName: &str = "MetadataImporterPluginFactory";
+
+/// Formats `uuid` the way `CFUUIDCreateString` and plists spell it.
+pub fn uuid_string(uuid: &[u8; 16]) -> String {
+    let hex: String = uuid.iter().map(|b| format!("{b:02X}")).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// Parses the `8-4-4-4-12` hex spelling [`uuid_string`] produces, in either
+/// case.
+pub fn uuid_from_string(s: &str) -> Option<[u8; 16]> {
+    let groups: Vec<&str> = s.split('-').collect();
+    let lengths: Vec<usize> = groups.iter().map(|g| g.len()).collect();
+    if lengths != [8, 4, 4, 4, 12] {
+        return None;
+    }
+    let hex = groups.concat();
+    let mut uuid = [0; 16];
+    for (i, byte) in uuid.iter_mut().enumerate() {
+        let pair = hex.get(2 * i..2 * i + 2)?;
+        if !pair.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        *byte = u8::from_str_radix(pair, 16).ok()?;
+    }
+    Some(uuid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_like_cf() {
+        assert_eq!(
+            uuid_string(&MetadataImporterPluginFactoryUUID),
+            "D87857F7-B0C0-4C70-9B8F-2E3D8E55198C"
+        );
+        assert_eq!(
+            uuid_string(&kMDImporterTypeID),
+            "8B08C4BF-415B-11D8-B3F9-0003936726FC"
+        );
+    }
+
+    #[test]
+    fn parses_what_it_formats() {
+        for uuid in [kMDImporterTypeID, kMDImporterInterfaceID, IUnknownUUID] {
+            assert_eq!(uuid_from_string(&uuid_string(&uuid)), Some(uuid));
+        }
+        assert_eq!(
+            uuid_from_string("d87857f7-b0c0-4c70-9b8f-2e3d8e55198c"),
+            Some(MetadataImporterPluginFactoryUUID)
+        );
+        assert_eq!(uuid_from_string("D87857F7B0C04C709B8F2E3D8E55198C"), None);
+        assert_eq!(
+            uuid_from_string("D87857F7-B0C0-4C70-9B8F-2E3D8E55198G"),
+            None
+        );
+        assert_eq!(
+            uuid_from_string("+87857F7-B0C0-4C70-9B8F-2E3D8E55198C"),
+            None
+        );
+    }
+}
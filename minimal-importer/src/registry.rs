@@ -0,0 +1,87 @@
+//! The content types the importer handles and how each one is imported.
+//!
+//! Handlers are picked the way Launch Services picks an importer: the file's
+//! UTI comes from a [`uti::Database`], and a handler registered for that UTI
+//! or, failing that, for the nearest type it conforms to, wins.
+
+use std::io;
+use std::path::Path;
+
+use crate::{Attributes, uti};
+
+/// A content type the importer is registered for.
+#[derive(Debug)]
+pub struct Handler {
+    /// The UTI Spotlight passes for files of this type.
+    pub uti: &'static str,
+    /// Imports a file's contents; [`crate::import_file`] does the reading.
+    pub import: fn(&mut Attributes, &[u8]) -> io::Result<()>,
+}
+
+/// Every handler, in the order they are matched.
+pub static HANDLERS: &[Handler] = &[
+    Handler {
+        uti: "vin.je.great",
+        import: crate::import_text,
+    },
+    Handler {
+        uti: "public.plain-text",
+        import: crate::import_text,
+    },
+];
+
+/// The UTIs the importer is registered for.
+pub fn utis() -> impl Iterator<Item = &'static str> {
+    HANDLERS.iter().map(|h| h.uti)
+}
+
+/// The handler registered for `uti`. UTIs are case-insensitive.
+pub fn handler_for_uti(uti: &str) -> Option<&'static Handler> {
+    HANDLERS.iter().find(|h| h.uti.eq_ignore_ascii_case(uti))
+}
+
+/// The handler for files of type `uti`: the one registered for `uti`
+/// itself, else the one for the nearest type `uti` conforms to in `db`.
+pub fn handler_for_type(db: &uti::Database, uti: &str) -> Option<&'static Handler> {
+    HANDLERS
+        .iter()
+        .filter_map(|h| Some((db.conformance_distance(uti, h.uti)?, h)))
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, h)| h)
+}
+
+/// Picks a handler for `path` from the types `db` gives its extension,
+/// trying the preferred type first.
+pub fn handler_for_path(db: &uti::Database, path: &Path) -> Option<&'static Handler> {
+    let ext = path.extension()?.to_str()?;
+    db.types_for_extension(ext)
+        .into_iter()
+        .find_map(|uti| handler_for_type(db, uti))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup() {
+        let mut db = uti::Database::with_system_types();
+        db.declare(uti::TypeDeclaration {
+            identifier: "vin.je.great".to_owned(),
+            conforms_to: vec!["public.text".to_owned()],
+            extensions: vec!["great".to_owned()],
+            ..uti::TypeDeclaration::default()
+        });
+        let uti_for = |path: &str| handler_for_path(&db, Path::new(path)).map(|h| h.uti);
+        assert_eq!(uti_for("a/test.GREAT"), Some("vin.je.great"));
+        assert_eq!(uti_for("notes.md"), Some("public.plain-text"));
+        assert_eq!(uti_for("page.html"), None);
+        assert_eq!(uti_for("noext"), None);
+        assert!(handler_for_uti("public.plain-text").is_some());
+        assert_eq!(
+            handler_for_uti("VIN.JE.Great").map(|h| h.uti),
+            Some("vin.je.great")
+        );
+        assert!(handler_for_uti("vin.je.rich").is_none());
+    }
+}
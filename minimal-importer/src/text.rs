@@ -0,0 +1,58 @@
+//! Turning raw file bytes into text.
+
+/// Decodes `bytes` as text, honouring a UTF-8 or UTF-16 byte order mark.
+///
+/// Without a BOM the bytes are taken as UTF-8 if they validate, and as
+/// Latin-1 otherwise, so decoding never fails.
+pub fn decode(bytes: &[u8]) -> String {
+    if let Some(rest) = bytes.strip_prefix(b"\xEF\xBB\xBF") {
+        return String::from_utf8_lossy(rest).into_owned();
+    }
+    if let Some(rest) = bytes.strip_prefix(b"\xFF\xFE") {
+        return decode_utf16(rest, u16::from_le_bytes);
+    }
+    if let Some(rest) = bytes.strip_prefix(b"\xFE\xFF") {
+        return decode_utf16(rest, u16::from_be_bytes);
+    }
+    match std::str::from_utf8(bytes) {
+        Ok(s) => s.to_owned(),
+        Err(_) => bytes.iter().map(|&b| char::from(b)).collect(),
+    }
+}
+
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> String {
+    let units = bytes.chunks_exact(2).map(|c| unit([c[0], c[1]]));
+    char::decode_utf16(units)
+        .map(|r| r.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// Splits `text` into lowercased word tokens.
+///
+/// Keyword extraction and search both go through this so their notion of a
+/// word stays the same.
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_boms_and_fallback() {
+        assert_eq!(decode(b"\xEF\xBB\xBFhello"), "hello");
+        assert_eq!(decode(b"\xFF\xFEh\0i\0"), "hi");
+        assert_eq!(decode(b"\xFE\xFF\0h\0i"), "hi");
+        assert_eq!(decode("grüße".as_bytes()), "grüße");
+        assert_eq!(decode(b"gr\xFC\xDFe"), "grüße");
+    }
+
+    #[test]
+    fn tokenizes_words() {
+        let tokens: Vec<String> = tokenize("Hello, World! l'été 2024-01").collect();
+        assert_eq!(tokens, ["hello", "world", "l", "été", "2024", "01"]);
+    }
+}
@@ -0,0 +1,280 @@
+//! A small stand-in for Launch Services' Uniform Type Identifier database.
+//!
+//! On macOS Spotlight tells the importer a file's UTI. Everywhere else,
+//! [`Database`] maps filename extensions and MIME types to UTIs and answers
+//! conformance questions, starting from the core system types in
+//! [`Database::with_system_types`] plus whatever declarations the caller
+//! adds, normally the ones in our bundles' `Info.plist`s.
+
+use std::collections::{HashSet, VecDeque};
+use std::path::Path;
+
+/// One `UTExportedTypeDeclarations`/`UTImportedTypeDeclarations` entry.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TypeDeclaration {
+    pub identifier: String,
+    pub conforms_to: Vec<String>,
+    /// Filename extensions without the dot.
+    pub extensions: Vec<String>,
+    pub mime_types: Vec<String>,
+}
+
+/// `(identifier, conforms to, extensions, MIME types)`.
+type SystemType = (
+    &'static str,
+    &'static [&'static str],
+    &'static [&'static str],
+    &'static [&'static str],
+);
+
+/// The system types importers are likely to meet.
+#[rustfmt::skip]
+static SYSTEM_TYPES: &[SystemType] = &[
+    ("public.item", &[], &[], &[]),
+    ("public.content", &[], &[], &[]),
+    ("public.composite-content", &["public.content"], &[], &[]),
+    ("public.data", &["public.item"], &[], &["application/octet-stream"]),
+    ("public.directory", &["public.item"], &[], &[]),
+    ("public.folder", &["public.directory"], &[], &[]),
+    ("public.text", &["public.data", "public.content"], &[], &["text/*"]),
+    ("public.plain-text", &["public.text"], &["txt", "text"], &["text/plain"]),
+    ("public.utf8-plain-text", &["public.plain-text"], &[], &[]),
+    ("public.utf16-plain-text", &["public.plain-text"], &[], &[]),
+    ("net.daringfireball.markdown", &["public.plain-text"], &["md", "markdown"], &["text/markdown"]),
+    ("public.source-code", &["public.plain-text"], &[], &[]),
+    ("public.c-source", &["public.source-code"], &["c"], &["text/x-c"]),
+    ("public.script", &["public.source-code"], &[], &[]),
+    ("public.shell-script", &["public.script"], &["sh", "command"], &["application/x-sh"]),
+    ("public.python-script", &["public.script"], &["py"], &["text/x-python-script"]),
+    ("public.html", &["public.text"], &["html", "htm"], &["text/html"]),
+    ("public.xml", &["public.text"], &["xml"], &["application/xml", "text/xml"]),
+    ("public.json", &["public.text"], &["json"], &["application/json"]),
+    ("public.comma-separated-values-text", &["public.text"], &["csv"], &["text/csv"]),
+    ("public.rtf", &["public.text"], &["rtf"], &["text/rtf", "application/rtf"]),
+    ("com.apple.property-list", &["public.data"], &["plist"], &[]),
+    ("public.image", &["public.data", "public.content"], &[], &["image/*"]),
+    ("public.png", &["public.image"], &["png"], &["image/png"]),
+    ("public.jpeg", &["public.image"], &["jpg", "jpeg"], &["image/jpeg"]),
+    ("com.adobe.pdf", &["public.data", "public.composite-content"], &["pdf"], &["application/pdf"]),
+    ("public.archive", &["public.data"], &[], &[]),
+    ("public.zip-archive", &["public.archive"], &["zip"], &["application/zip"]),
+];
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|&s| s.to_owned()).collect()
+}
+
+/// Declared types, searchable by tag and by conformance.
+#[derive(Clone, Debug, Default)]
+pub struct Database {
+    /// In declaration order; later declarations win tag lookups.
+    types: Vec<TypeDeclaration>,
+}
+
+impl Database {
+    /// An empty database.
+    pub fn new() -> Database {
+        Database::default()
+    }
+
+    /// A database holding the core `public.*` types and a few common others.
+    pub fn with_system_types() -> Database {
+        let mut db = Database::new();
+        for &(identifier, conforms_to, extensions, mime_types) in SYSTEM_TYPES {
+            db.declare(TypeDeclaration {
+                identifier: identifier.to_owned(),
+                conforms_to: strings(conforms_to),
+                extensions: strings(extensions),
+                mime_types: strings(mime_types),
+            });
+        }
+        db
+    }
+
+    /// Adds `decl`, replacing any earlier declaration of the same identifier.
+    pub fn declare(&mut self, decl: TypeDeclaration) {
+        self.types
+            .retain(|t| !t.identifier.eq_ignore_ascii_case(&decl.identifier));
+        self.types.push(decl);
+    }
+
+    /// Looks up a type; UTIs compare case-insensitively.
+    pub fn get(&self, uti: &str) -> Option<&TypeDeclaration> {
+        self.types
+            .iter()
+            .find(|t| t.identifier.eq_ignore_ascii_case(uti))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TypeDeclaration> {
+        self.types.iter()
+    }
+
+    /// Types claiming filename extension `ext`, preferred first.
+    pub fn types_for_extension(&self, ext: &str) -> Vec<&str> {
+        self.types
+            .iter()
+            .rev()
+            .filter(|t| t.extensions.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+            .map(|t| t.identifier.as_str())
+            .collect()
+    }
+
+    /// Types claiming MIME type `mime`, preferred first. Parameters such as
+    /// `; charset=utf-8` are ignored, and `text/*` style wildcards match.
+    pub fn types_for_mime_type(&self, mime: &str) -> Vec<&str> {
+        let mime = mime.split(';').next().unwrap_or_default().trim();
+        let matches = |pattern: &str| match pattern.strip_suffix("/*") {
+            Some(major) => mime
+                .split_once('/')
+                .is_some_and(|(m, _)| m.eq_ignore_ascii_case(major)),
+            None => pattern.eq_ignore_ascii_case(mime),
+        };
+        let mut exact = Vec::new();
+        let mut wildcard = Vec::new();
+        for t in self.types.iter().rev() {
+            for pattern in t.mime_types.iter().filter(|p| matches(p)) {
+                let list = if pattern.ends_with("/*") {
+                    &mut wildcard
+                } else {
+                    &mut exact
+                };
+                list.push(t.identifier.as_str());
+            }
+        }
+        exact.extend(wildcard);
+        exact.dedup();
+        exact
+    }
+
+    /// The preferred type for a file, from its extension.
+    pub fn type_for_path(&self, path: &Path) -> Option<&str> {
+        let ext = path.extension()?.to_str()?;
+        self.types_for_extension(ext).into_iter().next()
+    }
+
+    /// How many conformance steps lead from `uti` to `parent`: 0 if they
+    /// are the same type, `None` if `uti` doesn't conform to `parent`.
+    pub fn conformance_distance(&self, uti: &str, parent: &str) -> Option<usize> {
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::from([(uti.to_ascii_lowercase(), 0)]);
+        while let Some((current, distance)) = queue.pop_front() {
+            if current.eq_ignore_ascii_case(parent) {
+                return Some(distance);
+            }
+            if !seen.insert(current.clone()) {
+                continue;
+            }
+            for next in self.get(&current).into_iter().flat_map(|t| &t.conforms_to) {
+                queue.push_back((next.to_ascii_lowercase(), distance + 1));
+            }
+        }
+        None
+    }
+
+    /// True if `uti` is `parent` or conforms to it, directly or not.
+    pub fn conforms_to(&self, uti: &str, parent: &str) -> bool {
+        self.conformance_distance(uti, parent).is_some()
+    }
+
+    /// Every type `uti` conforms to, nearest first, not including itself.
+    pub fn supertypes(&self, uti: &str) -> Vec<&str> {
+        let mut found: Vec<&str> = Vec::new();
+        let mut queue = VecDeque::from([uti]);
+        while let Some(current) = queue.pop_front() {
+            for next in self.get(current).into_iter().flat_map(|t| &t.conforms_to) {
+                let next = self
+                    .get(next)
+                    .map_or(next.as_str(), |t| t.identifier.as_str());
+                if !next.eq_ignore_ascii_case(uti)
+                    && !found.iter().any(|f| f.eq_ignore_ascii_case(next))
+                {
+                    found.push(next);
+                    queue.push_back(next);
+                }
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn db() -> Database {
+        let mut db = Database::with_system_types();
+        db.declare(TypeDeclaration {
+            identifier: "vin.je.great".to_owned(),
+            conforms_to: vec!["public.text".to_owned()],
+            extensions: vec!["great".to_owned()],
+            ..TypeDeclaration::default()
+        });
+        db
+    }
+
+    #[test]
+    fn tag_lookup() {
+        let db = db();
+        assert_eq!(
+            db.type_for_path(Path::new("a/test.GREAT")),
+            Some("vin.je.great")
+        );
+        assert_eq!(
+            db.type_for_path(Path::new("README.md")),
+            Some("net.daringfireball.markdown")
+        );
+        assert_eq!(db.type_for_path(Path::new("noext")), None);
+        assert_eq!(
+            db.types_for_mime_type("text/plain; charset=utf-8"),
+            ["public.plain-text", "public.text"]
+        );
+        assert_eq!(
+            db.types_for_mime_type("image/png"),
+            ["public.png", "public.image"]
+        );
+        assert!(db.types_for_mime_type("video/mp4").is_empty());
+    }
+
+    #[test]
+    fn later_declarations_win() {
+        let mut db = db();
+        db.declare(TypeDeclaration {
+            identifier: "vin.je.rich".to_owned(),
+            conforms_to: vec!["vin.je.great".to_owned()],
+            extensions: vec!["great".to_owned()],
+            ..TypeDeclaration::default()
+        });
+        assert_eq!(
+            db.types_for_extension("great"),
+            ["vin.je.rich", "vin.je.great"]
+        );
+        db.declare(TypeDeclaration {
+            identifier: "VIN.JE.GREAT".to_owned(),
+            ..TypeDeclaration::default()
+        });
+        assert_eq!(db.types_for_extension("great"), ["vin.je.rich"]);
+    }
+
+    #[test]
+    fn conformance() {
+        let db = db();
+        assert!(db.conforms_to("vin.je.great", "public.text"));
+        assert!(db.conforms_to("vin.je.great", "PUBLIC.DATA"));
+        assert!(db.conforms_to("public.plain-text", "public.plain-text"));
+        assert!(!db.conforms_to("vin.je.great", "public.plain-text"));
+        assert!(!db.conforms_to("vin.je.unknown", "public.data"));
+        assert_eq!(
+            db.conformance_distance("public.python-script", "public.plain-text"),
+            Some(3)
+        );
+        assert_eq!(
+            db.supertypes("vin.je.great"),
+            [
+                "public.text",
+                "public.data",
+                "public.content",
+                "public.item"
+            ]
+        );
+    }
+}
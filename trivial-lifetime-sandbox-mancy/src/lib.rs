@@ -60,6 +60,8 @@ impl<T: ComInterface> ComPtr<T> {
 
 impl<T: ComInterface> Clone for ComPtr<T> {
     fn clone(&self) -> Self {
+        #[cfg(feature = "track-instances")]
+        minimal_importer::instances::check_live(self.ptr.cast());
         unsafe {
             let base = *(self.ptr as *mut *const VTableBase);
             if let Some(add_ref) = (&*base).add_ref {
@@ -75,6 +77,8 @@ impl<T: ComInterface> Clone for ComPtr<T> {
 
 impl<T: ComInterface> Drop for ComPtr<T> {
     fn drop(&mut self) {
+        #[cfg(feature = "track-instances")]
+        minimal_importer::instances::check_live(self.ptr.cast());
         unsafe {
             let base = *(self.ptr as *mut *const VTableBase);
             println!("base: {base:#?}");
@@ -149,6 +153,8 @@ unsafe extern "C" fn example_release(this: *mut c_void) -> u32 {
     let rc = (unsafe { &*this }).ref_cnt.fetch_sub(1, Ordering::Release) - 1;
     if rc == 0 {
         std::sync::atomic::fence(Ordering::Acquire);
+        #[cfg(feature = "track-instances")]
+        minimal_importer::instances::freed(this.cast());
         drop(unsafe { Box::from_raw(this) });
     }
     rc
@@ -167,6 +173,19 @@ static IEXAMPLE_VTBL: IExampleVTable = IExampleVTable {
     get_value: Some(example_get_value),
 };
 
+impl ExampleImpl {
+    pub fn create(value: i32) -> ComPtr<ExampleImpl> {
+        let this = Box::into_raw(Box::new(ExampleImpl {
+            vtbl: &IEXAMPLE_VTBL,
+            ref_cnt: AtomicU32::new(1),
+            value,
+        }));
+        #[cfg(feature = "track-instances")]
+        minimal_importer::instances::created(this.cast(), "ExampleImpl");
+        unsafe { ComPtr::from_raw(this) }
+    }
+}
+
 unsafe impl ComVtbl for ExampleImpl {
     type VTable = IExampleVTable;
     fn vtbl(&self) -> &Self::VTable {
@@ -177,6 +196,8 @@ unsafe impl ComVtbl for ExampleImpl {
     }
     fn query_interface<U: ComInterface>(&self) -> Option<ComPtr<U>> {
         if U::IID == IID_IEXAMPLE {
+            // The new pointer releases when dropped, so it needs a reference.
+            unsafe { example_add_ref(self.as_raw()) };
             Some(unsafe { ComPtr::from_raw(self as *const _ as *mut U) })
         } else {
             None
@@ -191,18 +212,38 @@ unsafe impl ComInterface for ExampleImpl {
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "track-instances")]
+    use minimal_importer::instances::Scope;
 
     #[test]
     fn use_com() {
-        let boxed = Box::new(ExampleImpl {
-            vtbl: &IEXAMPLE_VTBL,
-            ref_cnt: AtomicU32::new(1),
-            value: 1337,
-        });
-        let ptr = unsafe { ComPtr::from_raw(Box::into_raw(boxed)) };
+        let ptr = ExampleImpl::create(1337);
         let val = unsafe { ((&*ptr).vtbl().get_value.unwrap())(ptr.as_raw()) };
         println!("Value: {val}");
         let q: Option<ComPtr<ExampleImpl>> = ptr.query_interface();
         assert!(q.is_some());
+        assert_eq!(ptr.ref_cnt.load(Ordering::Relaxed), 2);
+        drop(q);
+        drop(ptr);
+        #[cfg(feature = "track-instances")]
+        minimal_importer::instances::assert_none_live(Scope::Thread);
+    }
+
+    #[test]
+    #[cfg(feature = "track-instances")]
+    #[should_panic(expected = "use after release of ExampleImpl")]
+    fn double_release_is_caught() {
+        let ptr = ExampleImpl::create(1);
+        let alias = unsafe { ComPtr::from_raw(ptr.as_ptr()) };
+        drop(ptr);
+        drop(alias);
+    }
+
+    #[test]
+    #[cfg(feature = "track-instances")]
+    #[should_panic(expected = "leaked instances:\nExampleImpl at")]
+    fn leak_is_caught() {
+        std::mem::forget(ExampleImpl::create(1));
+        minimal_importer::instances::assert_none_live(Scope::Thread);
     }
 }